@@ -5,14 +5,15 @@ _lal()
     local cur prev words cword
     _init_completion || return
 
-    local -r subcommands="build clean configure export fetch help init script run ls
+    local -r subcommands="build cache clean configure export fetch help init script run ls
                           query remove rm shell stash save status update upgrade verify
                           publish env list-components list-supported-environments list-dependencies
-                          list-environments list-configurations propagate"
+                          list-environments list-configurations propagate yank rdeps index outdated
+                          log history ps kill"
 
     local has_sub
     for (( i=0; i < ${#words[@]}-1; i++ )); do
-        if [[ ${words[i]} == @(build|clean|configure|export|script|propagate|fetch|help|init|remove|rm|script|run|query|shell|stash|save|status|ls|update|upgrade|verify|publish|env) ]]; then
+        if [[ ${words[i]} == @(build|cache|clean|configure|export|script|propagate|fetch|help|init|remove|rm|script|run|query|shell|stash|save|status|ls|update|upgrade|verify|publish|env|yank|rdeps|index|outdated|log|history|ps|kill) ]]; then
             has_sub=1
         fi
     done
@@ -36,7 +37,7 @@ _lal()
     # special subcommand completions
     local special i
     for (( i=0; i < ${#words[@]}-1; i++ )); do
-        if [[ ${words[i]} == @(build|remove|rm|propagate|export|init|update|script|run|status|ls|query|shell|publish|env|configure|help) ]]; then
+        if [[ ${words[i]} == @(build|cache|remove|rm|propagate|export|init|update|script|run|status|ls|query|shell|publish|env|configure|help|yank|rdeps|index|outdated|log|history|kill) ]]; then
             special=${words[i]}
         fi
     done
@@ -83,6 +84,29 @@ _lal()
                     COMPREPLY=($(compgen -W "$env_subs" -- "$cur"))
                 fi
                 ;;
+            cache)
+                if [[ $prev = "cache" ]]; then
+                    COMPREPLY=($(compgen -W "ls du prune help -h --help" -- "$cur"))
+                elif [[ $prev = "prune" ]]; then
+                    COMPREPLY=($(compgen -W "--max-size" -- "$cur"))
+                fi
+                ;;
+            index)
+                if [[ $prev = "index" ]]; then
+                    COMPREPLY=($(compgen -W "refresh help -h --help" -- "$cur"))
+                fi
+                ;;
+            log)
+                [[ $in_lal_repo ]] || return 0
+                COMPREPLY=($(compgen -W "-f --failed -h --help" -- "$cur"))
+                ;;
+            history)
+                [[ $in_lal_repo ]] || return 0
+                COMPREPLY=($(compgen -W "--limit -j --json -h --help" -- "$cur"))
+                ;;
+            kill)
+                COMPREPLY=($(compgen -W "-a --all -h --help" -- "$cur"))
+                ;;
             init)
                 if [[ $prev = "init" ]]; then
                     local -r envs="$(lal list-environments)"
@@ -94,10 +118,15 @@ _lal()
                 local -r ls_flags="-f --full -o --origin -t --time -h --help"
                 COMPREPLY=($(compgen -W "$ls_flags" -- "$cur"))
                 ;;
-            export|query)
+            export|query|yank|rdeps)
                 components=$(find "$HOME/.lal/cache/environments" -maxdepth 2 -mindepth 2 -type d -printf "%f " 2> /dev/null)
                 COMPREPLY=($(compgen -W "$components" -- "$cur"))
                 ;;
+            outdated)
+                [[ $in_lal_repo ]] || return 0
+                # compared against the manifest, so there are no component names to give
+                COMPREPLY=($(compgen -W "-j --json -h --help" -- "$cur"))
+                ;;
             update)
                 [[ $in_lal_repo ]] || return 0
                 # Looking in local cache for allowed component names
@@ -7,6 +7,10 @@ fn is_integer(v: String) -> Result<(), String> {
     Err(format!("{} is not an integer", v))
 }
 
+fn is_size(v: String) -> Result<(), String> {
+    crate::parse_size(&v).map(|_| ()).map_err(|e| e.to_string())
+}
+
 /// lal clap app
 pub fn new<'a>() -> App<'a, 'a> {
     #[rustfmt::skip]
@@ -36,7 +40,16 @@ pub fn new<'a>() -> App<'a, 'a> {
             .arg(Arg::with_name("core")
                 .long("core")
                 .short("c")
-                .help("Only fetch core dependencies")))
+                .help("Only fetch core dependencies"))
+            .arg(Arg::with_name("dry-run")
+                .long("dry-run")
+                .short("n")
+                .help("Print the planned changes without making them"))
+            .arg(Arg::with_name("json")
+                .long("json")
+                .short("j")
+                .requires("dry-run")
+                .help("Print the planned changes as json")))
         .subcommand(SubCommand::with_name("build")
             .about("Runs BUILD script in current directory in the configured container")
             .arg(Arg::with_name("component")
@@ -63,6 +76,11 @@ pub fn new<'a>() -> App<'a, 'a> {
                 .takes_value(true)
                 .requires("release")
                 .help("Configure lockfiles with an explicit version number"))
+            .arg(Arg::with_name("auto-version")
+                .long("auto-version")
+                .requires("release")
+                .conflicts_with("with-version")
+                .help("Configure lockfiles with the next free version across supported environments"))
             .arg(Arg::with_name("with-sha")
                 .long("with-sha")
                 .takes_value(true)
@@ -85,7 +103,26 @@ pub fn new<'a>() -> App<'a, 'a> {
             .arg(Arg::with_name("print")
                 .long("print-only")
                 .conflicts_with("release")
-                .help("Only print the docker run command and exit")))
+                .help("Only print the docker run command and exit"))
+            .arg(Arg::with_name("matrix")
+                .long("matrix")
+                .short("m")
+                .conflicts_with_all(&["configuration", "print"])
+                .help("Build in every supported environment and configuration"))
+            .arg(Arg::with_name("matrix-env")
+                .long("matrix-env")
+                .requires("matrix")
+                .multiple(true)
+                .takes_value(true)
+                .number_of_values(1)
+                .help("Only build the matrix in these environments"))
+            .arg(Arg::with_name("matrix-config")
+                .long("matrix-config")
+                .requires("matrix")
+                .multiple(true)
+                .takes_value(true)
+                .number_of_values(1)
+                .help("Only build the matrix in these configurations")))
         .subcommand(SubCommand::with_name("update")
             .about("Update arbitrary dependencies into INPUT")
             .arg(Arg::with_name("components")
@@ -101,7 +138,16 @@ pub fn new<'a>() -> App<'a, 'a> {
                 .short("D")
                 .long("save-dev")
                 .conflicts_with("save")
-                .help("Save updated versions in devDependencies in the manifest")))
+                .help("Save updated versions in devDependencies in the manifest"))
+            .arg(Arg::with_name("dry-run")
+                .long("dry-run")
+                .short("n")
+                .help("Print the planned changes without making them"))
+            .arg(Arg::with_name("json")
+                .long("json")
+                .short("j")
+                .requires("dry-run")
+                .help("Print the planned changes as json")))
         .subcommand(SubCommand::with_name("verify")
             .arg(Arg::with_name("simple")
                 .short("s")
@@ -123,6 +169,23 @@ pub fn new<'a>() -> App<'a, 'a> {
                 .long("origin")
                 .help("Print version and environment origin of artifact"))
             .about("Prints current dependencies and their status"))
+        .subcommand(SubCommand::with_name("log")
+            .about("Print the output of the last build")
+            .arg(Arg::with_name("failed")
+                .short("f")
+                .long("failed")
+                .help("Print the last failed build instead")))
+        .subcommand(SubCommand::with_name("history")
+            .about("List past builds with their duration and result")
+            .arg(Arg::with_name("limit")
+                .long("limit")
+                .takes_value(true)
+                .validator(is_integer)
+                .help("Only list this many of the latest builds"))
+            .arg(Arg::with_name("json")
+                .short("j")
+                .long("json")
+                .help("Print the builds as json")))
         .subcommand(SubCommand::with_name("shell")
             .about("Enters the configured container mounting the current directory")
             .alias("sh")
@@ -234,7 +297,16 @@ pub fn new<'a>() -> App<'a, 'a> {
                 .short("D")
                 .long("save-dev")
                 .conflicts_with("save")
-                .help("Save removal of devDependencies in the manifest")))
+                .help("Save removal of devDependencies in the manifest"))
+            .arg(Arg::with_name("dry-run")
+                .long("dry-run")
+                .short("n")
+                .help("Print the planned changes without making them"))
+            .arg(Arg::with_name("json")
+                .long("json")
+                .short("j")
+                .requires("dry-run")
+                .help("Print the planned changes as json")))
         .subcommand(SubCommand::with_name("clean")
             .about("Clean old artifacts in the cache directory to save space")
             .arg(Arg::with_name("days")
@@ -243,16 +315,99 @@ pub fn new<'a>() -> App<'a, 'a> {
                 .takes_value(true)
                 .default_value("14")
                 .validator(is_integer)
-                .help("Number of days to serve as cutoff")))
+                .help("Number of days to serve as cutoff"))
+            .arg(Arg::with_name("images")
+                .long("images")
+                .help("Clean unused lal docker images instead of cached artifacts"))
+            .arg(Arg::with_name("dry-run")
+                .long("dry-run")
+                .requires("images")
+                .help("Only list the images that would be removed")))
+        .subcommand(SubCommand::with_name("ps")
+            .about("List running containers started by lal"))
+        .subcommand(SubCommand::with_name("kill")
+            .about("Stop containers started by lal (by default your orphaned ones)")
+            .arg(Arg::with_name("containers")
+                .multiple(true)
+                .help("Names of the containers to stop"))
+            .arg(Arg::with_name("all")
+                .short("a")
+                .long("all")
+                .conflicts_with("containers")
+                .help("Stop all of your containers, not just orphaned ones")))
+        .subcommand(SubCommand::with_name("cache")
+            .about("Inspect and prune the local artifact cache")
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(SubCommand::with_name("ls")
+                .about("List cached components with their size and last use"))
+            .subcommand(SubCommand::with_name("du")
+                .about("Print the size of the cache"))
+            .subcommand(SubCommand::with_name("prune")
+                .about("Evict least recently used components until the cache fits a size")
+                .arg(Arg::with_name("max-size")
+                    .long("max-size")
+                    .takes_value(true)
+                    .required(true)
+                    .validator(is_size)
+                    .help("Maximum size of published components in the cache (e.g. 20G)"))))
+        .subcommand(SubCommand::with_name("index")
+            .about("Manage the local metadata index of the artifact store")
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(SubCommand::with_name("refresh")
+                .about("Fetch new versions, lockfiles and yanks into the index")))
         .subcommand(SubCommand::with_name("query")
             .about("Query for available versions on artifactory")
             .arg(Arg::with_name("latest")
                 .long("latest")
                 .short("l")
                 .help("Return latest version only"))
+            .arg(Arg::with_name("details")
+                .long("details")
+                .short("d")
+                .help("Show build time, sha, lal version and dependencies of each version"))
+            .arg(Arg::with_name("json")
+                .long("json")
+                .short("j")
+                .help("Print the versions as json"))
+            .arg(Arg::with_name("all-environments")
+                .long("all-environments")
+                .short("a")
+                .help("List versions in every configured environment side by side"))
+            .arg(Arg::with_name("since")
+                .long("since")
+                .takes_value(true)
+                .help("Only versions built on or after a date (YYYY-MM-DD)"))
+            .arg(Arg::with_name("sha")
+                .long("sha")
+                .takes_value(true)
+                .help("Only versions built from a sha (or a prefix of it)"))
+            .arg(Arg::with_name("depends-on")
+                .long("depends-on")
+                .takes_value(true)
+                .help("Only versions built against a component=version"))
             .arg(Arg::with_name("component")
                 .required(true)
                 .help("Component name to search for")))
+        .subcommand(SubCommand::with_name("yank")
+            .about("Withdraw a published version of a component")
+            .arg(Arg::with_name("component")
+                .required(true)
+                .help("The component=version to yank"))
+            .arg(Arg::with_name("reason")
+                .long("reason")
+                .short("r")
+                .takes_value(true)
+                .help("Why the version was yanked (shown by fetch and query)")))
+        .subcommand(SubCommand::with_name("rdeps")
+            .about("List the latest published components depending on a component")
+            .arg(Arg::with_name("component")
+                .required(true)
+                .help("Component to find dependents of"))
+            .arg(Arg::with_name("version")
+                .long("version")
+                .takes_value(true)
+                .validator(is_integer)
+                .help("Only dependents using this version of the component")))
         .subcommand(SubCommand::with_name("propagate")
             .about("Show steps to propagate a version fully through the tree")
             .arg(Arg::with_name("component")
@@ -262,6 +417,12 @@ pub fn new<'a>() -> App<'a, 'a> {
                 .short("j")
                 .long("json")
                 .help("Produce a machine readable instruction set")))
+        .subcommand(SubCommand::with_name("outdated")
+            .about("List dependencies with newer versions in all supported environments")
+            .arg(Arg::with_name("json")
+                .short("j")
+                .long("json")
+                .help("Print the report as json")))
         .subcommand(SubCommand::with_name("update-all")
             .about("Update all dependencies in the manifest")
             .arg(Arg::with_name("dev")
@@ -271,12 +432,35 @@ pub fn new<'a>() -> App<'a, 'a> {
             .arg(Arg::with_name("save")
                 .short("S")
                 .long("save")
-                .help("Save updated versions in the right object in the manifest")))
+                .help("Save updated versions in the right object in the manifest"))
+            .arg(Arg::with_name("dry-run")
+                .long("dry-run")
+                .short("n")
+                .help("Print the planned changes without making them"))
+            .arg(Arg::with_name("json")
+                .long("json")
+                .short("j")
+                .requires("dry-run")
+                .help("Print the planned changes as json")))
         .subcommand(SubCommand::with_name("publish")
             .setting(AppSettings::Hidden)
             .arg(Arg::with_name("component")
                 .required(true)
                 .help("Component name to publish"))
+            .arg(Arg::with_name("matrix")
+                .long("matrix")
+                .short("m")
+                .help("Publish the release build of every environment from lal build --matrix"))
+            .arg(Arg::with_name("configuration")
+                .long("config")
+                .short("c")
+                .takes_value(true)
+                .requires("matrix")
+                .help("Configuration to publish from the matrix if several were released"))
+            .arg(Arg::with_name("force")
+                .long("force")
+                .short("f")
+                .help("Overwrite versions that are already published"))
             .about("Publish a release build to the default artifactory location"))
         .subcommand(SubCommand::with_name("list-components")
             .setting(AppSettings::Hidden)
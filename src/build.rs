@@ -1,10 +1,21 @@
-use std::{fs, path::Path};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
 
 use super::{
-    ensure_dir_exists_fresh, output, CliError, Config, DockerRunFlags, Environment, LalResult, Lockfile,
-    Manifest, ShellModes,
+    ensure_dir_exists_fresh, output, parse_size, BuildStep, CliError, Config, DockerRunFlags, Environment,
+    LalResult, Lockfile, Manifest, ShellModes,
+};
+use crate::{
+    fetch::fetch,
+    history::BuildRecorder,
+    hooks::{run_hook, Hook, HookContext},
+    shell,
+    storage::CachedBackend,
+    verify::verify,
 };
-use crate::{shell, verify::verify};
 
 
 fn find_valid_build_script(component_dir: &Path) -> LalResult<String> {
@@ -40,6 +51,48 @@ fn find_valid_build_script(component_dir: &Path) -> LalResult<String> {
     Ok(build_string)
 }
 
+// run the build steps of a configuration from the manifest, timing each of them
+fn run_build_steps(
+    cfg: &Config,
+    environment: &Environment,
+    steps: &[BuildStep],
+    ctx: &HookContext,
+    flags: &DockerRunFlags,
+    modes: &ShellModes,
+    component_dir: &Path,
+) -> LalResult<()> {
+    let start = Instant::now();
+    for step in steps {
+        let name = step.display_name();
+        let mut step_modes = modes.clone();
+        step_modes.env_vars.extend(ctx.env_vars());
+        for (k, v) in &step.env {
+            step_modes.env_vars.push(format!("{}={}", k, v));
+        }
+        let script = match step.workdir {
+            Some(ref dir) => format!("cd '{}' && {}", dir.replace('\'', "'\\''"), step.run),
+            None => step.run.clone(),
+        };
+        let cmd = vec!["sh".into(), "-c".into(), script];
+        debug!("Build step {} is {:?}", name, cmd);
+        if !modes.printonly {
+            info!("Step: {}", name);
+        }
+
+        let step_start = Instant::now();
+        shell::run(cfg, environment, cmd, flags, &step_modes, component_dir).map_err(|e| match e {
+            CliError::SubprocessFailure(n) => CliError::BuildStepFailure(name.into(), n),
+            e => e,
+        })?;
+        if !modes.printonly {
+            info!("Step {} took {:.1}s", name, step_start.elapsed().as_secs_f64());
+        }
+    }
+    if !modes.printonly {
+        info!("All build steps took {:.1}s", start.elapsed().as_secs_f64());
+    }
+    Ok(())
+}
 
 /// Configurable build flags for `lal build`
 pub struct BuildOptions {
@@ -66,6 +119,11 @@ pub struct BuildOptions {
 ///
 /// The function performs basic sanity checks, before shelling out
 /// to perform the actual execution of the `./BUILD` script.
+///
+/// If the manifest declares build steps for the configuration, those are run
+/// one after another instead of the `./BUILD` script.
+///
+/// The `pre-build` and `post-build` hooks in `.lal/hooks/` are run around it if present.
 pub fn build(
     component_dir: &Path,
     cfg: &Config,
@@ -130,24 +188,95 @@ pub fn build(
     let lockpth = component_dir.join("./OUTPUT/lockfile.json");
     lockfile.write(&lockpth)?; // always put a lockfile in OUTPUT at the start of a build
 
-    let bpath = find_valid_build_script(&component_dir)?;
-    let cmd = vec![bpath, component.clone(), configuration_name];
+    // build steps in the manifest take the place of the BUILD script
+    let steps = component_settings.build.get(&configuration_name);
+    let cmd = match steps {
+        Some(_) => vec![],
+        None => {
+            let bpath = find_valid_build_script(&component_dir)?;
+            vec![bpath, component.clone(), configuration_name.clone()]
+        }
+    };
+    let hook_ctx = HookContext {
+        component: component.clone(),
+        configuration: Some(configuration_name),
+        version: opts.version.clone(),
+        env: envname.clone(),
+    };
 
     if let Some(v) = opts.version.clone() {
         modes.env_vars.push(format!("BUILD_VERSION={}", v));
     }
-
-    debug!("Build script is {:?} in {}", cmd, component_dir.display());
-    if !modes.printonly {
-        info!("Running build script in {} environment", envname);
+    modes.component = Some(manifest.name.clone());
+    // limits of the component take precedence over those of the environment
+    modes.limits = match opts.environment {
+        Environment::Container(ref c) => c.limits.merge(&component_settings.limits),
+        Environment::None => component_settings.limits.clone(),
     }
+    .merge(&modes.limits);
+    // the timeout covers the whole build, hooks and build steps included
+    modes.deadline = modes
+        .limits
+        .timeout
+        .map(|t| Instant::now() + Duration::from_secs(t));
 
     let run_flags = DockerRunFlags {
         interactive: cfg.interactive,
         privileged: false,
     };
 
-    shell::run(cfg, &opts.environment, cmd, &run_flags, &modes, &component_dir)?;
+    // keep the output and outcome of real builds in .lal/logs
+    let recorder = if modes.printonly {
+        None
+    } else {
+        let r = BuildRecorder::start(
+            &component_dir,
+            &component,
+            hook_ctx.configuration.as_ref().unwrap(),
+            opts.version.clone(),
+            &envname,
+            &opts.environment,
+        )?;
+        modes.log_file = Some(r.log_file());
+        Some(r)
+    };
+
+    let res = run_hook(
+        cfg,
+        &opts.environment,
+        Hook::PreBuild,
+        &hook_ctx,
+        &modes,
+        &component_dir,
+    )
+    .and_then(|_| match steps {
+        Some(steps) => {
+            if !modes.printonly {
+                info!("Running {} build steps in {} environment", steps.len(), envname);
+            }
+            run_build_steps(
+                cfg,
+                &opts.environment,
+                steps,
+                &hook_ctx,
+                &run_flags,
+                &modes,
+                &component_dir,
+            )
+        }
+        None => {
+            debug!("Build script is {:?} in {}", cmd, component_dir.display());
+            if !modes.printonly {
+                info!("Running build script in {} environment", envname);
+            }
+            shell::run(cfg, &opts.environment, cmd, &run_flags, &modes, &component_dir)
+        }
+    });
+    if let Some(r) = recorder {
+        let record = r.finish(&res)?;
+        debug!("Build took {:.1}s", record.duration);
+    }
+    res?;
 
     if modes.printonly {
         return Ok(()); // nothing else worth doing - warnings are pointless
@@ -171,11 +300,172 @@ pub fn build(
         trace!("Copy lockfile to ARTIFACT dir");
         fs::copy(&lockpth, &component_dir.join("./ARTIFACT/lockfile.json"))?;
 
-        trace!("Tar up OUTPUT into ARTIFACT/component.tar.gz");
-        let tarpth = component_dir
-            .join("./ARTIFACT")
-            .join([component, ".tar.gz".into()].concat());
-        output::tar(&component_dir, &tarpth)?;
+        let compression = manifest.compression_for(&component);
+        let packaging = manifest.packaging_for(&component);
+        trace!(
+            "Tar up OUTPUT into ARTIFACT/{}",
+            compression.tarball_name(&component)
+        );
+        let artdir = component_dir.join("./ARTIFACT");
+        output::tar(&component_dir, &artdir, &component, compression, &packaging)?;
+
+        if let Some(ref max) = packaging.maxSize {
+            let limit =
+                parse_size(max).map_err(|_| CliError::InvalidPackagingRule(format!("maxSize '{}'", max)))?;
+            // split parts and debug tarballs are uploaded too, so each one is held to the limit
+            for tarball in output::find_tarballs(&artdir, &component)? {
+                let size = fs::metadata(&tarball)?.len();
+                if size > limit {
+                    let name = tarball.file_name().unwrap().to_string_lossy().to_string();
+                    return Err(CliError::ArtifactTooLarge(name, size, limit));
+                }
+            }
+        }
+    }
+    run_hook(
+        cfg,
+        &opts.environment,
+        Hook::PostBuild,
+        &hook_ctx,
+        &modes,
+        &component_dir,
+    )?;
+    Ok(())
+}
+
+/// Filters for `lal build --matrix`
+///
+/// Empty lists mean every supported environment or configuration.
+#[derive(Default)]
+pub struct MatrixOptions {
+    /// Environments to build in (subset of `supportedEnvironments`)
+    pub environments: Vec<String>,
+    /// Configurations to build (subset of the component's `configurations`)
+    pub configurations: Vec<String>,
+}
+
+// directory a matrix build stages OUTPUT and ARTIFACT in
+// this mirrors a component directory so a staged release can be published from it
+fn matrix_dir(component_dir: &Path, env: &str, configuration: &str) -> PathBuf {
+    component_dir.join("./MATRIX").join(env).join(configuration)
+}
+
+/// Builds a component across environments and configurations
+///
+/// INPUT is fetched for each environment before its configurations are built,
+/// and the OUTPUT and ARTIFACT of every build is moved into `./MATRIX/{env}/{config}`.
+/// Failing builds do not stop the matrix, but are summarized and reported at the end.
+pub fn build_matrix(
+    component_dir: &Path,
+    cfg: &Config,
+    manifest: &Manifest,
+    backend: &dyn CachedBackend,
+    opts: &BuildOptions,
+    matrix: &MatrixOptions,
+    modes: ShellModes,
+) -> LalResult<()> {
+    let component = opts.name.clone().unwrap_or_else(|| manifest.name.clone());
+    let component_settings = match manifest.components.get(&component) {
+        Some(c) => c,
+        None => return Err(CliError::MissingComponent(component)),
+    };
+    for e in &matrix.environments {
+        if !manifest.supportedEnvironments.contains(e) {
+            return Err(CliError::UnsupportedEnvironment);
+        }
+    }
+    for c in &matrix.configurations {
+        if !component_settings.configurations.contains(c) {
+            let ename = format!("{} not found in configurations list", c);
+            return Err(CliError::InvalidBuildConfiguration(ename));
+        }
+    }
+    let envs: Vec<&String> = manifest
+        .supportedEnvironments
+        .iter()
+        .filter(|e| matrix.environments.is_empty() || matrix.environments.contains(e))
+        .collect();
+    let configurations: Vec<&String> = component_settings
+        .configurations
+        .iter()
+        .filter(|c| matrix.configurations.is_empty() || matrix.configurations.contains(c))
+        .collect();
+
+    ensure_dir_exists_fresh(&component_dir.join("./MATRIX"))?;
+    let mut results = vec![];
+    for env in envs {
+        info!("Fetching dependencies for the {} environment", env);
+        let fetched = cfg
+            .get_environment(env.clone())
+            .and_then(|environment| fetch(component_dir, manifest, backend, false, env).map(|_| environment));
+        let environment = match fetched {
+            Ok(environment) => environment,
+            Err(e) => {
+                warn!("Failed to prepare {} environment ({})", env, e);
+                for configuration in &configurations {
+                    results.push((format!("{}/{}", env, configuration), Some(e.to_string())));
+                }
+                continue;
+            }
+        };
+        for configuration in &configurations {
+            info!("Building {} {} in {}", component, configuration, env);
+            let build_opts = BuildOptions {
+                name: Some(component.clone()),
+                configuration: Some(configuration.to_string()),
+                environment: environment.clone(),
+                release: opts.release,
+                version: opts.version.clone(),
+                sha: opts.sha.clone(),
+                force: opts.force,
+                simple_verify: opts.simple_verify,
+            };
+            let res = build(
+                component_dir,
+                cfg,
+                manifest,
+                &build_opts,
+                env.clone(),
+                modes.clone(),
+            )
+            .and_then(|_| stage_matrix_build(component_dir, env, configuration, opts.release));
+            if let Err(ref e) = res {
+                warn!(
+                    "Build of {} {} in {} failed ({})",
+                    component, configuration, env, e
+                );
+            }
+            results.push((
+                format!("{}/{}", env, configuration),
+                res.err().map(|e| e.to_string()),
+            ));
+        }
+    }
+
+    info!("Build matrix for {}:", component);
+    let mut failed = vec![];
+    for (name, err) in results {
+        match err {
+            None => info!("  PASS {}", name),
+            Some(e) => {
+                info!("  FAIL {} ({})", name, e);
+                failed.push(name);
+            }
+        }
+    }
+    if !failed.is_empty() {
+        return Err(CliError::MatrixBuildFailure(failed));
+    }
+    Ok(())
+}
+
+// move the results of a build into its matrix staging area
+fn stage_matrix_build(component_dir: &Path, env: &str, configuration: &str, release: bool) -> LalResult<()> {
+    let stage = matrix_dir(component_dir, env, configuration);
+    ensure_dir_exists_fresh(&stage)?;
+    fs::rename(component_dir.join("./OUTPUT"), stage.join("OUTPUT"))?;
+    if release {
+        fs::rename(component_dir.join("./ARTIFACT"), stage.join("ARTIFACT"))?;
     }
     Ok(())
 }
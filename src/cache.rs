@@ -0,0 +1,207 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, UTC};
+use walkdir::WalkDir;
+
+use super::{cache_last_use, get_extracted_dir, human_size, parse_size, remove_extracted_tree, LalResult};
+use crate::clean::modified_time;
+
+/// A component tarball stored in the local cache
+pub struct CacheEntry {
+    /// Name of the component
+    pub name: String,
+    /// Version number, or name of the stash
+    pub version: String,
+    /// Environment the component was built in (`None` for stashed builds)
+    pub environment: Option<String>,
+    /// Directory holding the cached artifact
+    pub path: PathBuf,
+    /// Size of the directory in bytes
+    pub size: u64,
+    /// Last time the artifact was used (or cached if never used)
+    pub last_use: DateTime<UTC>,
+}
+
+fn dir_size(path: &Path) -> u64 {
+    WalkDir::new(path)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter_map(|e| e.metadata().ok())
+        .filter(|m| m.is_file())
+        .map(|m| m.len())
+        .sum()
+}
+
+impl CacheEntry {
+    fn new(path: &Path, name: &str, version: &str, environment: Option<&str>) -> LalResult<CacheEntry> {
+        let last_use = match cache_last_use(path) {
+            Some(t) => t,
+            None => modified_time(&fs::metadata(path)?),
+        };
+        Ok(CacheEntry {
+            name: name.into(),
+            version: version.into(),
+            environment: environment.map(String::from),
+            path: path.to_path_buf(),
+            size: dir_size(path),
+            last_use,
+        })
+    }
+
+    /// Where the entry came from - the environment name or "stash"
+    pub fn origin(&self) -> &str {
+        match self.environment {
+            Some(ref e) => e,
+            None => "stash",
+        }
+    }
+}
+
+// relative path components of a cache directory under a root
+fn path_parts(root: &Path, pth: &Path) -> Vec<String> {
+    pth.strip_prefix(root)
+        .unwrap()
+        .iter()
+        .map(|p| p.to_string_lossy().into_owned())
+        .collect()
+}
+
+/// Find all published and stashed components in the cache
+///
+/// Published components are sorted by environment, name and version,
+/// and are followed by stashed components.
+pub fn entries(cache: &Path) -> LalResult<Vec<CacheEntry>> {
+    let mut published = vec![];
+    let edir = cache.join("environments");
+    let edirs = WalkDir::new(&edir)
+        .min_depth(3)
+        .max_depth(3)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().is_dir());
+    for d in edirs {
+        let parts = path_parts(&edir, d.path());
+        published.push(CacheEntry::new(d.path(), &parts[1], &parts[2], Some(&parts[0]))?);
+    }
+    published.sort_by_key(|e| {
+        (
+            e.environment.clone(),
+            e.name.clone(),
+            e.version.parse::<u32>().unwrap_or(0),
+        )
+    });
+
+    let mut stashed = vec![];
+    let sdir = cache.join("stash");
+    let sdirs = WalkDir::new(&sdir)
+        .min_depth(2)
+        .max_depth(2)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().is_dir());
+    for d in sdirs {
+        let parts = path_parts(&sdir, d.path());
+        stashed.push(CacheEntry::new(d.path(), &parts[0], &parts[1], None)?);
+    }
+    stashed.sort_by_key(|e| (e.name.clone(), e.version.clone()));
+
+    published.extend(stashed);
+    Ok(published)
+}
+
+/// List all components in the cache with their size and last use
+pub fn ls(cache: &Path) -> LalResult<()> {
+    println!(
+        "{:<12} {:<30} {:>12} {:>10}  LAST USED",
+        "ENVIRONMENT", "COMPONENT", "VERSION", "SIZE"
+    );
+    for e in entries(cache)? {
+        println!(
+            "{:<12} {:<30} {:>12} {:>10}  {}",
+            e.origin(),
+            e.name,
+            e.version,
+            human_size(e.size),
+            e.last_use.format("%Y-%m-%d %H:%M")
+        );
+    }
+    Ok(())
+}
+
+/// Print the total size of the cache per environment and for stashed builds
+pub fn du(cache: &Path) -> LalResult<()> {
+    let mut totals: BTreeMap<String, u64> = BTreeMap::new();
+    let mut total = 0;
+    for e in entries(cache)? {
+        *totals.entry(e.origin().to_string()).or_insert(0) += e.size;
+        total += e.size;
+    }
+    for (origin, size) in totals {
+        println!("{:>10}  {}", human_size(size), origin);
+    }
+    // trees shared through hardlinks or symlinks when input_strategy is not extract
+    let extracted = dir_size(&cache.join("extracted"));
+    if extracted > 0 {
+        println!("{:>10}  extracted", human_size(extracted));
+        total += extracted;
+    }
+    println!("{:>10}  total", human_size(total));
+    Ok(())
+}
+
+/// Evict the least recently used published components until the cache fits in `max_size`
+///
+/// Stashed builds are never evicted, but they do not count towards the limit either.
+/// Last use is recorded whenever a component is fetched, updated or exported.
+/// Extracted trees of evicted components are removed along with them, unless an
+/// `INPUT` still symlinks or hardlinks to them.
+pub fn prune(cache: &Path, max_size: &str) -> LalResult<()> {
+    let limit = parse_size(max_size)?;
+    let mut published: Vec<CacheEntry> = entries(cache)?
+        .into_iter()
+        .filter(|e| e.environment.is_some())
+        .collect();
+    let mut total: u64 = published.iter().map(|e| e.size).sum();
+    debug!("Cache holds {} of published components (limit {})", human_size(total), human_size(limit));
+    if total <= limit {
+        return Ok(());
+    }
+
+    published.sort_by_key(|e| e.last_use);
+    let mut freed = 0;
+    for e in published {
+        if total <= limit {
+            break;
+        }
+        info!(
+            "Evicting {} {}={} ({}, last used {})",
+            e.origin(),
+            e.name,
+            e.version,
+            human_size(e.size),
+            e.last_use.format("%Y-%m-%d")
+        );
+        fs::remove_dir_all(&e.path)?;
+        if let (Some(env), Ok(v)) = (e.environment.as_ref(), e.version.parse::<u32>()) {
+            let tree = get_extracted_dir(cache, &e.name, v, env);
+            remove_extracted_tree(&tree)?;
+            // trees of only some parts of it are named `<version>+<parts>`
+            let prefix = format!("{}+", v);
+            if let Ok(entries) = fs::read_dir(tree.parent().unwrap()) {
+                for entry in entries.filter_map(|e| e.ok()) {
+                    if entry.file_name().to_string_lossy().starts_with(&prefix) {
+                        remove_extracted_tree(&entry.path())?;
+                    }
+                }
+            }
+        }
+        total -= e.size;
+        freed += e.size;
+    }
+    info!("Freed {} from the cache", human_size(freed));
+    Ok(())
+}
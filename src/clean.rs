@@ -1,10 +1,23 @@
-use std::{fs, path::Path};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::Path,
+};
 
 use chrono::{DateTime, Duration, TimeZone, UTC};
 use filetime::FileTime;
 use walkdir::WalkDir;
 
-use super::LalResult;
+use super::{
+    docker_image_size, fixup_base_name, human_size, list_docker_images, remove_docker_image,
+    remove_extracted_tree, Config, DockerImage, Environment, LalResult,
+};
+
+/// Modification time of a file or directory as a `DateTime`
+pub fn modified_time(meta: &fs::Metadata) -> DateTime<UTC> {
+    let mtime = FileTime::from_last_modification_time(meta);
+    UTC.ymd(1970, 1, 1).and_hms(0, 0, 0) + Duration::seconds(mtime.seconds_relative_to_1970() as i64)
+}
 
 // helper for `lal::clean`
 fn clean_in_dir(cutoff: DateTime<UTC>, dirs: WalkDir) -> LalResult<()> {
@@ -16,14 +29,14 @@ fn clean_in_dir(cutoff: DateTime<UTC>, dirs: WalkDir) -> LalResult<()> {
     for d in drs {
         let pth = d.path();
         trace!("Checking {}", pth.to_str().unwrap());
-        let mtime = FileTime::from_last_modification_time(&d.metadata().unwrap());
-        let mtimedate =
-            UTC.ymd(1970, 1, 1).and_hms(0, 0, 0) + Duration::seconds(mtime.seconds_relative_to_1970() as i64);
+        let mtimedate = modified_time(&d.metadata().unwrap());
 
         trace!("Found {} with mtime {}", pth.to_str().unwrap(), mtimedate);
         if mtimedate < cutoff {
             debug!("Cleaning {}", pth.to_str().unwrap());
             fs::remove_dir_all(pth)?;
+            // drop the component directory along with its last artifact
+            let _ = fs::remove_dir(pth.parent().unwrap());
         }
     }
     Ok(())
@@ -31,8 +44,9 @@ fn clean_in_dir(cutoff: DateTime<UTC>, dirs: WalkDir) -> LalResult<()> {
 
 /// Clean old artifacts in cache directory
 ///
-/// This does the equivalent of find CACHEDIR -mindepth 3 -maxdepth 3 -type d
-/// With the correct mtime flags, then -exec deletes these folders.
+/// This does the equivalent of find CACHEDIR/environments -mindepth 3 -maxdepth 3 -type d
+/// (and CACHEDIR/stash at depth 2) with the correct mtime flags, then -exec deletes these folders.
+/// Old extracted trees are deleted as well, unless an `INPUT` still links to them.
 pub fn clean(cache: &Path, days: i64) -> LalResult<()> {
     let cutoff = UTC::now() - Duration::days(days);
     debug!("Cleaning all artifacts from before {}", cutoff);
@@ -43,8 +57,98 @@ pub fn clean(cache: &Path, days: i64) -> LalResult<()> {
     clean_in_dir(cutoff, edirs)?;
 
     // clean out stash
-    let dirs = WalkDir::new(&cache).min_depth(3).max_depth(3);
-    clean_in_dir(cutoff, dirs)?;
+    let sdir = cache.join("stash");
+    let sdirs = WalkDir::new(&sdir).min_depth(2).max_depth(2);
+    clean_in_dir(cutoff, sdirs)?;
 
+    // clean out extracted trees no longer in use
+    let xdir = cache.join("extracted");
+    let xdirs = WalkDir::new(&xdir)
+        .min_depth(3)
+        .max_depth(3)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().is_dir());
+    for d in xdirs {
+        if modified_time(&d.metadata().unwrap()) < cutoff && remove_extracted_tree(d.path())? {
+            let _ = fs::remove_dir(d.path().parent().unwrap());
+        }
+    }
+
+    Ok(())
+}
+
+/// Finds the lal images no environment in the config uses anymore
+///
+/// These are user fixup images whose base is no longer a configured environment image,
+/// and tags of configured environment images that no environment references.
+/// Fixup images without the `lal.fixup` label (built by older lal versions) are only
+/// matched when their base is the image of a configured environment.
+pub fn find_stale_images(cfg: &Config, images: Vec<DockerImage>) -> Vec<DockerImage> {
+    // configured image names and their tags
+    let mut tags: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    for environment in cfg.environments.values() {
+        if let Environment::Container(c) = environment {
+            tags.entry(c.name.clone()).or_default().insert(c.tag.clone());
+        }
+    }
+    // image ids the configured environments currently resolve to
+    let current_ids: BTreeSet<String> = images
+        .iter()
+        .filter(|i| match tags.get(&i.repository) {
+            Some(t) => t.contains(&i.tag),
+            None => false,
+        })
+        .map(|i| i.id.clone())
+        .collect();
+
+    images
+        .into_iter()
+        .filter(|img| match (fixup_base_name(img), tags.get(&img.repository)) {
+            // fixup images are tagged with the image id they were built from
+            (Some((base, base_id)), _) if img.fixup || tags.contains_key(base) => {
+                !tags.contains_key(base) || !current_ids.contains(base_id)
+            }
+            // old pulls of a configured environment image
+            (None, Some(t)) => !t.contains(&img.tag),
+            _ => false,
+        })
+        .collect()
+}
+
+/// Clean docker images derived from environments that are no longer configured
+///
+/// This covers the `<name>-u<uid>_g<gid>:from_<id>` images built for the `rebuild`
+/// user mapping when their base image is no longer the configured one, as well as
+/// old tags of images used by environments in the config.
+///
+/// With `dry_run` set, the images are only listed along with their sizes.
+pub fn clean_images(cfg: &Config, dry_run: bool) -> LalResult<()> {
+    let stale = find_stale_images(cfg, list_docker_images()?);
+    if stale.is_empty() {
+        info!("No unused lal images found");
+        return Ok(());
+    }
+
+    let mut total = 0;
+    for img in stale {
+        let reference = img.reference();
+        let size = docker_image_size(&reference).unwrap_or(0);
+        if dry_run {
+            println!("{} ({})", reference, human_size(size));
+            total += size;
+        } else {
+            info!("Removing {} ({})", reference, human_size(size));
+            match remove_docker_image(&reference) {
+                Ok(()) => total += size,
+                Err(e) => warn!("Failed to remove {}: {}", reference, e),
+            }
+        }
+    }
+    if dry_run {
+        info!("Would reclaim {}", human_size(total));
+    } else {
+        info!("Reclaimed {}", human_size(total));
+    }
     Ok(())
 }
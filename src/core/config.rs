@@ -7,7 +7,7 @@ use std::{
     vec::Vec,
 };
 
-use super::{CliError, Container, Environment, LalResult};
+use super::{parse_size, CliError, Container, Environment, LalResult};
 use crate::storage::BackendConfiguration;
 
 fn find_home_dir() -> PathBuf {
@@ -39,6 +39,49 @@ pub struct Mount {
     pub readonly: bool,
 }
 
+/// How components are placed into `INPUT` from the cache
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum InputStrategy {
+    /// Extract the cached tarball into `INPUT` on every fetch
+    #[serde(rename = "extract")]
+    Extract,
+    /// Hardlink files from a shared extracted tree in the cache
+    #[serde(rename = "hardlink")]
+    Hardlink,
+    /// Copy-on-write copy of a shared extracted tree (falls back to a plain copy)
+    #[serde(rename = "reflink")]
+    Reflink,
+    /// Symlink `INPUT/<name>` to a shared extracted tree (mounted read-only in containers)
+    #[serde(rename = "symlink")]
+    Symlink,
+}
+
+/// Extracting is the historical behaviour
+impl Default for InputStrategy {
+    fn default() -> Self {
+        InputStrategy::Extract
+    }
+}
+
+/// Limits on what a single tarball may extract to
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExtractLimits {
+    /// Total size in bytes of the files in a tarball
+    pub max_size: u64,
+    /// Number of entries in a tarball
+    pub max_entries: u64,
+}
+
+/// Generous enough for any real component, while still stopping tarball bombs
+impl Default for ExtractLimits {
+    fn default() -> Self {
+        ExtractLimits {
+            max_size: 200 * 1000 * 1000 * 1000,
+            max_entries: 1_000_000,
+        }
+    }
+}
+
 /// Representation of `~/.lal/config`
 #[allow(non_snake_case)]
 #[derive(Serialize, Deserialize, Clone)]
@@ -59,6 +102,20 @@ pub struct Config {
     pub interactive: bool,
     /// Minimum version restriction of lal enforced by this config
     pub minimum_lal: Option<String>,
+    /// Cache size to prune down to after fetching (e.g. "20G")
+    pub max_cache_size: Option<String>,
+    /// How to populate `INPUT` from the cache
+    #[serde(default)]
+    pub input_strategy: InputStrategy,
+    /// Seconds the local metadata index of the backend is used before refreshing
+    #[serde(default)]
+    pub index_ttl: Option<u64>,
+    /// Total size a single tarball may extract to (e.g. "200G")
+    #[serde(default)]
+    pub max_extract_size: Option<String>,
+    /// Number of entries a single tarball may have
+    #[serde(default)]
+    pub max_extract_entries: Option<u64>,
 }
 
 /// Representation of a configuration defaults file
@@ -74,6 +131,20 @@ pub struct ConfigDefaults {
     pub mounts: Vec<Mount>,
     /// Optional minimum version restriction of lal
     pub minimum_lal: Option<String>,
+    /// Optional cache size to prune down to after fetching
+    pub max_cache_size: Option<String>,
+    /// How to populate `INPUT` from the cache
+    #[serde(default)]
+    pub input_strategy: InputStrategy,
+    /// Seconds the local metadata index of the backend is used before refreshing
+    #[serde(default)]
+    pub index_ttl: Option<u64>,
+    /// Optional total size a single tarball may extract to
+    #[serde(default)]
+    pub max_extract_size: Option<String>,
+    /// Optional number of entries a single tarball may have
+    #[serde(default)]
+    pub max_extract_entries: Option<u64>,
 }
 
 impl ConfigDefaults {
@@ -154,10 +225,27 @@ impl Config {
             environments: defaults.environments,
             backend: defaults.backend,
             minimum_lal: defaults.minimum_lal,
+            max_cache_size: defaults.max_cache_size,
+            input_strategy: defaults.input_strategy,
+            index_ttl: defaults.index_ttl,
+            max_extract_size: defaults.max_extract_size,
+            max_extract_entries: defaults.max_extract_entries,
             interactive: true,
         }
     }
 
+    /// Limits on extracting tarballs, with the defaults for anything not configured
+    pub fn extract_limits(&self) -> LalResult<ExtractLimits> {
+        let mut limits = ExtractLimits::default();
+        if let Some(ref size) = self.max_extract_size {
+            limits.max_size = parse_size(size)?;
+        }
+        if let Some(entries) = self.max_extract_entries {
+            limits.max_entries = entries;
+        }
+        Ok(limits)
+    }
+
     /// Read and deserialize a Config from ~/.lal/config
     pub fn read(home: Option<&Path>) -> LalResult<Config> {
         let cfg_path = config_dir(home).join("config");
@@ -1,5 +1,88 @@
 use std::fmt;
 
+/// Strategy for running as the host user inside a container
+///
+/// Builds need files in the mounted volume to be owned by the host user,
+/// so the `lal` user inside the container must end up with the host uid and gid.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum UserMapping {
+    /// Build a derived `<name>-u<uid>_g<gid>` image with a modified `lal` user
+    #[serde(rename = "rebuild")]
+    Rebuild,
+    /// Run with `--user` and bind mount a generated passwd and group file
+    #[serde(rename = "passwd")]
+    Passwd,
+    /// Start as root and remap the `lal` user in an entrypoint shim
+    #[serde(rename = "entrypoint")]
+    Entrypoint,
+}
+
+impl UserMapping {
+    /// Whether this is the default strategy (used to keep serialized configs terse)
+    pub fn is_default(&self) -> bool {
+        *self == UserMapping::default()
+    }
+}
+
+/// Rebuilding images is the historical behaviour
+impl Default for UserMapping {
+    fn default() -> Self {
+        UserMapping::Rebuild
+    }
+}
+
+/// Resource limits for commands run in a container
+///
+/// Set per environment in the config and per component in the manifest,
+/// where the component's limits take precedence.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct ResourceLimits {
+    /// Number of CPUs the container may use (`--cpus`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpus: Option<f64>,
+    /// Memory the container may use, e.g. "4g" (`--memory`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory: Option<String>,
+    /// Number of processes the container may run (`--pids-limit`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pids: Option<u32>,
+    /// Seconds a build may take before its container is killed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u64>,
+}
+
+impl ResourceLimits {
+    /// Whether no limits are set (used to keep serialized configs terse)
+    pub fn is_empty(&self) -> bool {
+        *self == ResourceLimits::default()
+    }
+
+    /// These limits with any set in `overrides` taking precedence
+    pub fn merge(&self, overrides: &ResourceLimits) -> ResourceLimits {
+        ResourceLimits {
+            cpus: overrides.cpus.or(self.cpus),
+            memory: overrides.memory.clone().or_else(|| self.memory.clone()),
+            pids: overrides.pids.or(self.pids),
+            timeout: overrides.timeout.or(self.timeout),
+        }
+    }
+
+    /// Arguments to `docker run` enforcing the limits (the timeout is enforced by lal)
+    pub fn docker_args(&self) -> Vec<String> {
+        let mut args = vec![];
+        if let Some(cpus) = self.cpus {
+            args.push(format!("--cpus={}", cpus));
+        }
+        if let Some(ref memory) = self.memory {
+            args.push(format!("--memory={}", memory));
+        }
+        if let Some(pids) = self.pids {
+            args.push(format!("--pids-limit={}", pids));
+        }
+        args
+    }
+}
+
 /// Representation of a docker container image
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Container {
@@ -7,6 +90,12 @@ pub struct Container {
     pub name: String,
     /// The tag to use
     pub tag: String,
+    /// How the host user is mapped onto the `lal` user in this container
+    #[serde(default, skip_serializing_if = "UserMapping::is_default")]
+    pub user_mapping: UserMapping,
+    /// Resource limits for commands run in this container
+    #[serde(default, skip_serializing_if = "ResourceLimits::is_empty")]
+    pub limits: ResourceLimits,
 }
 
 impl Container {
@@ -15,6 +104,8 @@ impl Container {
         Container {
             name: name.into(),
             tag: "latest".into(),
+            user_mapping: UserMapping::default(),
+            limits: ResourceLimits::default(),
         }
     }
 }
@@ -32,6 +123,8 @@ impl Default for Container {
         Container {
             name: "ubuntu".into(),
             tag: "xenial".into(),
+            user_mapping: UserMapping::default(),
+            limits: ResourceLimits::default(),
         }
     }
 }
@@ -48,6 +141,8 @@ impl Container {
         Container {
             name: cname.into(),
             tag: tag.into(),
+            user_mapping: UserMapping::default(),
+            limits: ResourceLimits::default(),
         }
     }
 }
@@ -1,5 +1,7 @@
 use std::{fmt, io};
 
+use super::human_size;
+
 /// The one and only error type for the lal library
 ///
 /// Every command will raise one of these on failure, and these is some reuse between
@@ -73,16 +75,40 @@ pub enum CliError {
     BuildScriptNotExecutable(String),
     /// BUILD script not found
     MissingBuildScript,
+    /// Build step in the manifest is not valid
+    InvalidBuildStep(String),
+    /// Build step from the manifest exited with a non-zero code
+    BuildStepFailure(String, i32),
+    /// No recorded build to show the log of
+    MissingBuildLog,
 
     // script errors
     /// Script not found in local .lal/scripts/ directory
     MissingScript(String),
 
+    // hook errors
+    /// Hook script in .lal/hooks/ not executable
+    HookNotExecutable(String),
+    /// Hook script exited with a non-zero code
+    HookFailure(String, i32),
+
     // cache errors
     /// Failed to find a tarball after fetching from artifactory
     MissingTarball,
     /// Failed to find build artifacts in OUTPUT after a build or before stashing
     MissingBuild,
+    /// Cache size limit could not be parsed
+    InvalidCacheSize(String),
+    /// Tarball is not compressed with a supported format
+    UnknownCompression(String),
+    /// Tarball entry would escape the extraction directory or exceed extraction limits
+    UnsafeTarballEntry(String, String),
+    /// Invalid glob or size in the packaging rules of a component
+    InvalidPackagingRule(String),
+    /// Release tarball is larger than the maxSize of its packaging rules
+    ArtifactTooLarge(String, u64, u64),
+    /// Builds in a build matrix that failed
+    MatrixBuildFailure(Vec<String>),
 
     // stash errors
     /// Invalid integer name used with lal stash
@@ -92,6 +118,12 @@ pub enum CliError {
 
     /// Shell errors from docker subprocess
     SubprocessFailure(i32),
+    /// Command ran for longer than its timeout and was stopped
+    Timeout(u64),
+    /// Command was stopped because lal received a signal
+    Interrupted(i32),
+    /// Container was not started by lal
+    UnknownContainer(String),
     /// Docker permission gate
     DockerPermissionSafety(String, u32, u32),
     /// Docker image not found
@@ -102,12 +134,30 @@ pub enum CliError {
     InstallFailure,
     /// Fetch failure related to backend
     BackendFailure(String),
+    /// Nothing published at a location on the backend
+    BackendNotFound(String),
     /// No version found at same version across `supportedEnvironments`
     NoIntersectedVersion(String),
+    /// Version of a component not published in any environment looked at
+    MissingPublishedVersion(String, u32),
+    /// Argument not of the form component=version
+    InvalidVersionSpec(String),
+    /// Filter passed to query that cannot be parsed
+    InvalidQueryFilter(String),
+    /// Dependencies with newer versions available
+    OutdatedDependencies(Vec<String>),
 
     // publish errors
     /// Missing release build
     MissingReleaseBuild,
+    /// Release build that must not be published
+    InvalidReleaseBuild(String),
+    /// Version already published in an environment
+    VersionAlreadyPublished(String, u32, String),
+    /// Release builds of a build matrix that cannot be published together
+    InconsistentMatrix(String),
+    /// Publish that failed, and could not be rolled back in some environments
+    IncompletePublish(String, u32, Vec<String>),
     /// Config missing backend credentials
     MissingBackendCredentials,
     /// Failed upload request to the backend
@@ -191,11 +241,36 @@ impl fmt::Display for CliError {
             CliError::InvalidBuildConfiguration(ref s) => write!(f, "Invalid build configuration - {}", s),
             CliError::BuildScriptNotExecutable(ref s) => write!(f, "BUILD script at {} is not executable", s),
             CliError::MissingBuildScript => write!(f, "No `BUILD` script found"),
+            CliError::InvalidBuildStep(ref s) => write!(f, "Invalid build step - {}", s),
+            CliError::BuildStepFailure(ref s, n) => write!(f, "Build step '{}' exited with {}", s, n),
+            CliError::MissingBuildLog => write!(f, "No recorded builds found in .lal/logs"),
             CliError::MissingScript(ref s) => {
                 write!(f, "Missing script '{}' in local folder .lal/scripts/", s)
             }
+            CliError::HookNotExecutable(ref s) => write!(f, "Hook script at {} is not executable", s),
+            CliError::HookFailure(ref s, n) => write!(f, "The {} hook exited with {}", s, n),
             CliError::MissingTarball => write!(f, "Tarball missing in PWD"),
             CliError::MissingBuild => write!(f, "No build found in OUTPUT"),
+            CliError::InvalidCacheSize(ref s) => {
+                write!(f, "Invalid cache size '{}' - expected a number with an optional K/M/G/T suffix", s)
+            }
+            CliError::UnknownCompression(ref s) => {
+                write!(f, "Unrecognised compression format of {} - expected gzip, zstd or xz", s)
+            }
+            CliError::UnsafeTarballEntry(ref entry, ref reason) => {
+                write!(f, "Refusing to extract '{}' from tarball - {}", entry, reason)
+            }
+            CliError::InvalidPackagingRule(ref s) => write!(f, "Invalid packaging rule {}", s),
+            CliError::ArtifactTooLarge(ref s, size, max) => write!(
+                f,
+                "Artifact {} is {}, larger than the maxSize of {}",
+                s,
+                human_size(size),
+                human_size(max)
+            ),
+            CliError::MatrixBuildFailure(ref xs) => {
+                write!(f, "Matrix build failed for {}", xs.join(", "))
+            }
             CliError::InvalidStashName(n) => {
                 write!(f, "Invalid name '{}' to stash under - must not be an integer", n)
             }
@@ -203,6 +278,9 @@ impl fmt::Display for CliError {
                 write!(f, "No stashed artifact '{}' found in ~/.lal/cache/stash", s)
             }
             CliError::SubprocessFailure(n) => write!(f, "Process exited with {}", n),
+            CliError::Timeout(n) => write!(f, "Timed out after {}s and was stopped", n),
+            CliError::Interrupted(sig) => write!(f, "Interrupted by signal {} and was stopped", sig),
+            CliError::UnknownContainer(ref s) => write!(f, "{} is not a container started by lal", s),
             CliError::DockerPermissionSafety(ref s, u, g) => write!(
                 f,
                 "ID mismatch inside and outside docker - {}; UID and GID are {}:{}",
@@ -211,10 +289,33 @@ impl fmt::Display for CliError {
             CliError::DockerImageNotFound(ref s) => write!(f, "Could not find docker image {}", s),
             CliError::InstallFailure => write!(f, "Install failed"),
             CliError::BackendFailure(ref s) => write!(f, "Backend - {}", s),
+            CliError::BackendNotFound(ref s) => write!(f, "Backend - nothing found at {}", s),
             CliError::NoIntersectedVersion(ref s) => {
                 write!(f, "No version of {} found across all environments", s)
             }
+            CliError::MissingPublishedVersion(ref n, v) => write!(f, "{}={} is not published", n, v),
+            CliError::InvalidVersionSpec(ref s) => {
+                write!(f, "Expected component=version with an integer version, got {}", s)
+            }
+            CliError::InvalidQueryFilter(ref s) => write!(f, "Invalid query filter {}", s),
+            CliError::OutdatedDependencies(ref xs) => {
+                write!(f, "Newer versions available for {}", xs.join(", "))
+            }
             CliError::MissingReleaseBuild => write!(f, "Missing release build"),
+            CliError::InvalidReleaseBuild(ref s) => write!(f, "Invalid release build: {}", s),
+            CliError::VersionAlreadyPublished(ref n, v, ref env) => write!(
+                f,
+                "{}={} is already published in {} - use --force to overwrite it",
+                n, v, env
+            ),
+            CliError::InconsistentMatrix(ref s) => write!(f, "Cannot publish build matrix: {}", s),
+            CliError::IncompletePublish(ref n, v, ref envs) => write!(
+                f,
+                "{}={} is incompletely published - remove it from {} manually",
+                n,
+                v,
+                envs.join(", ")
+            ),
             CliError::MissingBackendCredentials => write!(f, "Missing backend credentials in ~/.lal/config"),
             CliError::MissingPrefixPermissions(ref s) => write!(
                 f,
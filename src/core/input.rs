@@ -1,6 +1,11 @@
 #![allow(missing_docs)]
 
-use std::{collections::BTreeMap, fs::File, io::prelude::*, path::Path};
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{self, prelude::*},
+    path::{Path, PathBuf},
+};
 
 use walkdir::WalkDir;
 
@@ -24,10 +29,69 @@ fn read_partial_lockfile(component: &str, component_dir: &Path) -> LalResult<Par
     Ok(serde_json::from_str(&lock_str)?)
 }
 
+/// Directory in `INPUT` that components are unpacked into before being swapped in
+pub const STAGING_DIR: &str = ".staging";
+
+/// Component directory that components unpacked into `dir` end up in
+///
+/// This is `dir` itself, except for the staging area of an `InputTransaction`.
+pub fn final_component_dir(dir: &Path) -> &Path {
+    let staged = dir.file_name().map(|f| f == STAGING_DIR).unwrap_or(false);
+    match dir.parent() {
+        Some(input) if staged && input.file_name().map(|f| f == "INPUT").unwrap_or(false) => {
+            input.parent().unwrap()
+        }
+        _ => dir,
+    }
+}
+
 pub fn present(component_dir: &Path) -> bool {
     component_dir.join("./INPUT").is_dir()
 }
 
+/// File next to `INPUT/<name>` recording which parts of the component are installed
+///
+/// Components installed whole have no such file.
+pub fn parts_file(component_dir: &Path, name: &str) -> PathBuf {
+    component_dir.join("INPUT").join(format!(".{}.parts", name))
+}
+
+// parts in the order they are recorded in
+fn sorted_parts(parts: &[String]) -> Vec<String> {
+    let mut parts = parts.to_vec();
+    parts.sort();
+    parts.dedup();
+    parts
+}
+
+/// Record the parts of a component installed in `INPUT`
+pub fn record_parts(component_dir: &Path, name: &str, parts: &[String]) -> LalResult<()> {
+    let mut f = File::create(parts_file(component_dir, name))?;
+    writeln!(f, "{}", serde_json::to_string(&sorted_parts(parts))?)?;
+    Ok(())
+}
+
+/// Whether `INPUT/<name>` holds exactly the wanted parts (`None` for the whole component)
+pub fn has_parts(component_dir: &Path, name: &str, wanted: Option<&Vec<String>>) -> bool {
+    let pth = parts_file(component_dir, name);
+    let installed: Option<Vec<String>> = if pth.is_file() {
+        let mut data = String::new();
+        if File::open(&pth)
+            .and_then(|mut f| f.read_to_string(&mut data))
+            .is_err()
+        {
+            return false;
+        }
+        match serde_json::from_str(&data) {
+            Ok(parts) => Some(parts),
+            Err(_) => return false,
+        }
+    } else {
+        None
+    };
+    installed == wanted.map(|p| sorted_parts(p))
+}
+
 /// Simple INPUT analyzer for the lockfile generator and `analyze_full`
 pub fn analyze(component_dir: &Path) -> LalResult<BTreeMap<String, String>> {
     let input = component_dir.join("./INPUT");
@@ -41,7 +105,7 @@ pub fn analyze(component_dir: &Path) -> LalResult<BTreeMap<String, String>> {
         .max_depth(1)
         .into_iter()
         .filter_map(|e| e.ok())
-        .filter(|e| e.path().is_dir());
+        .filter(|e| e.path().is_dir() && e.file_name() != STAGING_DIR);
 
     for d in dirs {
         let pth = d.path().strip_prefix(&component_dir.join("INPUT")).unwrap();
@@ -121,7 +185,7 @@ pub fn verify_dependencies_present(component_dir: &Path, m: &Manifest) -> LalRes
         .max_depth(1)
         .into_iter()
         .filter_map(|e| e.ok())
-        .filter(|e| e.path().is_dir());
+        .filter(|e| e.path().is_dir() && e.file_name() != STAGING_DIR);
     for entry in dirs {
         let pth = entry.path().strip_prefix(component_dir.join("INPUT")).unwrap();
         debug!("-> {}", pth.display());
@@ -196,7 +260,22 @@ pub fn verify_consistent_dependency_versions(lf: &Lockfile, m: &Manifest) -> Lal
 }
 
 /// Strict requirement for verifier - all deps must be built in same environment
-pub fn verify_environment_consistency(lf: &Lockfile, env: &str) -> LalResult<()> {
+///
+/// Dependencies declared with their own environment in the manifest are exempt,
+/// but they, and everything they depend on, must be built in that environment instead.
+pub fn verify_environment_consistency(mut lf: Lockfile, manifest: &Manifest, env: &str) -> LalResult<()> {
+    for (name, dep_env) in &manifest.dependencyEnvironments {
+        if let Some(dep) = lf.dependencies.remove(name) {
+            debug!("Verifying {} against its declared environment {}", name, dep_env);
+            let mut sub = Lockfile::default();
+            sub.dependencies.insert(name.clone(), dep);
+            verify_single_environment(&sub, dep_env)?;
+        }
+    }
+    verify_single_environment(&lf, env)
+}
+
+fn verify_single_environment(lf: &Lockfile, env: &str) -> LalResult<()> {
     for (name, envs) in lf.find_all_environments() {
         debug!("Found environment(s) for {} as {:?}", name, envs);
         if envs.len() != 1 {
@@ -211,3 +290,112 @@ pub fn verify_environment_consistency(lf: &Lockfile, env: &str) -> LalResult<()>
     }
     Ok(())
 }
+
+/// Changes to `INPUT` that are applied together or not at all
+///
+/// Components are unpacked into `INPUT/.staging` (by passing `staging_dir` as the
+/// component directory to the unpack functions), and only swapped into `INPUT`
+/// by `commit`. Dropping the transaction without committing it removes the
+/// staging area and leaves `INPUT` as it was.
+pub struct InputTransaction {
+    input: PathBuf,
+    staging: PathBuf,
+    staged: Vec<String>,
+    removed: Vec<String>,
+}
+
+impl InputTransaction {
+    /// Start a transaction, clearing out anything left from an interrupted one
+    pub fn begin(component_dir: &Path) -> LalResult<Self> {
+        let input = component_dir.join("INPUT");
+        let staging = input.join(STAGING_DIR);
+        if staging.is_dir() {
+            debug!("Removing leftover {}", staging.display());
+            fs::remove_dir_all(&staging)?;
+        }
+        fs::create_dir_all(&staging)?;
+        Ok(InputTransaction {
+            input,
+            staging,
+            staged: vec![],
+            removed: vec![],
+        })
+    }
+
+    /// Component directory to unpack staged components into
+    ///
+    /// A component unpacked here ends up in `INPUT/.staging/INPUT/<name>`.
+    pub fn staging_dir(&self) -> &Path {
+        &self.staging
+    }
+
+    /// Record a component that was unpacked into the staging area
+    pub fn stage(&mut self, name: &str) {
+        self.staged.push(name.into());
+    }
+
+    /// Record a component to delete from `INPUT` on commit
+    pub fn remove(&mut self, name: &str) {
+        self.removed.push(name.into());
+    }
+
+    /// Swap every staged component into `INPUT` and delete the removed ones
+    ///
+    /// Previous and removed component directories (and their `.<name>.parts` records)
+    /// are moved aside before the staged ones are renamed into their place. If any
+    /// move fails, every earlier one is undone so `INPUT` is left as it was.
+    pub fn commit(self) -> LalResult<()> {
+        fs::create_dir_all(self.staging.join("previous"))?;
+        let mut moved = vec![];
+        if let Err(e) = self.apply(&mut moved) {
+            warn!("Failed to update INPUT ({}), rolling back", e);
+            for (from, to) in moved.iter().rev() {
+                if let Err(e) = fs::rename(to, from) {
+                    warn!("Failed to restore {} ({})", from.display(), e);
+                }
+            }
+            return Err(e.into());
+        }
+        Ok(())
+    }
+
+    // perform every move of the commit, recording the ones that succeeded
+    fn apply(&self, moved: &mut Vec<(PathBuf, PathBuf)>) -> io::Result<()> {
+        let staged = self.staging.join("INPUT");
+        let previous = self.staging.join("previous");
+        for name in self.staged.iter().chain(&self.removed) {
+            // the record of installed parts goes with the component
+            for entry in &[name.clone(), format!(".{}.parts", name)] {
+                let dest = self.input.join(entry);
+                if fs::symlink_metadata(&dest).is_ok() {
+                    debug!("Moving aside INPUT/{}", entry);
+                    move_entry(&dest, &previous.join(entry), moved)?;
+                }
+            }
+        }
+        for name in &self.staged {
+            debug!("Swapping in INPUT/{}", name);
+            move_entry(&staged.join(name), &self.input.join(name), moved)?;
+            let parts = format!(".{}.parts", name);
+            if staged.join(&parts).is_file() {
+                move_entry(&staged.join(&parts), &self.input.join(&parts), moved)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// rename a path, recording it so it can be undone
+fn move_entry(from: &Path, to: &Path, moved: &mut Vec<(PathBuf, PathBuf)>) -> io::Result<()> {
+    fs::rename(from, to)?;
+    moved.push((from.to_path_buf(), to.to_path_buf()));
+    Ok(())
+}
+
+impl Drop for InputTransaction {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_dir_all(&self.staging) {
+            warn!("Failed to clean up {} ({})", self.staging.display(), e);
+        }
+    }
+}
@@ -2,11 +2,13 @@ use std::{
     collections::BTreeMap,
     fs::{self, File},
     io::prelude::*,
-    path::{Path, PathBuf},
+    path::{Component, Path, PathBuf},
     vec::Vec,
 };
 
-use super::{CliError, LalResult};
+use serde::{ser::SerializeStruct, Deserialize, Deserializer, Serialize, Serializer};
+
+use super::{output::PackagingRules, CliError, Compression, LalResult, ResourceLimits};
 
 /// A startup helper used in a few places
 pub fn create_lal_subdir(pwd: &Path) -> LalResult<()> {
@@ -17,6 +19,101 @@ pub fn create_lal_subdir(pwd: &Path) -> LalResult<()> {
     Ok(())
 }
 
+/// Rules for which files in OUTPUT end up in the published tarballs
+///
+/// Globs are relative to OUTPUT, where `*` stays within a directory and `**` crosses them.
+/// The `lockfile.json` in OUTPUT is always packaged.
+#[allow(non_snake_case)]
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Packaging {
+    /// Files to package - everything if empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+    /// Files to leave out even if included
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+    /// Debug files to split out into a separate `<name>-debug` tarball
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub debug: Vec<String>,
+    /// Fail release builds whose tarball is larger than this (e.g. "2G")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub maxSize: Option<String>,
+    /// Named parts, each published as a separate `<name>-<part>` tarball of the files matching its globs
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub parts: BTreeMap<String, Vec<String>>,
+}
+
+impl Packaging {
+    /// Whether OUTPUT is packaged as is
+    pub fn packages_everything(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty() && self.debug.is_empty() && self.parts.is_empty()
+    }
+}
+
+/// A command run by `lal build` instead of a `BUILD` script
+#[derive(Serialize, Deserialize, Clone)]
+pub struct BuildStep {
+    /// Name to report the step by (the command if unset)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Shell command to run
+    pub run: String,
+    /// Directory to run the command in, relative to the component directory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workdir: Option<String>,
+    /// Extra environment variables for the command
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+}
+
+impl BuildStep {
+    /// Name to report the step by
+    pub fn display_name(&self) -> &str {
+        match self.name {
+            Some(ref n) => n,
+            None => &self.run,
+        }
+    }
+}
+
+/// A dependency as written in the manifest - either a plain version or a detailed object
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum DependencySpec {
+    Version(u32),
+    Detailed {
+        version: u32,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        parts: Vec<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        environment: Option<String>,
+    },
+}
+
+impl DependencySpec {
+    fn version(&self) -> u32 {
+        match *self {
+            DependencySpec::Version(v) | DependencySpec::Detailed { version: v, .. } => v,
+        }
+    }
+}
+
+// deserialize a dependency map, leaving the details to `DependencySpecs`
+fn dependency_versions<'de, D: Deserializer<'de>>(d: D) -> Result<BTreeMap<String, u32>, D::Error> {
+    let specs: BTreeMap<String, DependencySpec> = BTreeMap::deserialize(d)?;
+    Ok(specs.into_iter().map(|(k, v)| (k, v.version())).collect())
+}
+
+// Just the dependency maps of a manifest, to pick up the parts and environments requested
+#[allow(non_snake_case)]
+#[derive(Deserialize)]
+struct DependencySpecs {
+    #[serde(default)]
+    dependencies: BTreeMap<String, DependencySpec>,
+    #[serde(default)]
+    devDependencies: BTreeMap<String, DependencySpec>,
+}
+
 /// Representation of a value of the manifest.components hash
 #[allow(non_snake_case)]
 #[derive(Serialize, Deserialize, Clone)]
@@ -25,6 +122,18 @@ pub struct ComponentConfiguration {
     pub defaultConfig: String,
     /// List of allowed configurations (must contain defaultConfig)
     pub configurations: Vec<String>,
+    /// Compression of the published tarball (overrides the manifest default)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compression: Option<Compression>,
+    /// Rules for what goes into the published tarball (everything in OUTPUT if unset)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub packaging: Option<Packaging>,
+    /// Build steps per configuration, run instead of the `BUILD` script
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub build: BTreeMap<String, Vec<BuildStep>>,
+    /// Resource limits for builds (overrides the limits of the environment)
+    #[serde(default, skip_serializing_if = "ResourceLimits::is_empty")]
+    pub limits: ResourceLimits,
 }
 
 impl Default for ComponentConfiguration {
@@ -32,13 +141,17 @@ impl Default for ComponentConfiguration {
         ComponentConfiguration {
             configurations: vec!["release".to_string()],
             defaultConfig: "release".to_string(),
+            compression: None,
+            packaging: None,
+            build: BTreeMap::new(),
+            limits: ResourceLimits::default(),
         }
     }
 }
 
 /// Representation of `manifest.json`
 #[allow(non_snake_case)]
-#[derive(Serialize, Deserialize, Clone, Default)]
+#[derive(Deserialize, Clone, Default)]
 pub struct Manifest {
     /// Name of the main component
     pub name: String,
@@ -49,15 +162,67 @@ pub struct Manifest {
     /// Components and their available configurations that are buildable
     pub components: BTreeMap<String, ComponentConfiguration>,
     /// Dependencies that are always needed
+    #[serde(deserialize_with = "dependency_versions")]
     pub dependencies: BTreeMap<String, u32>,
     /// Development dependencies
+    #[serde(deserialize_with = "dependency_versions")]
     pub devDependencies: BTreeMap<String, u32>,
+    /// Compression of published tarballs (gzip if unset)
+    #[serde(default)]
+    pub compression: Option<Compression>,
+
+    /// Parts requested of (dev)dependencies - these are fetched whole if not listed
+    ///
+    /// Written as `"name": { "version": 42, "parts": ["runtime"] }` in the dependency maps.
+    #[serde(skip_deserializing)]
+    pub dependencyParts: BTreeMap<String, Vec<String>>,
+
+    /// Environments (dev)dependencies are fetched from when not the build environment
+    ///
+    /// Written as `"name": { "version": 42, "environment": "xenial" }` in the dependency maps.
+    #[serde(skip_deserializing)]
+    pub dependencyEnvironments: BTreeMap<String, String>,
 
     /// Internal path of this manifest
-    #[serde(skip_serializing, skip_deserializing)]
+    #[serde(skip_deserializing)]
     location: String,
 }
 
+/// Serialized by hand to fold `dependencyParts` and `dependencyEnvironments` back into the dependency maps
+impl Serialize for Manifest {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let specs = |deps: &BTreeMap<String, u32>| -> BTreeMap<String, DependencySpec> {
+            deps.iter()
+                .map(|(name, &version)| {
+                    let parts = self.dependencyParts.get(name).cloned().unwrap_or_default();
+                    let environment = self.dependencyEnvironments.get(name).cloned();
+                    let spec = if parts.is_empty() && environment.is_none() {
+                        DependencySpec::Version(version)
+                    } else {
+                        DependencySpec::Detailed {
+                            version,
+                            parts,
+                            environment,
+                        }
+                    };
+                    (name.clone(), spec)
+                })
+                .collect()
+        };
+        let mut s = serializer.serialize_struct("Manifest", 7)?;
+        s.serialize_field("name", &self.name)?;
+        s.serialize_field("environment", &self.environment)?;
+        s.serialize_field("supportedEnvironments", &self.supportedEnvironments)?;
+        s.serialize_field("components", &self.components)?;
+        s.serialize_field("dependencies", &specs(&self.dependencies))?;
+        s.serialize_field("devDependencies", &specs(&self.devDependencies))?;
+        if let Some(ref compression) = self.compression {
+            s.serialize_field("compression", compression)?;
+        }
+        s.end()
+    }
+}
+
 /// An enum to clarify intent
 pub enum ManifestLocation {
     /// Plain style (old default)
@@ -128,6 +293,35 @@ impl Manifest {
         deps
     }
 
+    /// Compression to use for the tarball of a component
+    ///
+    /// A compression set on the component takes precedence over the manifest's.
+    pub fn compression_for(&self, component: &str) -> Compression {
+        self.components
+            .get(component)
+            .and_then(|c| c.compression)
+            .or(self.compression)
+            .unwrap_or_default()
+    }
+
+    /// Packaging rules of a component
+    pub fn packaging_for(&self, component: &str) -> Packaging {
+        self.components
+            .get(component)
+            .and_then(|c| c.packaging.clone())
+            .unwrap_or_default()
+    }
+
+    /// Environment to fetch a dependency from
+    ///
+    /// This is the build environment unless the dependency declares its own.
+    pub fn environment_for<'a>(&'a self, dependency: &str, env: &'a str) -> &'a str {
+        self.dependencyEnvironments
+            .get(dependency)
+            .map(|e| e.as_str())
+            .unwrap_or(env)
+    }
+
     /// Read a manifest file in component dir
     pub fn read(component_dir: &Path) -> LalResult<Manifest> {
         Manifest::read_from(&component_dir.to_path_buf())
@@ -141,6 +335,20 @@ impl Manifest {
         let mut data = String::new();
         f.read_to_string(&mut data)?;
         let mut res: Manifest = serde_json::from_str(&data)?;
+        let specs: DependencySpecs = serde_json::from_str(&data)?;
+        for (name, spec) in specs.dependencies.into_iter().chain(specs.devDependencies) {
+            if let DependencySpec::Detailed {
+                parts, environment, ..
+            } = spec
+            {
+                if !parts.is_empty() {
+                    res.dependencyParts.insert(name.clone(), parts);
+                }
+                if let Some(env) = environment {
+                    res.dependencyEnvironments.insert(name, env);
+                }
+            }
+        }
         // store the location internally (not serialized to disk)
         res.location = mpath.to_string_lossy().into();
         Ok(res)
@@ -171,6 +379,27 @@ impl Manifest {
                 );
                 return Err(CliError::InvalidBuildConfiguration(ename));
             }
+            if let Some(ref packaging) = conf.packaging {
+                PackagingRules::new(packaging)?;
+            }
+            for (config, steps) in &conf.build {
+                if !conf.configurations.contains(config) {
+                    let ename = format!("build steps for '{}' not found in configurations list", config);
+                    return Err(CliError::InvalidBuildConfiguration(ename));
+                }
+                for step in steps {
+                    if step.run.trim().is_empty() {
+                        return Err(CliError::InvalidBuildStep(format!("empty command in {}", config)));
+                    }
+                    if let Some(ref dir) = step.workdir {
+                        let pth = Path::new(dir);
+                        if pth.is_absolute() || pth.components().any(|c| c == Component::ParentDir) {
+                            let reason = format!("workdir {} is outside the component", dir);
+                            return Err(CliError::InvalidBuildStep(reason));
+                        }
+                    }
+                }
+            }
         }
         for name in self.dependencies.keys() {
             if &name.to_lowercase() != name {
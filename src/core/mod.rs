@@ -1,12 +1,14 @@
 pub use self::{
-    config::{config_dir, Config, ConfigDefaults, Mount},
-    container::Container,
+    config::{config_dir, Config, ConfigDefaults, ExtractLimits, InputStrategy, Mount},
+    container::{Container, ResourceLimits, UserMapping},
     ensure::ensure_dir_exists_fresh,
     environment::Environment,
     errors::{CliError, LalResult},
     lockfile::Lockfile,
-    manifest::{ComponentConfiguration, Manifest, ManifestLocation},
+    manifest::{BuildStep, ComponentConfiguration, Manifest, ManifestLocation, Packaging},
+    output::Compression,
     sticky::StickyOptions,
+    util::{human_size, parse_size},
 };
 
 mod config;
@@ -16,6 +18,7 @@ mod environment;
 mod errors;
 mod lockfile;
 mod sticky;
+mod util;
 
 /// Manifest module can be used directly
 pub mod manifest;
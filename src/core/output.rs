@@ -1,16 +1,189 @@
-use flate2::{write::GzEncoder, Compression};
-use std::{fs::File, path::Path};
-use tar::Builder;
+use flate2::{read::GzDecoder, write::GzEncoder};
+use glob::{MatchOptions, Pattern};
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+};
+use tar::{Archive, Builder};
+use walkdir::WalkDir;
+use xz2::{read::XzDecoder, write::XzEncoder};
 
-use super::LalResult;
+use super::{CliError, LalResult, Packaging};
 
-/// Helper for stash and build
-pub fn tar(component_dir: &Path, tarball: &Path) -> LalResult<()> {
-    info!("Taring OUTPUT");
+/// Compression format of component tarballs
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum Compression {
+    /// `.tar.gz` - the historical format
+    #[serde(rename = "gzip")]
+    Gzip,
+    /// `.tar.zst` - fast to both compress and decompress
+    #[serde(rename = "zstd")]
+    Zstd,
+    /// `.tar.xz` - slow, but small
+    #[serde(rename = "xz")]
+    Xz,
+}
 
-    let tarball = File::create(tarball)?;
-    let compressor = GzEncoder::new(tarball, Compression::default());
-    let mut archive = Builder::new(compressor);
+impl Default for Compression {
+    fn default() -> Self {
+        Compression::Gzip
+    }
+}
+
+/// Formats in the order they are looked for when several tarballs could exist
+pub const FORMATS: [Compression; 3] = [Compression::Zstd, Compression::Xz, Compression::Gzip];
+
+impl Compression {
+    /// File extension of a tarball compressed with this format
+    pub fn extension(self) -> &'static str {
+        match self {
+            Compression::Gzip => "tar.gz",
+            Compression::Zstd => "tar.zst",
+            Compression::Xz => "tar.xz",
+        }
+    }
+
+    /// Name of the tarball of a component
+    pub fn tarball_name(self, name: &str) -> String {
+        format!("{}.{}", name, self.extension())
+    }
+
+    /// Infer the format from the name of a tarball (defaulting to gzip)
+    pub fn from_name(tarball: &str) -> Compression {
+        for c in &FORMATS {
+            if tarball.ends_with(&format!(".{}", c.extension())) {
+                return *c;
+            }
+        }
+        Compression::Gzip
+    }
+
+    /// Detect the format of a tarball from its magic bytes
+    pub fn detect(tarball: &Path) -> LalResult<Compression> {
+        let mut magic = [0; 6];
+        let mut f = File::open(tarball)?;
+        let n = f.read(&mut magic)?;
+        match &magic[..n] {
+            [0x1f, 0x8b, ..] => Ok(Compression::Gzip),
+            [0x28, 0xb5, 0x2f, 0xfd, ..] => Ok(Compression::Zstd),
+            [0xfd, b'7', b'z', b'X', b'Z', 0x00] => Ok(Compression::Xz),
+            _ => Err(CliError::UnknownCompression(tarball.display().to_string())),
+        }
+    }
+
+    /// Wrap a reader of compressed data in the matching decoder
+    pub fn decoder<'a, R: Read + 'a>(self, data: R) -> LalResult<Box<dyn Read + 'a>> {
+        Ok(match self {
+            Compression::Gzip => Box::new(GzDecoder::new(data)?),
+            Compression::Zstd => Box::new(zstd::Decoder::new(data)?),
+            Compression::Xz => Box::new(XzDecoder::new(data)),
+        })
+    }
+}
+
+/// Find the tarball of a component in a directory, whatever its compression
+pub fn find_tarball(dir: &Path, name: &str) -> Option<PathBuf> {
+    FORMATS
+        .iter()
+        .map(|c| dir.join(c.tarball_name(name)))
+        .find(|p| p.is_file())
+}
+
+/// Compiled packaging rules of a component
+pub struct PackagingRules {
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+    debug: Vec<Pattern>,
+    parts: BTreeMap<String, Vec<Pattern>>,
+}
+
+fn compile_globs(globs: &[String]) -> LalResult<Vec<Pattern>> {
+    globs
+        .iter()
+        .map(|g| Pattern::new(g).map_err(|e| CliError::InvalidPackagingRule(format!("'{}' - {}", g, e.msg))))
+        .collect()
+}
+
+impl PackagingRules {
+    /// Compile the globs of a component's packaging rules
+    pub fn new(packaging: &Packaging) -> LalResult<PackagingRules> {
+        let mut parts = BTreeMap::new();
+        for (part, globs) in &packaging.parts {
+            let valid = part
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+            if part.is_empty() || !valid {
+                let reason = "must be lowercase alphanumeric or dashes";
+                return Err(CliError::InvalidPackagingRule(format!(
+                    "part '{}' - {}",
+                    part, reason
+                )));
+            }
+            if part == "debug" && !packaging.debug.is_empty() {
+                let reason = "clashes with the debug tarball";
+                return Err(CliError::InvalidPackagingRule(format!(
+                    "part '{}' - {}",
+                    part, reason
+                )));
+            }
+            parts.insert(part.clone(), compile_globs(globs)?);
+        }
+        Ok(PackagingRules {
+            include: compile_globs(&packaging.include)?,
+            exclude: compile_globs(&packaging.exclude)?,
+            debug: compile_globs(&packaging.debug)?,
+            parts,
+        })
+    }
+
+    fn matches(patterns: &[Pattern], path: &Path) -> bool {
+        let opts = MatchOptions {
+            require_literal_separator: true,
+            ..MatchOptions::new()
+        };
+        patterns.iter().any(|p| p.matches_path_with(path, opts))
+    }
+
+    /// Where a file in OUTPUT goes - `None` if it is not packaged at all
+    pub fn destination(&self, path: &Path) -> Option<Tarball> {
+        if path == Path::new("lockfile.json") {
+            return Some(Tarball::Main); // needed by everything using the component
+        }
+        if Self::matches(&self.exclude, path)
+            || (!self.include.is_empty() && !Self::matches(&self.include, path))
+        {
+            None
+        } else if Self::matches(&self.debug, path) {
+            Some(Tarball::Debug)
+        } else {
+            Some(Tarball::Main)
+        }
+    }
+
+    /// The parts a packaged file in OUTPUT also goes into
+    pub fn parts_of(&self, path: &Path) -> Vec<&str> {
+        self.parts
+            .iter()
+            .filter(|(_, globs)| path == Path::new("lockfile.json") || Self::matches(globs, path))
+            .map(|(part, _)| part.as_str())
+            .collect()
+    }
+}
+
+/// The tarballs a component is packaged into
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Tarball {
+    /// `<name>.tar.*` - what gets fetched into INPUT
+    Main,
+    /// `<name>-debug.tar.*` - debug files split out by the packaging rules
+    Debug,
+}
+
+// tar up OUTPUT (or a selection of it) and hand back the writer so the compressor can be finished
+fn append_output<W: Write>(outdir: &Path, files: Option<&[PathBuf]>, writer: W) -> LalResult<W> {
+    let mut archive = Builder::new(writer);
 
     // Don't dereference symlinks, archive them as-is.
     // Dereferencing symlinks makes the archive larger, as we will then store two copies  of the
@@ -18,7 +191,145 @@ pub fn tar(component_dir: &Path, tarball: &Path) -> LalResult<()> {
     // will fail with a NotFound error.
     archive.follow_symlinks(false);
 
-    archive.append_dir_all(".", component_dir.join("OUTPUT"))?;
+    match files {
+        None => archive.append_dir_all(".", outdir)?,
+        Some(files) => {
+            for f in files {
+                archive.append_path_with_name(outdir.join(f), f)?;
+            }
+        }
+    }
+
+    Ok(archive.into_inner()?)
+}
+
+fn write_tarball(
+    outdir: &Path,
+    files: Option<&[PathBuf]>,
+    tarball: &Path,
+    compression: Compression,
+) -> LalResult<()> {
+    let tarball = File::create(tarball)?;
+    match compression {
+        Compression::Gzip => {
+            let compressor = GzEncoder::new(tarball, flate2::Compression::default());
+            append_output(outdir, files, compressor)?.finish()?;
+        }
+        Compression::Zstd => {
+            let compressor = zstd::Encoder::new(tarball, 0)?; // 0 is zstd's default level
+            append_output(outdir, files, compressor)?.finish()?;
+        }
+        Compression::Xz => {
+            let compressor = XzEncoder::new(tarball, 6);
+            append_output(outdir, files, compressor)?.finish()?;
+        }
+    }
+    Ok(())
+}
+
+/// Name of the tarball holding a named part of a component
+pub fn part_name(name: &str, part: &str) -> String {
+    format!("{}-{}", name, part)
+}
+
+/// Name of the tarball holding split out debug files of a component
+pub fn debug_name(name: &str) -> String {
+    part_name(name, "debug")
+}
+
+/// Tarballs of a component and its parts in a directory
+pub fn find_tarballs(dir: &Path, name: &str) -> LalResult<Vec<PathBuf>> {
+    let prefix = format!("{}-", name);
+    let mut tarballs = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let filename = path.file_name().unwrap().to_string_lossy().to_string();
+        let ext = format!(".{}", Compression::from_name(&filename).extension());
+        if filename.ends_with(&ext)
+            && (filename == format!("{}{}", name, ext) || filename.starts_with(&prefix))
+        {
+            tarballs.push(path);
+        }
+    }
+    tarballs.sort();
+    Ok(tarballs)
+}
 
+/// Check that a tarball can be read in full
+///
+/// This decompresses every entry, so truncated or corrupt tarballs are caught.
+pub fn verify_tarball(tarball: &Path) -> LalResult<()> {
+    let compression = Compression::detect(tarball)?;
+    let mut archive = Archive::new(compression.decoder(File::open(tarball)?)?);
+    for entry in archive.entries()? {
+        io::copy(&mut entry?, &mut io::sink())?;
+    }
     Ok(())
 }
+
+/// Helper for stash and build
+///
+/// Tars up OUTPUT into `<name>.tar.*` in `dest` following the component's packaging rules.
+/// Debug files split out by the rules go into `<name>-debug.tar.*` next to it,
+/// and every part gets a `<name>-<part>.tar.*` with its own files and the lockfile.
+/// Returns the path of the main tarball.
+pub fn tar(
+    component_dir: &Path,
+    dest: &Path,
+    name: &str,
+    compression: Compression,
+    packaging: &Packaging,
+) -> LalResult<PathBuf> {
+    info!("Taring OUTPUT");
+    let outdir = component_dir.join("OUTPUT");
+    let tarball = dest.join(compression.tarball_name(name));
+
+    if packaging.packages_everything() {
+        write_tarball(&outdir, None, &tarball, compression)?;
+        return Ok(tarball);
+    }
+
+    let rules = PackagingRules::new(packaging)?;
+    let mut main = vec![];
+    let mut debug = vec![];
+    let mut parts: BTreeMap<&str, Vec<PathBuf>> = BTreeMap::new();
+    for part in packaging.parts.keys() {
+        parts.insert(part, vec![]);
+    }
+    for entry in WalkDir::new(&outdir).min_depth(1) {
+        let entry = entry.map_err(|e| CliError::Io(e.into()))?;
+        if entry.file_type().is_dir() {
+            continue; // directories are created as needed on extraction
+        }
+        let path = entry.path().strip_prefix(&outdir).unwrap().to_path_buf();
+        let destination = match rules.destination(&path) {
+            Some(d) => d,
+            None => {
+                debug!("Not packaging {}", path.display());
+                continue;
+            }
+        };
+        for part in rules.parts_of(&path) {
+            parts.get_mut(part).unwrap().push(path.clone());
+        }
+        match destination {
+            Tarball::Main => main.push(path),
+            Tarball::Debug => debug.push(path),
+        }
+    }
+
+    write_tarball(&outdir, Some(&main), &tarball, compression)?;
+    if !debug.is_empty() {
+        info!("Splitting {} debug files into a separate tarball", debug.len());
+        let debug_tarball = dest.join(compression.tarball_name(&debug_name(name)));
+        write_tarball(&outdir, Some(&debug), &debug_tarball, compression)?;
+    }
+    for (part, files) in parts {
+        if files.len() <= 1 {
+            warn!("Part {} of {} only contains the lockfile", part, name);
+        }
+        let part_tarball = dest.join(compression.tarball_name(&part_name(name, part)));
+        write_tarball(&outdir, Some(&files), &part_tarball, compression)?;
+    }
+    Ok(tarball)
+}
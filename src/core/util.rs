@@ -0,0 +1,40 @@
+use super::{CliError, LalResult};
+
+/// Human readable size of a number of bytes
+pub fn human_size(bytes: u64) -> String {
+    let units = ["B", "kB", "MB", "GB", "TB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1000.0 && unit < units.len() - 1 {
+        size /= 1000.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, units[0])
+    } else {
+        format!("{:.1} {}", size, units[unit])
+    }
+}
+
+/// Parse a size like `20G`, `500M` or `1.5T` into bytes
+///
+/// Suffixes are decimal (K = 1000) and may optionally end in `B`.
+pub fn parse_size(size: &str) -> LalResult<u64> {
+    let invalid = || CliError::InvalidCacheSize(size.into());
+    let upper = size.trim().to_uppercase();
+    let digits = upper.trim_end_matches('B');
+    let (number, multiplier) = match digits.chars().last() {
+        Some('K') => (&digits[..digits.len() - 1], 1e3),
+        Some('M') => (&digits[..digits.len() - 1], 1e6),
+        Some('G') => (&digits[..digits.len() - 1], 1e9),
+        Some('T') => (&digits[..digits.len() - 1], 1e12),
+        Some(_) => (digits, 1.0),
+        None => return Err(invalid()),
+    };
+    let n = number.trim().parse::<f64>().map_err(|_| invalid())?;
+    // f64 parsing also accepts "inf" and "nan"
+    if !n.is_finite() || n < 0.0 {
+        return Err(invalid());
+    }
+    Ok((n * multiplier) as u64)
+}
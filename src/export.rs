@@ -1,6 +1,6 @@
 use std::{fs, path::Path};
 
-use super::{CliError, LalResult};
+use super::{CliError, Compression, LalResult};
 use crate::storage::CachedBackend;
 
 /// Export a specific component from the storage backend
@@ -36,7 +36,9 @@ pub fn export(backend: &dyn CachedBackend, comp: &str, output: &Path, _env: Opti
         backend.retrieve_published_component(comp, None, env)?.0
     };
 
-    let dest = output.join(format!("{}.tar.gz", component_name));
+    // keep the extension of the cached tarball as it tells the compression
+    let ext = Compression::from_name(&tarname.to_string_lossy()).extension();
+    let dest = output.join(format!("{}.{}", component_name, ext));
     debug!("Copying {:?} to {:?}", tarname, dest);
 
     fs::copy(tarname, dest)?;
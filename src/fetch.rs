@@ -1,42 +1,50 @@
-use std::{fs, path::Path};
+use std::{collections::BTreeMap, path::Path};
 
-use super::{CliError, LalResult, Lockfile, Manifest};
+use super::{
+    input::{self, InputTransaction},
+    plan::{Action, ComponentAction, Plan},
+    CliError, LalResult, Lockfile, Manifest,
+};
 use crate::storage::CachedBackend;
 
-fn clean_input(component_dir: &Path) {
-    let input = component_dir.join("./INPUT");
-    if input.is_dir() {
-        fs::remove_dir_all(&input).unwrap();
+// create the joined hashmap of dependencies and possibly devdependencies
+fn dependencies(manifest: &Manifest, core: bool) -> BTreeMap<String, u32> {
+    let mut deps = manifest.dependencies.clone();
+    if !core {
+        for (k, v) in &manifest.devDependencies {
+            deps.insert(k.clone(), *v);
+        }
     }
+    deps
 }
 
-/// Fetch all dependencies from `manifest.json`
+/// Unpack a published dependency into `INPUT`
 ///
-/// This will read, and HTTP GET all the dependencies at the specified versions.
-/// If the `core` bool is set, then `devDependencies` are not installed.
-pub fn fetch(
-    component_dir: &Path,
-    manifest: &Manifest,
+/// Only the `dependencyParts` the manifest lists for it are installed, if any.
+pub fn unpack_dependency(
     backend: &dyn CachedBackend,
-    core: bool,
+    manifest: &Manifest,
+    component_dir: &Path,
+    name: &str,
+    version: u32,
     env: &str,
 ) -> LalResult<()> {
+    match manifest.dependencyParts.get(name) {
+        Some(parts) => backend.unpack_published_parts(component_dir, name, Some(version), env, parts)?,
+        None => backend.unpack_published_component(component_dir, name, Some(version), env)?,
+    };
+    Ok(())
+}
+
+/// Work out what `fetch` would do to `INPUT` without changing anything
+///
+/// Components already in `INPUT` at the right version, environment and parts are reused,
+/// anything else in the manifest is downloaded, and anything not in it is removed.
+pub fn plan_fetch(component_dir: &Path, manifest: &Manifest, core: bool, env: &str) -> LalResult<Plan> {
     // first ensure manifest is sane:
     manifest.verify()?;
 
-    debug!(
-        "Installing dependencies{}",
-        if !core { " and devDependencies" } else { "" }
-    );
-
-    // create the joined hashmap of dependencies and possibly devdependencies
-    let mut deps = manifest.dependencies.clone();
-    if !core {
-        for (k, v) in &manifest.devDependencies {
-            deps.insert(k.clone(), *v);
-        }
-    }
-    let mut extraneous = vec![]; // stuff we should remove
+    let mut deps = dependencies(manifest, core);
 
     // figure out what we have already
     let lf = Lockfile::default()
@@ -48,6 +56,9 @@ pub fn fetch(
             warn!("Try to `rm -rf INPUT` and `lal fetch` again.");
             e
         })?;
+    let mut reused = vec![];
+    let mut extraneous = vec![]; // stuff we should remove
+    let mut previous = BTreeMap::new();
     // filter out what we already have (being careful to examine env)
     for (name, d) in lf.dependencies {
         // if d.name at d.version in d.envname matches something in deps
@@ -55,54 +66,107 @@ pub fn fetch(
             // version found in manifest
             // ignore non-integer versions (stashed things must be overwritten)
             if let Ok(n) = d.version.parse::<u32>() {
-                if n == cand && d.envname == env {
-                    info!("Reuse {} {} {}", env, name, n);
+                let dep_env = manifest.environment_for(&name, env);
+                let parts = manifest.dependencyParts.get(&name);
+                if n == cand && d.envname == dep_env && input::has_parts(component_dir, &name, parts) {
                     deps.remove(&name);
+                    reused.push(ComponentAction {
+                        name,
+                        action: Action::Reuse,
+                        version: Some(n.to_string()),
+                        environment: Some(dep_env.to_string()),
+                        previous: None,
+                    });
+                    continue;
                 }
             }
+            previous.insert(name, d.version);
         } else {
-            extraneous.push(name.clone());
+            extraneous.push(ComponentAction {
+                name,
+                action: Action::Remove,
+                version: None,
+                environment: None,
+                previous: Some(d.version),
+            });
         }
     }
 
-    let mut err = None;
+    let mut plan = Plan {
+        actions: reused,
+        ..Default::default()
+    };
     for (k, v) in deps {
-        info!("Fetch {} {} {}", env, k, v);
+        let dep_env = manifest.environment_for(&k, env).to_string();
+        let prev = previous.remove(&k);
+        plan.actions.push(ComponentAction {
+            name: k,
+            action: Action::Download,
+            version: Some(v.to_string()),
+            environment: Some(dep_env),
+            previous: prev,
+        });
+    }
+    plan.actions.extend(extraneous);
+    Ok(plan)
+}
 
-        // first kill the folders we actually need to fetch:
-        let cmponent_dir = component_dir.join("./INPUT").join(&k);
-        if cmponent_dir.is_dir() {
-            // Don't think this can fail, but we are dealing with NFS
-            fs::remove_dir_all(&cmponent_dir).map_err(|e| {
-                warn!("Failed to remove INPUT/{} - {}", k, e);
-                warn!("Please clean out your INPUT folder yourself to avoid corruption");
-                e
-            })?;
-        }
+/// Fetch all dependencies from `manifest.json`
+///
+/// This will read, and HTTP GET all the dependencies at the specified versions.
+/// If the `core` bool is set, then `devDependencies` are not installed.
+///
+/// Changes to `INPUT` are staged and only applied if every component was installed,
+/// so a failed fetch leaves the previous `INPUT` intact.
+pub fn fetch(
+    component_dir: &Path,
+    manifest: &Manifest,
+    backend: &dyn CachedBackend,
+    core: bool,
+    env: &str,
+) -> LalResult<()> {
+    debug!(
+        "Installing dependencies{}",
+        if !core { " and devDependencies" } else { "" }
+    );
+    let plan = plan_fetch(component_dir, manifest, core, env)?;
+    let deps = dependencies(manifest, core);
 
-        let _ = backend
-            .unpack_published_component(&component_dir, &k, Some(v), env)
-            .map_err(|e| {
-                warn!("Failed to completely install {} ({})", k, e);
-                // likely symlinks inside tarball that are being dodgy
-                // this is why we clean_input
-                err = Some(e);
-            });
-    }
+    // everything is unpacked into INPUT/.staging first, and only swapped in if all succeeded
+    let mut tx = InputTransaction::begin(component_dir)?;
+    let mut err = None;
+    for a in &plan.actions {
+        let k = &a.name;
+        match a.action {
+            Action::Reuse => info!("Reuse {} {} {}", a.environment.as_ref().unwrap(), k, deps[k]),
+            Action::Download => {
+                let dep_env = a.environment.as_ref().unwrap();
+                let v = deps[k];
+                info!("Fetch {} {} {}", dep_env, k, v);
 
-    // remove extraneous deps
-    for name in extraneous {
-        info!("Remove {}", name);
-        let pth = component_dir.join("./INPUT").join(&name);
-        if pth.is_dir() {
-            fs::remove_dir_all(&pth)?;
+                match unpack_dependency(backend, manifest, tx.staging_dir(), k, v, dep_env) {
+                    Ok(_) => tx.stage(k),
+                    Err(e) => {
+                        // likely a tarball rejected for escaping INPUT, or a broken download
+                        warn!("Failed to completely install {} ({})", k, e);
+                        err = Some(e);
+                    }
+                }
+            }
+            Action::Remove => {
+                // remove extraneous deps
+                info!("Remove {}", k);
+                tx.remove(k);
+            }
+            Action::Unstash => unreachable!("fetch does not unstash"),
         }
     }
 
     if err.is_some() {
-        warn!("Cleaning potentially broken INPUT");
-        clean_input(&component_dir); // don't want to risk having users in corrupted states
+        // dropping the transaction only discards what was staged
+        warn!("Leaving the previous INPUT untouched");
         return Err(CliError::InstallFailure);
     }
+    tx.commit()?;
     Ok(())
 }
@@ -0,0 +1,213 @@
+use std::{
+    fs::{self, File},
+    io::prelude::*,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use chrono::UTC;
+
+use super::{CliError, Environment, LalResult};
+
+/// A build done by `lal build`, stored as json next to its log in `.lal/logs`
+#[derive(Serialize, Deserialize, Clone)]
+pub struct BuildRecord {
+    /// When the build started
+    pub started: String,
+    /// Component built
+    pub component: String,
+    /// Configuration built
+    pub configuration: String,
+    /// Version built, if one was given
+    pub version: Option<String>,
+    /// Name of the environment built in
+    pub environment: String,
+    /// Container image built in (`None` when building natively)
+    pub image: Option<String>,
+    /// Time taken in seconds
+    pub duration: f64,
+    /// Exit code of the build (`None` if it failed before running anything)
+    pub exit_code: Option<i32>,
+    /// Error the build failed with
+    pub error: Option<String>,
+    /// Name of the log file in `.lal/logs`
+    pub log: String,
+}
+
+impl BuildRecord {
+    /// Whether the build succeeded
+    pub fn success(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Directory the logs and records of builds are kept in
+pub fn logs_dir(component_dir: &Path) -> PathBuf {
+    component_dir.join(".lal").join("logs")
+}
+
+// the exit code behind a build error, if it came from a process
+fn exit_code(res: &LalResult<()>) -> Option<i32> {
+    match *res {
+        Ok(()) => Some(0),
+        Err(CliError::SubprocessFailure(n))
+        | Err(CliError::BuildStepFailure(_, n))
+        | Err(CliError::HookFailure(_, n)) => Some(n),
+        Err(_) => None,
+    }
+}
+
+/// A build being recorded into `.lal/logs`
+pub struct BuildRecorder {
+    dir: PathBuf,
+    stem: String,
+    start: Instant,
+    record: BuildRecord,
+}
+
+impl BuildRecorder {
+    /// Start recording a build
+    pub fn start(
+        component_dir: &Path,
+        component: &str,
+        configuration: &str,
+        version: Option<String>,
+        envname: &str,
+        environment: &Environment,
+    ) -> LalResult<Self> {
+        let dir = logs_dir(component_dir);
+        fs::create_dir_all(&dir)?;
+        let now = UTC::now();
+        let stem = format!(
+            "{}-{}-{}",
+            now.format("%Y%m%dT%H%M%S%.3f"),
+            component,
+            configuration
+        );
+        let image = match *environment {
+            Environment::Container(ref c) => Some(c.to_string()),
+            Environment::None => None,
+        };
+        let record = BuildRecord {
+            started: now.format("%Y-%m-%d %H:%M:%S").to_string(),
+            component: component.into(),
+            configuration: configuration.into(),
+            version,
+            environment: envname.into(),
+            image,
+            duration: 0.0,
+            exit_code: None,
+            error: None,
+            log: format!("{}.log", stem),
+        };
+        Ok(BuildRecorder {
+            dir,
+            stem,
+            start: Instant::now(),
+            record,
+        })
+    }
+
+    /// File to write the output of the build to
+    pub fn log_file(&self) -> PathBuf {
+        self.dir.join(&self.record.log)
+    }
+
+    /// Stop recording and save the outcome of the build
+    pub fn finish(mut self, res: &LalResult<()>) -> LalResult<BuildRecord> {
+        self.record.duration = self.start.elapsed().as_secs_f64();
+        self.record.exit_code = exit_code(res);
+        self.record.error = res.as_ref().err().map(|e| e.to_string());
+
+        let encoded = serde_json::to_string_pretty(&self.record)?;
+        let mut f = File::create(self.dir.join(format!("{}.json", self.stem)))?;
+        writeln!(f, "{}", encoded)?;
+        debug!("Recorded build in {}", self.log_file().display());
+        Ok(self.record)
+    }
+}
+
+/// Read the records of past builds, oldest first
+pub fn read_history(component_dir: &Path) -> LalResult<Vec<BuildRecord>> {
+    let dir = logs_dir(component_dir);
+    if !dir.is_dir() {
+        return Ok(vec![]);
+    }
+    let mut paths = vec![];
+    for entry in fs::read_dir(&dir)? {
+        let pth = entry?.path();
+        if pth.extension().and_then(|e| e.to_str()) == Some("json") {
+            paths.push(pth);
+        }
+    }
+    // file names start with the time the build started
+    paths.sort();
+
+    let mut records = vec![];
+    for pth in paths {
+        let mut data = String::new();
+        File::open(&pth)?.read_to_string(&mut data)?;
+        match serde_json::from_str(&data) {
+            Ok(r) => records.push(r),
+            Err(e) => warn!("Ignoring unreadable build record {}: {}", pth.display(), e),
+        }
+    }
+    Ok(records)
+}
+
+/// Print the records of past builds, newest first
+pub fn history(component_dir: &Path, limit: Option<usize>, json: bool) -> LalResult<()> {
+    let mut records = read_history(component_dir)?;
+    records.reverse();
+    if let Some(n) = limit {
+        records.truncate(n);
+    }
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&records)?);
+        return Ok(());
+    }
+    println!(
+        "{:<20} {:<20} {:<10} {:<10} {:>8} {:>9}  RESULT",
+        "STARTED", "COMPONENT", "CONFIG", "ENV", "VERSION", "DURATION"
+    );
+    for r in &records {
+        let result = match (r.success(), r.exit_code) {
+            (true, _) => "ok".to_string(),
+            (false, Some(n)) => format!("failed ({})", n),
+            (false, None) => "failed".to_string(),
+        };
+        println!(
+            "{:<20} {:<20} {:<10} {:<10} {:>8} {:>8.1}s  {}",
+            r.started,
+            r.component,
+            r.configuration,
+            r.environment,
+            r.version.as_deref().unwrap_or("-"),
+            r.duration,
+            result
+        );
+    }
+    Ok(())
+}
+
+/// Print the output of the last build (or the last failed one)
+pub fn log(component_dir: &Path, failed: bool) -> LalResult<()> {
+    let record = read_history(component_dir)?
+        .into_iter()
+        .rev()
+        .find(|r| !failed || !r.success())
+        .ok_or(CliError::MissingBuildLog)?;
+
+    info!(
+        "Build of {} {} in {} at {} took {:.1}s",
+        record.component, record.configuration, record.environment, record.started, record.duration
+    );
+    if let Some(ref e) = record.error {
+        warn!("Build failed: {}", e);
+    }
+    let mut data = vec![];
+    File::open(logs_dir(component_dir).join(&record.log))?.read_to_end(&mut data)?;
+    std::io::stdout().write_all(&data)?;
+    Ok(())
+}
@@ -0,0 +1,147 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use super::{CliError, Config, DockerRunFlags, Environment, LalResult, Lockfile, ShellModes};
+use crate::shell;
+
+/// Points in lal commands where scripts in `.lal/hooks/` are run
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Hook {
+    /// Before the `BUILD` script of `lal build`
+    PreBuild,
+    /// After a successful `lal build`
+    PostBuild,
+    /// Before anything is uploaded by `lal publish`
+    PrePublish,
+    /// After a successful `lal publish`
+    PostPublish,
+    /// After a successful `lal fetch`
+    PostFetch,
+    /// After a successful `lal update` or `lal update-all`
+    PostUpdate,
+}
+
+impl fmt::Display for Hook {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match *self {
+            Hook::PreBuild => "pre-build",
+            Hook::PostBuild => "post-build",
+            Hook::PrePublish => "pre-publish",
+            Hook::PostPublish => "post-publish",
+            Hook::PostFetch => "post-fetch",
+            Hook::PostUpdate => "post-update",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// What a hook is run for, passed to the hook script as environment variables
+#[derive(Default)]
+pub struct HookContext {
+    /// Component being built, published or fetched for (`LAL_COMPONENT`)
+    pub component: String,
+    /// Configuration being built or published (`LAL_CONFIGURATION`)
+    pub configuration: Option<String>,
+    /// Version being built or published (`LAL_VERSION`)
+    pub version: Option<String>,
+    /// Name of the environment used (`LAL_ENV`)
+    pub env: String,
+}
+
+impl HookContext {
+    /// The context as `KEY=value` pairs for the environment of a command
+    pub(crate) fn env_vars(&self) -> Vec<String> {
+        let mut vars = vec![
+            format!("LAL_COMPONENT={}", self.component),
+            format!("LAL_ENV={}", self.env),
+        ];
+        if let Some(ref c) = self.configuration {
+            vars.push(format!("LAL_CONFIGURATION={}", c));
+        }
+        if let Some(ref v) = self.version {
+            vars.push(format!("LAL_VERSION={}", v));
+        }
+        vars
+    }
+}
+
+/// Location of the script for a hook
+pub fn hook_path(component_dir: &Path, hook: Hook) -> PathBuf {
+    component_dir.join(".lal").join("hooks").join(hook.to_string())
+}
+
+/// Run the script for a hook in `.lal/hooks/` if the component has one
+///
+/// The script is run through `shell::run` in `environment`, with the context in
+/// `LAL_COMPONENT`, `LAL_CONFIGURATION`, `LAL_VERSION` and `LAL_ENV`.
+/// A script that exits non-zero fails with `HookFailure`.
+pub fn run_hook(
+    cfg: &Config,
+    environment: &Environment,
+    hook: Hook,
+    ctx: &HookContext,
+    modes: &ShellModes,
+    component_dir: &Path,
+) -> LalResult<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let pth = hook_path(component_dir, hook);
+    if !pth.is_file() {
+        trace!("No {} hook in {}", hook, pth.display());
+        return Ok(());
+    }
+    let script = format!("./.lal/hooks/{}", hook);
+    if pth.metadata()?.permissions().mode() & 0o111 == 0 {
+        return Err(CliError::HookNotExecutable(script));
+    }
+
+    let mut modes = modes.clone();
+    modes.env_vars.extend(ctx.env_vars());
+    modes.component = Some(ctx.component.clone());
+    let flags = DockerRunFlags {
+        interactive: cfg.interactive,
+        privileged: false,
+    };
+    if !modes.printonly {
+        info!("Running {} hook", hook);
+    }
+    shell::run(cfg, environment, vec![script], &flags, &modes, component_dir).map_err(|e| match e {
+        CliError::SubprocessFailure(n) => CliError::HookFailure(hook.to_string(), n),
+        e => e,
+    })
+}
+
+/// Run the script for a hook once for each release build
+///
+/// The context and environment are taken from the lockfile in `ARTIFACT` of each
+/// release build directory (the component directory itself, or stages in `./MATRIX`).
+pub fn run_release_hook(
+    cfg: &Config,
+    hook: Hook,
+    releases: &[PathBuf],
+    component_dir: &Path,
+) -> LalResult<()> {
+    if !hook_path(component_dir, hook).is_file() {
+        return Ok(());
+    }
+    for release in releases {
+        let lock = Lockfile::release_build(release)?;
+        let ctx = HookContext {
+            component: lock.name,
+            configuration: Some(lock.config),
+            version: Some(lock.version),
+            env: lock.envname,
+        };
+        run_hook(
+            cfg,
+            &lock.environment,
+            hook,
+            &ctx,
+            &ShellModes::default(),
+            component_dir,
+        )?;
+    }
+    Ok(())
+}
@@ -0,0 +1,23 @@
+use super::{CliError, LalResult};
+use crate::storage::CachedBackend;
+
+/// Bring the local metadata index of the backend up to date
+///
+/// Only the lockfiles of versions published since the last refresh are fetched,
+/// so this is cheap to run regularly (e.g. from cron) to keep queries fast.
+pub fn refresh(backend: &dyn CachedBackend, environments: &[String]) -> LalResult<()> {
+    if environments.is_empty() {
+        return Err(CliError::EnvironmentUnspecified);
+    }
+    for env in environments {
+        let index = backend.refresh_index(env)?;
+        let versions: usize = index.components.values().map(|vs| vs.len()).sum();
+        info!(
+            "Indexed {} versions of {} components in {}",
+            versions,
+            index.components.len(),
+            env
+        );
+    }
+    Ok(())
+}
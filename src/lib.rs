@@ -37,8 +37,12 @@ pub use crate::runners::*;
 mod storage;
 pub use crate::storage::*;
 
+/// Cache module for cache subcommand (which has further subcommands)
+pub mod cache;
 /// Env module for env subcommand (which has further subcommands)
 pub mod env;
+/// Index module for index subcommand (which has further subcommands)
+pub mod index;
 /// List module for all the list-* subcommands
 pub mod list;
 /// Propagation module with all structs describing the steps
@@ -48,20 +52,27 @@ pub mod propagate;
 // lift most other pub functions into our libraries main scope
 // this avoids having to type lal:build in tests and main.rs
 pub use crate::{
-    build::{build, BuildOptions},
-    clean::clean,
+    build::{build, build_matrix, BuildOptions, MatrixOptions},
+    clean::{clean, clean_images, find_stale_images},
     configure::configure,
     export::export,
-    fetch::fetch,
+    fetch::{fetch, plan_fetch},
+    history::{history, log, logs_dir, read_history, BuildRecord},
+    hooks::{hook_path, run_hook, run_release_hook, Hook, HookContext},
     init::init,
-    publish::publish,
-    query::query,
-    remove::remove,
+    outdated::{find_outdated, outdated, DependencyStatus},
+    publish::{find_matrix_releases, next_version, publish, publish_matrix},
+    query::{query, query_entries, QueryEntry, QueryOptions},
+    rdeps::{find_reverse_dependencies, rdeps, ReverseDependency},
+    plan::{Action, ComponentAction, ManifestChange, Plan},
+    ps::{kill, ps},
+    remove::{plan_remove, remove},
     shell::{run, script, shell},
     stash::stash,
     status::status,
-    update::{update, update_all},
+    update::{plan_update, plan_update_all, update, update_all},
     verify::verify,
+    yank::yank,
 };
 
 mod build;
@@ -69,15 +80,22 @@ mod clean;
 mod configure;
 mod export;
 mod fetch;
+mod history;
+mod hooks;
 mod init;
+mod outdated;
+mod plan;
+mod ps;
 mod publish;
 mod query;
+mod rdeps;
 mod remove;
 mod shell;
 mod stash;
 mod status;
 mod update;
 mod verify;
+mod yank;
 
 #[cfg(feature = "upgrade")] pub use upgrade::upgrade;
 #[cfg(feature = "upgrade")] mod upgrade;
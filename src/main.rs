@@ -15,11 +15,22 @@ fn result_exit<T>(name: &str, x: LalResult<T>) {
     process::exit(0);
 }
 
-fn get_backend(config: &Config) -> Box<dyn CachedBackend> {
-    match config.backend {
-        BackendConfiguration::Artifactory(ref cfg) => Box::new(ArtifactoryBackend::new(&cfg, &config.cache)),
-        BackendConfiguration::Local(ref cfg) => Box::new(LocalBackend::new(&cfg, &config.cache)),
-    }
+fn get_backend(config: &Config) -> LalResult<Box<dyn CachedBackend>> {
+    let limits = config.extract_limits()?;
+    Ok(match config.backend {
+        BackendConfiguration::Artifactory(ref cfg) => Box::new(
+            ArtifactoryBackend::new(&cfg, &config.cache)
+                .with_input_strategy(config.input_strategy.clone())
+                .with_index_ttl(config.index_ttl)
+                .with_extract_limits(limits),
+        ),
+        BackendConfiguration::Local(ref cfg) => Box::new(
+            LocalBackend::new(&cfg, &config.cache)
+                .with_input_strategy(config.input_strategy.clone())
+                .with_index_ttl(config.index_ttl)
+                .with_extract_limits(limits),
+        ),
+    })
 }
 
 // functions that work without a manifest, and thus can run without a set env
@@ -34,14 +45,88 @@ fn handle_manifest_agnostic_cmds(
         let curdir = current_dir().unwrap();
         lal::export(backend, a.value_of("component").unwrap(), &curdir, explicit_env)
     } else if let Some(a) = args.subcommand_matches("query") {
-        lal::query(
+        let envs = if a.is_present("all-environments") {
+            cfg.environments.keys().cloned().collect()
+        } else {
+            explicit_env.map(String::from).into_iter().collect::<Vec<_>>()
+        };
+        let opts = QueryOptions {
+            latest: a.is_present("latest"),
+            details: a.is_present("details"),
+            json: a.is_present("json"),
+            since: a.value_of("since").map(String::from),
+            sha: a.value_of("sha").map(String::from),
+            depends_on: a.value_of("depends-on").map(String::from),
+        };
+        lal::query(backend, &envs, a.value_of("component").unwrap(), &opts)
+    } else if let Some(a) = args.subcommand_matches("yank") {
+        // yank everywhere it is published unless told otherwise
+        let envs = match explicit_env {
+            Some(e) => vec![e.to_string()],
+            None => cfg.environments.keys().cloned().collect(),
+        };
+        lal::yank(
+            None,
             backend,
-            explicit_env,
+            envs,
             a.value_of("component").unwrap(),
-            a.is_present("latest"),
+            a.value_of("reason"),
         )
+    } else if let Some(a) = args.subcommand_matches("rdeps") {
+        // search everywhere unless told otherwise
+        let envs: Vec<String> = match explicit_env {
+            Some(e) => vec![e.to_string()],
+            None => cfg.environments.keys().cloned().collect(),
+        };
+        let version = a.value_of("version").map(|v| v.parse().unwrap());
+        lal::rdeps(backend, &envs, a.value_of("component").unwrap(), version)
+    } else if let Some(a) = args.subcommand_matches("index") {
+        // index everything unless told otherwise
+        let envs: Vec<String> = match explicit_env {
+            Some(e) => vec![e.to_string()],
+            None => cfg.environments.keys().cloned().collect(),
+        };
+        if a.subcommand_matches("refresh").is_some() {
+            lal::index::refresh(backend, &envs)
+        } else {
+            unreachable!("index subcommand required");
+        }
     } else if let Some(a) = args.subcommand_matches("publish") {
-        lal::publish(None, &component_dir, a.value_of("component").unwrap(), backend)
+        let name = a.value_of("component").unwrap();
+        if a.is_present("matrix") {
+            // the matrix is published to every supported environment
+            Manifest::read(&component_dir).and_then(|mf| {
+                let releases = lal::find_matrix_releases(
+                    &component_dir,
+                    a.value_of("configuration"),
+                    &mf.supportedEnvironments,
+                )?
+                .into_iter()
+                .map(|(_, release)| release)
+                .collect::<Vec<_>>();
+                lal::run_release_hook(cfg, Hook::PrePublish, &releases, &component_dir)?;
+                lal::publish_matrix(
+                    None,
+                    &component_dir,
+                    name,
+                    a.value_of("configuration"),
+                    &mf.supportedEnvironments,
+                    a.is_present("force"),
+                    backend,
+                )?;
+                lal::run_release_hook(cfg, Hook::PostPublish, &releases, &component_dir)
+            })
+        } else {
+            let releases = vec![component_dir.to_path_buf()];
+            lal::run_release_hook(cfg, Hook::PrePublish, &releases, &component_dir)
+                .and_then(|_| lal::publish(None, &component_dir, name, a.is_present("force"), backend))
+                .and_then(|_| lal::run_release_hook(cfg, Hook::PostPublish, &releases, &component_dir))
+        }
+    } else if let Some(a) = args.subcommand_matches("log") {
+        lal::log(&component_dir, a.is_present("failed"))
+    } else if let Some(a) = args.subcommand_matches("history") {
+        let limit = a.value_of("limit").map(|n| n.parse().unwrap());
+        lal::history(&component_dir, limit, a.is_present("json"))
     } else if args.subcommand_matches("list-environments").is_some() {
         lal::list::environments(cfg)
     } else {
@@ -79,13 +164,18 @@ fn handle_environment_agnostic_cmds(
             .unwrap()
             .map(String::from)
             .collect::<Vec<_>>();
-        lal::remove(
-            &component_dir,
-            mf,
-            xs,
-            a.is_present("save"),
-            a.is_present("savedev"),
-        )
+        if a.is_present("dry-run") {
+            lal::plan_remove(&component_dir, mf, &xs, a.is_present("save"), a.is_present("savedev"))
+                .and_then(|p| p.print(a.is_present("json")))
+        } else {
+            lal::remove(
+                &component_dir,
+                mf,
+                xs,
+                a.is_present("save"),
+                a.is_present("savedev"),
+            )
+        }
     } else if let Some(a) = args.subcommand_matches("stash") {
         lal::stash(&component_dir, backend, mf, a.value_of("name").unwrap())
     } else if let Some(a) = args.subcommand_matches("propagate") {
@@ -101,12 +191,31 @@ fn handle_environment_agnostic_cmds(
     result_exit(args.subcommand_name().unwrap(), res);
 }
 
+// run a post hook of a command on the component in the manifest
+fn post_hook(
+    cfg: &Config,
+    environment: &Environment,
+    hook: Hook,
+    mf: &Manifest,
+    env: &str,
+    component_dir: &Path,
+) -> LalResult<()> {
+    let ctx = HookContext {
+        component: mf.name.clone(),
+        env: env.into(),
+        ..Default::default()
+    };
+    lal::run_hook(cfg, environment, hook, &ctx, &ShellModes::default(), component_dir)
+}
+
 fn handle_network_cmds(
     args: &ArgMatches<'_>,
     component_dir: &Path,
     mf: &Manifest,
+    cfg: &Config,
     backend: &dyn CachedBackend,
     env: &str,
+    environment: &Environment,
 ) {
     let res = if let Some(a) = args.subcommand_matches("update") {
         let xs = a
@@ -114,6 +223,21 @@ fn handle_network_cmds(
             .unwrap()
             .map(String::from)
             .collect::<Vec<_>>();
+        if a.is_present("dry-run") {
+            return result_exit(
+                "update",
+                lal::plan_update(
+                    &component_dir,
+                    mf,
+                    backend,
+                    &xs,
+                    a.is_present("save"),
+                    a.is_present("savedev"),
+                    env,
+                )
+                .and_then(|p| p.print(a.is_present("json"))),
+            );
+        }
         lal::update(
             &component_dir,
             mf,
@@ -123,7 +247,22 @@ fn handle_network_cmds(
             a.is_present("savedev"),
             env,
         )
+        .and_then(|_| post_hook(cfg, environment, Hook::PostUpdate, mf, env, &component_dir))
     } else if let Some(a) = args.subcommand_matches("update-all") {
+        if a.is_present("dry-run") {
+            return result_exit(
+                "update-all",
+                lal::plan_update_all(
+                    &component_dir,
+                    mf,
+                    backend,
+                    a.is_present("save"),
+                    a.is_present("dev"),
+                    env,
+                )
+                .and_then(|p| p.print(a.is_present("json"))),
+            );
+        }
         lal::update_all(
             &component_dir,
             mf,
@@ -132,11 +271,28 @@ fn handle_network_cmds(
             a.is_present("dev"),
             env,
         )
+        .and_then(|_| post_hook(cfg, environment, Hook::PostUpdate, mf, env, &component_dir))
     } else if let Some(a) = args.subcommand_matches("fetch") {
+        if a.is_present("dry-run") {
+            return result_exit(
+                "fetch",
+                lal::plan_fetch(&component_dir, mf, a.is_present("core"), env)
+                    .and_then(|p| p.print(a.is_present("json"))),
+            );
+        }
         lal::fetch(&component_dir, mf, backend, a.is_present("core"), env)
+            .and_then(|_| post_hook(cfg, environment, Hook::PostFetch, mf, env, &component_dir))
+    } else if let Some(a) = args.subcommand_matches("outdated") {
+        lal::outdated(mf, backend, env, a.is_present("json"))
     } else {
         return; // not a network cmnd
     };
+    // keep the cache within its configured limits now that it may have grown
+    if let (Ok(_), Some(max)) = (&res, &cfg.max_cache_size) {
+        let _ = lal::cache::prune(&cfg.cache, max).map_err(|e| {
+            warn!("Failed to prune cache to {}: {}", max, e);
+        });
+    }
     result_exit(args.subcommand_name().unwrap(), res)
 }
 
@@ -215,6 +371,7 @@ fn handle_docker_cmds(
     component_dir: &Path,
     mf: &Manifest,
     cfg: &Config,
+    backend: &dyn CachedBackend,
     env: &str,
     environment: &Environment,
 ) {
@@ -223,11 +380,21 @@ fn handle_docker_cmds(
         // the resolved env to verify consistent dependency usage
         lal::verify(&component_dir, mf, env, a.is_present("simple"))
     } else if let Some(a) = args.subcommand_matches("build") {
+        let version = if a.is_present("auto-version") {
+            // claim the version before building so every lockfile gets it
+            let name = a.value_of("component").unwrap_or(&mf.name);
+            match lal::next_version(None, backend, name, &mf.supportedEnvironments) {
+                Ok(v) => Some(v.to_string()),
+                Err(e) => return result_exit("build", Err::<(), _>(e)),
+            }
+        } else {
+            a.value_of("with-version").map(String::from)
+        };
         let bopts = BuildOptions {
             name: a.value_of("component").map(String::from),
             configuration: a.value_of("configuration").map(String::from),
             release: a.is_present("release"),
-            version: a.value_of("with-version").map(String::from),
+            version,
             sha: a.value_of("with-sha").map(String::from),
             environment: environment.clone(),
             force: a.is_present("force"),
@@ -238,8 +405,18 @@ fn handle_docker_cmds(
             x11_forwarding: a.is_present("x11"),
             host_networking: a.is_present("net-host"),
             env_vars: values_t!(a.values_of("env-var"), String).unwrap_or_default(),
+            component: Some(mf.name.clone()),
+            ..Default::default()
         };
-        lal::build(&component_dir, cfg, mf, &bopts, env.into(), modes)
+        if a.is_present("matrix") {
+            let matrix = MatrixOptions {
+                environments: values_t!(a.values_of("matrix-env"), String).unwrap_or_default(),
+                configurations: values_t!(a.values_of("matrix-config"), String).unwrap_or_default(),
+            };
+            lal::build_matrix(&component_dir, cfg, mf, backend, &bopts, &matrix, modes)
+        } else {
+            lal::build(&component_dir, cfg, mf, &bopts, env.into(), modes)
+        }
     } else if let Some(a) = args.subcommand_matches("shell") {
         let xs = if a.is_present("cmd") {
             Some(a.values_of("cmd").unwrap().collect::<Vec<_>>())
@@ -251,6 +428,8 @@ fn handle_docker_cmds(
             x11_forwarding: a.is_present("x11"),
             host_networking: a.is_present("net-host"),
             env_vars: values_t!(a.values_of("env-var"), String).unwrap_or_default(),
+            component: Some(mf.name.clone()),
+            ..Default::default()
         };
         lal::shell(
             cfg,
@@ -271,6 +450,8 @@ fn handle_docker_cmds(
             x11_forwarding: a.is_present("x11"),
             host_networking: a.is_present("net-host"),
             env_vars: values_t!(a.values_of("env-var"), String).unwrap_or_default(),
+            component: Some(mf.name.clone()),
+            ..Default::default()
         };
         lal::script(
             cfg,
@@ -322,7 +503,12 @@ fn main() {
         .unwrap();
 
     // Create a storage backend (something that implements storage/traits.rs)
-    let backend: Box<dyn CachedBackend> = get_backend(&config);
+    let backend: Box<dyn CachedBackend> = get_backend(&config)
+        .map_err(|e| {
+            error!("Configuration error: {}", e);
+            process::exit(1);
+        })
+        .unwrap();
 
     // Ensure SSL is initialized before using the backend
     openssl_probe::init_ssl_cert_env_vars();
@@ -332,7 +518,7 @@ fn main() {
     handle_upgrade(&args, &config);
 
     let component_dir = current_dir().unwrap();
-    // Allow lal init / clean without manifest existing in PWD
+    // Allow lal init / clean / ps / kill without manifest existing in PWD
     if let Some(a) = args.subcommand_matches("init") {
         result_exit(
             "init",
@@ -344,8 +530,27 @@ fn main() {
             ),
         );
     } else if let Some(a) = args.subcommand_matches("clean") {
+        if a.is_present("images") {
+            result_exit("clean", lal::clean_images(&config, a.is_present("dry-run")));
+        }
         let days = a.value_of("days").unwrap().parse().unwrap();
         result_exit("clean", lal::clean(&config.cache, days));
+    } else if args.subcommand_matches("ps").is_some() {
+        result_exit("ps", lal::ps());
+    } else if let Some(a) = args.subcommand_matches("kill") {
+        let names: Vec<&str> = a.values_of("containers").map(|v| v.collect()).unwrap_or_default();
+        result_exit("kill", lal::kill(&names, a.is_present("all")));
+    } else if let Some(a) = args.subcommand_matches("cache") {
+        let res = if a.subcommand_matches("ls").is_some() {
+            lal::cache::ls(&config.cache)
+        } else if a.subcommand_matches("du").is_some() {
+            lal::cache::du(&config.cache)
+        } else if let Some(sa) = a.subcommand_matches("prune") {
+            lal::cache::prune(&config.cache, sa.value_of("max-size").unwrap())
+        } else {
+            unreachable!("cache subcommand required");
+        };
+        result_exit("cache", res);
     }
 
     // Read .lal/opts if it exists
@@ -408,8 +613,24 @@ fn main() {
     }
 
     // Main subcommands
-    handle_network_cmds(&args, &component_dir, &manifest, backend.deref(), &env);
-    handle_docker_cmds(&args, &component_dir, &manifest, &config, &env, &environment);
+    handle_network_cmds(
+        &args,
+        &component_dir,
+        &manifest,
+        &config,
+        backend.deref(),
+        &env,
+        &environment,
+    );
+    handle_docker_cmds(
+        &args,
+        &component_dir,
+        &manifest,
+        &config,
+        backend.deref(),
+        &env,
+        &environment,
+    );
 
     unreachable!("Subcommand valid, but not implemented");
 }
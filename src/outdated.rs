@@ -0,0 +1,181 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::{CliError, LalResult, Lockfile, Manifest};
+use crate::storage::CachedBackend;
+
+/// A manifest dependency compared against the newest available version
+#[derive(Serialize)]
+pub struct DependencyStatus {
+    /// Name of the dependency
+    pub name: String,
+    /// Version in the manifest
+    pub current: u32,
+    /// Newest version available in all supported environments
+    pub latest: u32,
+    /// Whether this is a devDependency
+    pub dev: bool,
+    /// Whether upgrading to `latest` keeps the tree consistent (`None` if unknown)
+    pub consistent: Option<bool>,
+    /// Components with multiple versions in the tree after upgrading
+    pub conflicts: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl DependencyStatus {
+    /// Whether a newer version is available
+    pub fn is_outdated(&self) -> bool {
+        self.latest > self.current
+    }
+}
+
+// all versions used of every component in a set of trees (roots included)
+fn tree_versions<'a, I>(locks: I) -> BTreeMap<String, BTreeSet<String>>
+where
+    I: Iterator<Item = &'a Lockfile>,
+{
+    let mut acc: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    for lock in locks {
+        acc.entry(lock.name.clone())
+            .or_default()
+            .insert(lock.version.clone());
+        for (name, versions) in lock.find_all_dependency_versions() {
+            acc.entry(name).or_default().extend(versions);
+        }
+    }
+    acc
+}
+
+// conflicts introduced by replacing a dependency with a candidate tree
+fn find_conflicts(
+    candidate: &Lockfile,
+    current: &BTreeMap<String, Lockfile>,
+    deps: &BTreeMap<String, u32>,
+) -> BTreeMap<String, BTreeSet<String>> {
+    let others = current.values().filter(|l| l.name != candidate.name);
+    let mut versions = tree_versions(others.chain(Some(candidate)));
+    // dependencies without a published lockfile still pin their own version
+    for (name, v) in deps.iter().filter(|&(name, _)| name != &candidate.name) {
+        versions.entry(name.clone()).or_default().insert(v.to_string());
+    }
+    let touched = tree_versions(Some(candidate).into_iter());
+    versions
+        .into_iter()
+        .filter(|(name, vs)| vs.len() > 1 && touched.contains_key(name))
+        .collect()
+}
+
+/// Compare every manifest dependency against the newest version in all supported environments
+///
+/// The newest version is found with `get_latest_supported_versions` in the
+/// `supportedEnvironments` of the manifest (or the environment the dependency declares).
+/// Upgrading is consistent if the published lockfile of the newest version does not
+/// introduce a second version of anything in the tree of the current dependencies.
+pub fn find_outdated(
+    manifest: &Manifest,
+    backend: &dyn CachedBackend,
+    env: &str,
+) -> LalResult<Vec<DependencyStatus>> {
+    let deps = manifest.all_dependencies();
+
+    // the current tree, as published
+    let mut current = BTreeMap::new();
+    for (name, &version) in &deps {
+        let dep_env = manifest.environment_for(name, env);
+        match backend.get_published_lockfile(name, version, dep_env) {
+            Ok(lock) => {
+                current.insert(name.clone(), lock);
+            }
+            Err(e) => warn!("No lockfile for {}={} in {}: {}", name, version, dep_env, e),
+        }
+    }
+
+    let mut res = vec![];
+    for (name, &version) in &deps {
+        let environments = if manifest.dependencyEnvironments.contains_key(name) {
+            vec![manifest.environment_for(name, env).to_string()]
+        } else if manifest.supportedEnvironments.is_empty() {
+            vec![env.to_string()]
+        } else {
+            manifest.supportedEnvironments.clone()
+        };
+        let latest = backend
+            .get_latest_supported_versions(name, environments)?
+            .into_iter()
+            .max()
+            .ok_or_else(|| CliError::NoIntersectedVersion(name.clone()))?;
+
+        let mut status = DependencyStatus {
+            name: name.clone(),
+            current: version,
+            latest,
+            dev: !manifest.dependencies.contains_key(name),
+            consistent: Some(true),
+            conflicts: BTreeMap::new(),
+        };
+        if status.is_outdated() {
+            let dep_env = manifest.environment_for(name, env);
+            match backend.get_published_lockfile(name, latest, dep_env) {
+                Ok(candidate) => {
+                    status.conflicts = find_conflicts(&candidate, &current, &deps);
+                    status.consistent = Some(status.conflicts.is_empty());
+                }
+                Err(e) => {
+                    warn!("No lockfile for {}={} in {}: {}", name, latest, dep_env, e);
+                    status.consistent = None;
+                }
+            }
+        }
+        res.push(status);
+    }
+    Ok(res)
+}
+
+/// Print how far behind the manifest dependencies are
+///
+/// Fails with `OutdatedDependencies` if any dependency has a newer version,
+/// so it can be used to nag in CI.
+pub fn outdated(manifest: &Manifest, backend: &dyn CachedBackend, env: &str, json: bool) -> LalResult<()> {
+    let statuses = find_outdated(manifest, backend, env)?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&statuses)?);
+    } else {
+        println!("{:<30} {:>8} {:>8}  UPGRADE", "DEPENDENCY", "CURRENT", "LATEST");
+        for s in &statuses {
+            let upgrade = if !s.is_outdated() {
+                "up to date".to_string()
+            } else {
+                match s.consistent {
+                    Some(true) => "consistent".to_string(),
+                    Some(false) => {
+                        let conflicts: Vec<String> = s
+                            .conflicts
+                            .iter()
+                            .map(|(name, vs)| {
+                                let vs: Vec<&str> = vs.iter().map(String::as_str).collect();
+                                format!("{}={}", name, vs.join("|"))
+                            })
+                            .collect();
+                        format!("conflicts on {}", conflicts.join(", "))
+                    }
+                    None => "unknown (no published lockfile)".to_string(),
+                }
+            };
+            let name = if s.dev {
+                format!("{} (dev)", s.name)
+            } else {
+                s.name.clone()
+            };
+            println!("{:<30} {:>8} {:>8}  {}", name, s.current, s.latest, upgrade);
+        }
+    }
+
+    let behind: Vec<String> = statuses
+        .into_iter()
+        .filter(|s| s.is_outdated())
+        .map(|s| s.name)
+        .collect();
+    if !behind.is_empty() {
+        return Err(CliError::OutdatedDependencies(behind));
+    }
+    Ok(())
+}
@@ -0,0 +1,160 @@
+use std::{cmp::Ordering, fmt};
+
+use super::{LalResult, Manifest};
+
+/// What happens to a component in `INPUT`
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    /// Fetched from the backend (or the cache) into `INPUT`
+    #[serde(rename = "download")]
+    Download,
+    /// Already in `INPUT` at the right version and environment
+    #[serde(rename = "reuse")]
+    Reuse,
+    /// Unpacked from a stashed build into `INPUT`
+    #[serde(rename = "unstash")]
+    Unstash,
+    /// Deleted from `INPUT`
+    #[serde(rename = "remove")]
+    Remove,
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match *self {
+            Action::Download => "download",
+            Action::Reuse => "reuse",
+            Action::Unstash => "unstash",
+            Action::Remove => "remove",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// A planned change to a component in `INPUT`
+#[derive(Serialize)]
+pub struct ComponentAction {
+    /// Name of the component
+    pub name: String,
+    /// What happens to it
+    pub action: Action,
+    /// Version (or stash name) it ends up at
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// Environment it is fetched from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub environment: Option<String>,
+    /// Version currently in `INPUT`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub previous: Option<String>,
+}
+
+/// A planned change to a dependency in the manifest
+#[derive(Serialize)]
+pub struct ManifestChange {
+    /// Name of the dependency
+    pub name: String,
+    /// Whether it is in `devDependencies` rather than `dependencies`
+    pub dev: bool,
+    /// Version before the change (`None` if added)
+    pub from: Option<u32>,
+    /// Version after the change (`None` if removed)
+    pub to: Option<u32>,
+}
+
+impl ManifestChange {
+    /// Apply the change to a manifest
+    pub fn apply(&self, manifest: &mut Manifest) {
+        let deps = if self.dev {
+            &mut manifest.devDependencies
+        } else {
+            &mut manifest.dependencies
+        };
+        match self.to {
+            Some(v) => {
+                deps.insert(self.name.clone(), v);
+            }
+            None => {
+                deps.remove(&self.name);
+                // parts and environment overrides are shared by both dependency maps
+                if !manifest.dependencies.contains_key(&self.name)
+                    && !manifest.devDependencies.contains_key(&self.name)
+                {
+                    manifest.dependencyParts.remove(&self.name);
+                    manifest.dependencyEnvironments.remove(&self.name);
+                }
+            }
+        }
+    }
+}
+
+// `upgrade` / `downgrade` marker for two versions if they are both integers
+fn version_marker(from: &str, to: &str) -> &'static str {
+    match (from.parse::<u32>(), to.parse::<u32>()) {
+        (Ok(f), Ok(t)) => match t.cmp(&f) {
+            Ordering::Greater => " (upgrade)",
+            Ordering::Less => " (downgrade)",
+            Ordering::Equal => "",
+        },
+        _ => "",
+    }
+}
+
+/// Changes to `INPUT` and the manifest that a command would make
+///
+/// Produced by the `plan_*` functions, which only read state, so that
+/// `--dry-run` can show what would happen without changing anything.
+#[derive(Serialize, Default)]
+pub struct Plan {
+    /// What happens to each component in `INPUT`
+    pub actions: Vec<ComponentAction>,
+    /// Changes to the dependencies in the manifest
+    pub manifest: Vec<ManifestChange>,
+}
+
+impl Plan {
+    /// Print the plan as text or json
+    pub fn print(&self, json: bool) -> LalResult<()> {
+        if json {
+            println!("{}", serde_json::to_string_pretty(self)?);
+            return Ok(());
+        }
+        for a in &self.actions {
+            let mut line = format!("{:<8} {}", a.action, a.name);
+            if let Some(ref v) = a.version {
+                line.push_str(&format!("={}", v));
+            }
+            if let Some(ref env) = a.environment {
+                line.push_str(&format!(" ({})", env));
+            }
+            match (&a.previous, &a.version) {
+                (Some(prev), Some(v)) if prev != v => {
+                    line.push_str(&format!(" from {}{}", prev, version_marker(prev, v)))
+                }
+                (Some(prev), None) => line.push_str(&format!(" at {}", prev)),
+                _ => {}
+            }
+            println!("{}", line);
+        }
+        if !self.manifest.is_empty() {
+            println!("manifest.json:");
+        }
+        for c in &self.manifest {
+            let key = if c.dev { "devDependencies" } else { "dependencies" };
+            match (c.from, c.to) {
+                (Some(f), Some(t)) => println!(
+                    "  {}.{}: {} -> {}{}",
+                    key,
+                    c.name,
+                    f,
+                    t,
+                    version_marker(&f.to_string(), &t.to_string())
+                ),
+                (None, Some(t)) => println!("+ {}.{}: {}", key, c.name, t),
+                (Some(f), None) => println!("- {}.{}: {}", key, c.name, f),
+                (None, None) => {}
+            }
+        }
+        Ok(())
+    }
+}
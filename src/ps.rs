@@ -0,0 +1,59 @@
+use super::{kill_docker_container, list_lal_containers, CliError, ContainerStatus, LalContainer, LalResult};
+
+/// List the running containers started by lal on this host
+///
+/// Containers whose lal process is gone are marked as orphaned. Those started from
+/// another host or pid namespace (e.g. other CI agents sharing the docker daemon)
+/// cannot be told apart and are marked as unknown.
+pub fn ps() -> LalResult<()> {
+    let containers = list_lal_containers()?;
+    println!(
+        "{:<40} {:<20} {:<12} {:>8} {:<20}  STATUS",
+        "NAME", "COMPONENT", "USER", "PID", "RUNNING FOR"
+    );
+    for c in &containers {
+        println!(
+            "{:<40} {:<20} {:<12} {:>8} {:<20}  {}",
+            c.name,
+            c.component,
+            c.user,
+            c.pid,
+            c.running_for,
+            c.status()
+        );
+    }
+    Ok(())
+}
+
+/// Stop containers started by lal
+///
+/// Stops the named containers, or with no names, your orphaned containers
+/// (or all of your containers with `all` set). Without names, only containers
+/// started from this host and pid namespace are stopped.
+pub fn kill(names: &[&str], all: bool) -> LalResult<()> {
+    let containers = list_lal_containers()?;
+    let targets: Vec<&LalContainer> = if names.is_empty() {
+        containers
+            .iter()
+            .filter(|c| c.is_own() && (all || c.status() == ContainerStatus::Orphaned))
+            .collect()
+    } else {
+        let mut targets = vec![];
+        for name in names {
+            match containers.iter().find(|c| c.name == *name) {
+                Some(c) => targets.push(c),
+                None => return Err(CliError::UnknownContainer(name.to_string())),
+            }
+        }
+        targets
+    };
+
+    if targets.is_empty() {
+        info!("No lal containers to stop");
+    }
+    for c in targets {
+        info!("Stopping {} ({})", c.name, c.component);
+        kill_docker_container(&c.name)?;
+    }
+    Ok(())
+}
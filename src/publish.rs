@@ -1,23 +1,58 @@
-use std::path::Path;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
 
 // Need both the struct and the trait
-use super::{CliError, LalResult, Lockfile};
+use super::{output, CliError, LalResult, Lockfile};
 use crate::storage::CachedBackend;
 
-/// Publish a release build to the storage backend
-///
-/// Meant to be done after a `lal build -r <component>`
-/// and requires publish credentials in the local `Config`.
-pub fn publish(
-    home: Option<&Path>,
-    component_dir: &Path,
-    name: &str,
-    backend: &dyn CachedBackend,
-) -> LalResult<()> {
+// every version of a component in an environment - including yanked ones
+fn published_versions(backend: &dyn CachedBackend, name: &str, env: &str) -> LalResult<Vec<u32>> {
+    match backend.get_all_versions(name, env) {
+        Ok((versions, _)) => Ok(versions),
+        // components that were never published have no versions to list
+        Err(CliError::BackendNotFound(_)) => Ok(vec![]),
+        Err(e) => Err(e),
+    }
+}
+
+// whether a version of a component is already in an environment
+fn is_published(backend: &dyn CachedBackend, name: &str, version: u32, env: &str) -> LalResult<bool> {
+    Ok(published_versions(backend, name, env)?.contains(&version))
+}
+
+// find dependencies in a lockfile tree that are not published versions
+fn find_unpublished(lock: &Lockfile, acc: &mut Vec<String>) {
+    for (name, dep) in &lock.dependencies {
+        if dep.version.parse::<u32>().is_err() {
+            acc.push(format!("{}={}", name, dep.version));
+        }
+        find_unpublished(dep, acc);
+    }
+}
+
+// find dependencies used at more than one version in a lockfile tree
+fn find_multiple_versions(lock: &Lockfile) -> Vec<String> {
+    let mut names: Vec<String> = lock
+        .find_all_dependency_versions()
+        .into_iter()
+        .filter(|(_, versions)| versions.len() > 1)
+        .map(|(name, _)| name)
+        .collect();
+    names.sort();
+    names
+}
+
+// check a release build in `component_dir` before any of it is uploaded
+//
+// Its lockfile must be for `name`, only use published dependencies at a single version
+// each (the parts of a strict verify that the lockfile alone can tell), and the tarballs
+// must be readable. Returns the lockfile and the version it was built as.
+fn validate_release(component_dir: &Path, name: &str) -> LalResult<(Lockfile, u32)> {
     let artdir = component_dir.join("./ARTIFACT");
-    let tarball = artdir.join(format!("{}.tar.gz", name));
-    if !artdir.is_dir() || !tarball.exists() {
-        warn!("Missing: {}", tarball.display());
+    if output::find_tarball(&artdir, name).is_none() {
+        warn!("Missing: {}", artdir.join(format!("{}.tar.*", name)).display());
         return Err(CliError::MissingReleaseBuild);
     }
 
@@ -33,11 +68,260 @@ pub fn publish(
         warn!("Release build not done --with-sha=$(git rev-parse HEAD)");
     }
 
+    if lock.name != name {
+        let reason = format!("ARTIFACT contains a build of {} and not {}", lock.name, name);
+        return Err(CliError::InvalidReleaseBuild(reason));
+    }
+    let mut unpublished = vec![];
+    find_unpublished(&lock, &mut unpublished);
+    if !unpublished.is_empty() {
+        let reason = format!("built with unpublished dependencies {}", unpublished.join(", "));
+        return Err(CliError::InvalidReleaseBuild(reason));
+    }
+    let multiple = find_multiple_versions(&lock);
+    if !multiple.is_empty() {
+        let reason = format!("built with multiple versions of {}", multiple.join(", "));
+        return Err(CliError::InvalidReleaseBuild(reason));
+    }
+    for tarball in output::find_tarballs(&artdir, name)? {
+        debug!("Verifying {}", tarball.display());
+        output::verify_tarball(&tarball).map_err(|e| {
+            let reason = format!("unreadable {} ({})", tarball.display(), e);
+            CliError::InvalidReleaseBuild(reason)
+        })?;
+    }
+    Ok((lock, version))
+}
+
+/// Publish a release build to the storage backend
+///
+/// Meant to be done after a `lal build -r <component>`
+/// and requires publish credentials in the local `Config`.
+///
+/// The release build is checked before anything is uploaded: its lockfile must be
+/// for `name`, only use published dependencies at a single version each (the parts
+/// of a strict verify that the lockfile alone can tell), and the tarballs must be
+/// readable. Environment consistency needs the manifest, and is left to the verify
+/// done by the release build.
+/// An already published version is never overwritten unless `force` is set, and
+/// failing to list the published versions fails the publish.
+pub fn publish(
+    home: Option<&Path>,
+    component_dir: &Path,
+    name: &str,
+    force: bool,
+    backend: &dyn CachedBackend,
+) -> LalResult<()> {
+    let (lock, version) = validate_release(component_dir, name)?;
+
     // always publish to the environment in the lockfile
     let envname = lock.envname;
 
+    // never clobber released artifacts by accident
+    if is_published(backend, name, version, &envname)? {
+        if !force {
+            return Err(CliError::VersionAlreadyPublished(name.into(), version, envname));
+        }
+        warn!("Overwriting {}={} in {}", name, version, envname);
+    }
+
     info!("Publishing {}={} to {}", name, version, envname);
     backend.publish_artifact(home, &component_dir, name, version, &envname)?;
 
     Ok(())
 }
+
+/// Allocate the next version of a component across environments
+///
+/// This is one higher than the latest version published in any of `environments`,
+/// and is reserved on the backend so that concurrent publishers get different versions.
+/// Nothing is reserved if the versions in any environment cannot be listed.
+pub fn next_version(
+    home: Option<&Path>,
+    backend: &dyn CachedBackend,
+    name: &str,
+    environments: &[String],
+) -> LalResult<u32> {
+    let mut latest = 0;
+    for env in environments {
+        // yanked versions are never handed out again
+        if let Some(v) = published_versions(backend, name, env)?.into_iter().max() {
+            latest = latest.max(v);
+        }
+    }
+    let mut version = latest + 1;
+    while !backend.reserve_version(home, name, version)? {
+        debug!("{}={} is reserved by another publish", name, version);
+        version += 1;
+    }
+    info!("Allocated {}={}", name, version);
+    Ok(version)
+}
+
+/// Publish a matrix release build to every supported environment at once
+///
+/// Meant to be done after a `lal build -r --matrix`. Every environment in `environments`
+/// must have a single release build in `./MATRIX` (or one of the given `configuration`),
+/// and they must all pass the checks of `publish` and share the same version and sha
+/// before anything is uploaded.
+///
+/// If an upload fails, the environments already published are rolled back,
+/// so that a version never ends up in only some of the supported environments.
+/// Versions overwritten with `force` cannot be restored, so a failure after one was
+/// overwritten is reported as an `IncompletePublish`.
+pub fn publish_matrix(
+    home: Option<&Path>,
+    component_dir: &Path,
+    name: &str,
+    configuration: Option<&str>,
+    environments: &[String],
+    force: bool,
+    backend: &dyn CachedBackend,
+) -> LalResult<()> {
+    let matrix = component_dir.join("./MATRIX");
+    if !matrix.is_dir() {
+        warn!("Missing: {}", matrix.display());
+        return Err(CliError::MissingReleaseBuild);
+    }
+    for entry in fs::read_dir(&matrix)? {
+        let env = entry?.file_name().to_string_lossy().to_string();
+        if !environments.contains(&env) {
+            warn!("Ignoring matrix build in unsupported {} environment", env);
+        }
+    }
+
+    let stages = find_matrix_releases(component_dir, configuration, environments)?;
+    if stages.is_empty() {
+        return Err(CliError::MissingReleaseBuild);
+    }
+
+    // every environment must be published at the same version and sha
+    let mut released: Option<(u32, Option<String>)> = None;
+    for (env, stage) in &stages {
+        debug!("Checking the release build for {}", env);
+        let (lock, v) = validate_release(stage, name)?;
+        if &lock.envname != env {
+            let reason = format!("{} was built in {}", stage.display(), lock.envname);
+            return Err(CliError::InconsistentMatrix(reason));
+        }
+        match released {
+            None => released = Some((v, lock.sha)),
+            Some((first, _)) if first != v => {
+                let reason = format!("{} is built as version {} and not {}", env, v, first);
+                return Err(CliError::InconsistentMatrix(reason));
+            }
+            Some((_, ref sha)) if *sha != lock.sha => {
+                let reason = format!("{} is built from a different sha", env);
+                return Err(CliError::InconsistentMatrix(reason));
+            }
+            Some(_) => {}
+        }
+    }
+    let version = released.map(|(v, _)| v).unwrap();
+
+    // refuse up front rather than rolling back after hitting a released version
+    let mut existing = vec![];
+    for (env, _) in &stages {
+        if is_published(backend, name, version, env)? {
+            if !force {
+                return Err(CliError::VersionAlreadyPublished(
+                    name.into(),
+                    version,
+                    env.clone(),
+                ));
+            }
+            existing.push(env.clone());
+        }
+    }
+
+    // upload everything, remembering what to remove if a later upload fails
+    let mut published = vec![];
+    let mut overwritten = vec![];
+    for (env, stage) in &stages {
+        // never roll back a version that was already there
+        if existing.contains(env) {
+            warn!("Overwriting {}={} in {}", name, version, env);
+            overwritten.push(env.clone());
+        } else {
+            published.push(env.clone());
+        }
+        info!("Publishing {}={} to {}", name, version, env);
+        if let Err(e) = backend.publish_artifact(home, stage, name, version, env) {
+            warn!("Failed to publish {}={} to {} ({})", name, version, env, e);
+            rollback_matrix(home, name, version, &published, overwritten, backend)?;
+            return Err(e);
+        }
+    }
+    info!("Published {}={} to {}", name, version, environments.join(", "));
+    Ok(())
+}
+
+/// Find the release build to publish for every environment in `./MATRIX`
+///
+/// Fails if an environment has no release build, or several without a `configuration` given.
+pub fn find_matrix_releases(
+    component_dir: &Path,
+    configuration: Option<&str>,
+    environments: &[String],
+) -> LalResult<Vec<(String, PathBuf)>> {
+    let matrix = component_dir.join("./MATRIX");
+    let mut stages = vec![];
+    for env in environments {
+        let stage = find_matrix_release(&matrix.join(env), configuration)?
+            .ok_or_else(|| CliError::InconsistentMatrix(format!("no release build for {}", env)))?;
+        stages.push((env.clone(), stage));
+    }
+    Ok(stages)
+}
+
+// the single release build staged for an environment in `./MATRIX`
+fn find_matrix_release(envdir: &Path, configuration: Option<&str>) -> LalResult<Option<PathBuf>> {
+    if !envdir.is_dir() {
+        return Ok(None);
+    }
+    let mut releases = vec![];
+    for entry in fs::read_dir(envdir)? {
+        let stage = entry?.path();
+        let cfg = stage.file_name().unwrap().to_string_lossy().to_string();
+        if let Some(c) = configuration {
+            if c != cfg {
+                continue;
+            }
+        }
+        if stage.join("ARTIFACT/lockfile.json").is_file() {
+            releases.push(stage);
+        }
+    }
+    if releases.len() > 1 {
+        let env = envdir.file_name().unwrap().to_string_lossy();
+        let reason = format!("multiple release builds in {} - pick a configuration", env);
+        return Err(CliError::InconsistentMatrix(reason));
+    }
+    Ok(releases.pop())
+}
+
+// remove a partially published version from every environment it was uploaded to
+//
+// Environments where an existing version was overwritten cannot be rolled back,
+// and are reported as incomplete along with any failed removals.
+fn rollback_matrix(
+    home: Option<&Path>,
+    name: &str,
+    version: u32,
+    environments: &[String],
+    overwritten: Vec<String>,
+    backend: &dyn CachedBackend,
+) -> LalResult<()> {
+    let mut incomplete = overwritten;
+    for env in environments {
+        info!("Rolling back {}={} in {}", name, version, env);
+        if let Err(e) = backend.delete_artifact(home, name, version, env) {
+            warn!("Failed to roll back {}={} in {} ({})", name, version, env, e);
+            incomplete.push(env.clone());
+        }
+    }
+    if !incomplete.is_empty() {
+        return Err(CliError::IncompletePublish(name.into(), version, incomplete));
+    }
+    Ok(())
+}
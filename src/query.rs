@@ -1,28 +1,229 @@
-use std::io::{self, Write};
+use std::{
+    cmp::Reverse,
+    collections::BTreeMap,
+    io::{self, Write},
+};
 
-use super::{CliError, LalResult};
+use chrono::NaiveDate;
+
+use super::{CliError, LalResult, Lockfile};
 use crate::storage::CachedBackend;
 
-/// Prints a list of versions associated with a component
-pub fn query(backend: &dyn CachedBackend, _env: Option<&str>, component: &str, last: bool) -> LalResult<()> {
+/// Filters and output flags for `lal query`
+#[derive(Default)]
+pub struct QueryOptions {
+    /// Only show the latest (matching) version
+    pub latest: bool,
+    /// Show build time, sha, tool version and dependencies of every version
+    pub details: bool,
+    /// Print the results as json
+    pub json: bool,
+    /// Only versions built on or after this date (`YYYY-MM-DD`)
+    pub since: Option<String>,
+    /// Only versions built from a sha starting with this
+    pub sha: Option<String>,
+    /// Only versions built against this `component=version` somewhere in their tree
+    pub depends_on: Option<String>,
+}
+
+impl QueryOptions {
+    fn filters(&self) -> bool {
+        self.since.is_some() || self.sha.is_some() || self.depends_on.is_some()
+    }
+
+    // filters and details need the published lockfile of every version
+    fn needs_lockfiles(&self) -> bool {
+        self.details || self.filters()
+    }
+}
+
+/// A published version as reported by `lal query`
+#[derive(Serialize, Debug)]
+pub struct QueryEntry {
+    /// The published version
+    pub version: u32,
+    /// Environment it was published in
+    pub environment: String,
+    /// Reason it was yanked (empty if none was given) if it was yanked
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub yanked: Option<String>,
+    /// Build time from its lockfile (when details or filters are used)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub built: Option<String>,
+    /// Sha it was built from (when details or filters are used)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha: Option<String>,
+    /// Version of lal it was built with (when details or filters are used)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool: Option<String>,
+    /// Versions of its direct dependencies (when details or filters are used)
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub dependencies: BTreeMap<String, String>,
+}
+
+impl QueryEntry {
+    fn new(version: u32, environment: &str, yanked: Option<String>) -> Self {
+        QueryEntry {
+            version,
+            environment: environment.into(),
+            yanked,
+            built: None,
+            sha: None,
+            tool: None,
+            dependencies: BTreeMap::new(),
+        }
+    }
+
+    fn with_lockfile(mut self, lock: &Lockfile) -> Self {
+        self.built = lock.built.clone();
+        self.sha = lock.sha.clone();
+        self.tool = Some(lock.tool.clone());
+        self.dependencies = lock
+            .dependencies
+            .iter()
+            .map(|(k, v)| (k.clone(), v.version.clone()))
+            .collect();
+        self
+    }
+}
+
+// whether a lockfile tree uses a dependency at a version anywhere
+fn depends_on(lock: &Lockfile, name: &str, version: &str) -> bool {
+    lock.dependencies
+        .iter()
+        .any(|(k, v)| (k == name && v.version == version) || depends_on(v, name, version))
+}
+
+// check the lockfile of a version against the query filters
+fn matches(lock: &Lockfile, opts: &QueryOptions, dependency: Option<(&str, &str)>) -> bool {
+    if let Some(ref since) = opts.since {
+        // built is `%Y-%m-%d %H:%M:%S` so dates compare as strings
+        match lock.built {
+            Some(ref built) if built.as_str() >= since.as_str() => {}
+            _ => return false,
+        }
+    }
+    if let Some(ref sha) = opts.sha {
+        match lock.sha {
+            Some(ref s) if s.starts_with(sha.as_str()) => {}
+            _ => return false,
+        }
+    }
+    if let Some((name, version)) = dependency {
+        if !depends_on(lock, name, version) {
+            return false;
+        }
+    }
+    true
+}
+
+/// Finds the versions of a component that `lal query` lists
+///
+/// Yanked versions are included, newest first alongside the others, with the reason
+/// for yanking them, but are never the `latest` version.
+///
+/// With `details` or any of the filters, the published lockfile of every version is
+/// looked up to show or match against its build time, sha, tool and dependency versions.
+pub fn query_entries(
+    backend: &dyn CachedBackend,
+    environments: &[String],
+    component: &str,
+    opts: &QueryOptions,
+) -> LalResult<Vec<QueryEntry>> {
     if component.to_lowercase() != component {
         return Err(CliError::InvalidComponentName(component.into()));
     }
-    let env = match _env {
-        None => {
-            error!("query is no longer allowed without an explicit environment");
-            return Err(CliError::EnvironmentUnspecified);
+    if environments.is_empty() {
+        error!("query is no longer allowed without an explicit environment");
+        return Err(CliError::EnvironmentUnspecified);
+    }
+    if let Some(ref since) = opts.since {
+        if NaiveDate::parse_from_str(since, "%Y-%m-%d").is_err() {
+            return Err(CliError::InvalidQueryFilter(format!("--since {}", since)));
         }
-        Some(e) => e,
+    }
+    let dependency = match opts.depends_on {
+        Some(ref spec) => {
+            let pair: Vec<&str> = spec.split('=').collect();
+            if pair.len() != 2 || pair[1].parse::<u32>().is_err() {
+                return Err(CliError::InvalidVersionSpec(spec.clone()));
+            }
+            Some((pair[0], pair[1]))
+        }
+        None => None,
     };
 
-    if last {
-        let ver = backend.get_latest_version(component, env)?;
-        println!("{}", ver);
+    let mut entries = vec![];
+    for env in environments {
+        let versions = match backend.get_versions(component, env) {
+            Ok(vs) => vs,
+            // a component need not exist in every environment listed side by side
+            Err(e) if environments.len() > 1 => {
+                debug!("No versions of {} in {}: {}", component, env, e);
+                vec![]
+            }
+            Err(e) => return Err(e),
+        };
+        let mut vers: Vec<(u32, Option<String>)> = versions.into_iter().map(|v| (v, None)).collect();
+        if !opts.latest {
+            // yanked versions are listed too, but marked as such
+            for (v, reason) in backend.get_yanked(component, env).unwrap_or_default() {
+                vers.push((v, Some(reason.trim().to_string())));
+            }
+        }
+        vers.sort_by_key(|&(v, _)| Reverse(v));
+
+        for (v, yanked) in vers {
+            let mut entry = QueryEntry::new(v, env, yanked);
+            if opts.needs_lockfiles() {
+                match backend.get_published_lockfile(component, v, env) {
+                    Ok(lock) => {
+                        if !matches(&lock, opts, dependency) {
+                            continue;
+                        }
+                        entry = entry.with_lockfile(&lock);
+                    }
+                    Err(e) => {
+                        warn!("No lockfile for {}={} in {}: {}", component, v, env, e);
+                        if opts.filters() {
+                            continue; // cannot tell if it matches
+                        }
+                    }
+                }
+            }
+            entries.push(entry);
+            if opts.latest {
+                break;
+            }
+        }
+    }
+    Ok(entries)
+}
+
+/// Prints a list of versions associated with a component
+///
+/// See `query_entries` for the versions listed.
+/// Several environments are listed side by side.
+pub fn query(
+    backend: &dyn CachedBackend,
+    environments: &[String],
+    component: &str,
+    opts: &QueryOptions,
+) -> LalResult<()> {
+    let entries = query_entries(backend, environments, component, opts)?;
+    if opts.json {
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+    } else if opts.details {
+        print_details(&entries);
+    } else if environments.len() > 1 {
+        print_side_by_side(&entries, environments);
     } else {
-        let vers = backend.get_versions(component, env)?;
-        for v in vers {
-            println!("{}", v);
+        for e in &entries {
+            match e.yanked {
+                None => println!("{}", e.version),
+                Some(ref reason) if reason.is_empty() => println!("{} (yanked)", e.version),
+                Some(ref reason) => println!("{} (yanked: {})", e.version, reason),
+            }
             // needed because sigpipe handling is broken for stdout atm
             // see #36 - can probably be taken out in rust 1.16 or 1.17
             // if `lal query media-engine | head` does not crash
@@ -33,3 +234,59 @@ pub fn query(backend: &dyn CachedBackend, _env: Option<&str>, component: &str, l
     }
     Ok(())
 }
+
+fn print_details(entries: &[QueryEntry]) {
+    for e in entries {
+        let dash = "-".to_string();
+        println!(
+            "{} {} built {} sha {} lal {}{}",
+            e.version,
+            e.environment,
+            e.built.as_ref().unwrap_or(&dash),
+            e.sha.as_ref().unwrap_or(&dash),
+            e.tool.as_ref().unwrap_or(&dash),
+            match e.yanked {
+                None => "".to_string(),
+                Some(ref r) if r.is_empty() => " (yanked)".to_string(),
+                Some(ref r) => format!(" (yanked: {})", r),
+            }
+        );
+        for (name, version) in &e.dependencies {
+            println!("  {}={}", name, version);
+        }
+        if io::stdout().flush().is_err() {
+            return;
+        }
+    }
+}
+
+// a row per version, with a column per environment it is published in
+fn print_side_by_side(entries: &[QueryEntry], environments: &[String]) {
+    let mut rows: BTreeMap<Reverse<u32>, BTreeMap<&str, &str>> = BTreeMap::new();
+    for e in entries {
+        let state = if e.yanked.is_some() { "yanked" } else { "x" };
+        rows.entry(Reverse(e.version))
+            .or_default()
+            .insert(&e.environment, state);
+    }
+    let width = environments.iter().map(|e| e.len()).max().unwrap_or(0).max(6);
+    print!("{:<10}", "version");
+    for env in environments {
+        print!(" {:<width$}", env, width = width);
+    }
+    println!();
+    for (Reverse(version), envs) in rows {
+        print!("{:<10}", version);
+        for env in environments {
+            print!(
+                " {:<width$}",
+                envs.get(env.as_str()).unwrap_or(&"-"),
+                width = width
+            );
+        }
+        println!();
+        if io::stdout().flush().is_err() {
+            return;
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use std::collections::BTreeSet;
+
+use super::{CliError, LalResult};
+use crate::storage::CachedBackend;
+
+/// A published component that depends on the component searched for
+pub struct ReverseDependency {
+    /// Name of the dependent component
+    pub name: String,
+    /// Latest version of the dependent component
+    pub version: u32,
+    /// Environment the dependent component was published in
+    pub environment: String,
+    /// Versions of the searched for component in the tree of the dependent
+    pub versions: BTreeSet<String>,
+    /// Whether the dependency is declared directly rather than pulled in transitively
+    pub direct: bool,
+}
+
+/// Find the latest published components that depend on a component
+///
+/// The published lockfiles of the latest version of every component in `environments`
+/// are scanned for `component` (at `version` if given) anywhere in their dependency tree.
+/// This uses the metadata index, which only fetches the lockfiles of new versions when refreshed.
+pub fn find_reverse_dependencies(
+    backend: &dyn CachedBackend,
+    environments: &[String],
+    component: &str,
+    version: Option<u32>,
+) -> LalResult<Vec<ReverseDependency>> {
+    if component.to_lowercase() != component {
+        return Err(CliError::InvalidComponentName(component.into()));
+    }
+    if environments.is_empty() {
+        return Err(CliError::EnvironmentUnspecified);
+    }
+    let version = version.map(|v| v.to_string());
+
+    let mut res = vec![];
+    for env in environments {
+        let index = backend.get_index(env)?;
+        for name in index.components.keys() {
+            let (latest, summary) = match index.latest(name) {
+                Some((v, iv)) => match iv.lockfile {
+                    Some(ref summary) => (v, summary),
+                    None => continue,
+                },
+                None => continue,
+            };
+            let versions: BTreeSet<String> = match summary.tree.get(component) {
+                Some(vs) => vs
+                    .iter()
+                    .filter(|v| version.is_none() || version.as_ref() == Some(*v))
+                    .cloned()
+                    .collect(),
+                None => continue,
+            };
+            if versions.is_empty() {
+                continue;
+            }
+            let direct = match summary.dependencies.get(component) {
+                Some(v) => versions.contains(v),
+                None => false,
+            };
+            res.push(ReverseDependency {
+                name: name.clone(),
+                version: latest,
+                environment: env.clone(),
+                versions,
+                direct,
+            });
+        }
+    }
+    Ok(res)
+}
+
+/// Print the latest published components that depend on a component
+///
+/// See `find_reverse_dependencies`.
+pub fn rdeps(
+    backend: &dyn CachedBackend,
+    environments: &[String],
+    component: &str,
+    version: Option<u32>,
+) -> LalResult<()> {
+    let rdeps = find_reverse_dependencies(backend, environments, component, version)?;
+    if rdeps.is_empty() {
+        info!("No published components depend on {}", component);
+        return Ok(());
+    }
+    for r in rdeps {
+        let versions: Vec<&str> = r.versions.iter().map(String::as_str).collect();
+        println!(
+            "{}={} ({}) uses {}={}{}",
+            r.name,
+            r.version,
+            r.environment,
+            component,
+            versions.join(","),
+            if r.direct { "" } else { " transitively" }
+        );
+    }
+    Ok(())
+}
@@ -1,32 +1,29 @@
 use std::{fs, path::Path};
 
-use super::{CliError, LalResult, Manifest};
+use super::{
+    input,
+    plan::{Action, ComponentAction, ManifestChange, Plan},
+    CliError, LalResult, Manifest,
+};
 
-/// Remove specific components from `./INPUT` and the manifest.
-///
-/// This takes multiple components strings (without versions), and if the component
-/// is found in `./INPUT` it is deleted.
-///
-/// If one of `save` or `savedev` was set, `manifest.json` is also updated to remove
-/// the specified components from the corresponding dictionary.
-pub fn remove(
+/// Work out what `remove` would do without changing anything
+pub fn plan_remove(
     component_dir: &Path,
     manifest: &Manifest,
-    xs: Vec<String>,
+    xs: &[String],
     save: bool,
     savedev: bool,
-) -> LalResult<()> {
-    debug!("Removing dependencies {:?}", xs);
+) -> LalResult<Plan> {
+    let mut plan = Plan::default();
 
     // remove entries in xs from manifest.
     if save || savedev {
-        let mut mf = manifest.clone();
-        let mut hmap = if save {
-            mf.dependencies.clone()
+        let hmap = if save {
+            &manifest.dependencies
         } else {
-            mf.devDependencies.clone()
+            &manifest.devDependencies
         };
-        for component in xs.clone() {
+        for component in xs {
             // We could perhaps allow people to just specify ANY dependency
             // and have a generic save flag, which we could infer from
             // thus we could modify both maps if listing many components
@@ -34,32 +31,66 @@ pub fn remove(
             // This could work, but it's not currently what install does, so not doing it.
             // => all components uninstalled from either dependencies, or all from devDependencies
             // if doing multiple components from different maps, do multiple calls
-            if !hmap.contains_key(&component) {
-                return Err(CliError::MissingComponent(component));
+            match hmap.get(component) {
+                Some(&v) => plan.manifest.push(ManifestChange {
+                    name: component.clone(),
+                    dev: !save,
+                    from: Some(v),
+                    to: None,
+                }),
+                None => return Err(CliError::MissingComponent(component.clone())),
             }
-            debug!("Removing {} from manifest", component);
-            hmap.remove(&component);
-        }
-        if save {
-            mf.dependencies = hmap;
-        } else {
-            mf.devDependencies = hmap;
         }
-        info!("Updating manifest with removed dependencies");
-        mf.write()?;
     }
 
     // delete the folder (ignore if the folder does not exist)
+    let present = input::analyze(component_dir).unwrap_or_default();
     let input = component_dir.join("./INPUT");
-    if !input.is_dir() {
-        return Ok(());
-    }
     for component in xs {
-        let pth = input.join(&component);
-        if pth.is_dir() {
-            debug!("Deleting INPUT/{}", component);
-            fs::remove_dir_all(&pth)?;
+        if input.join(component).is_dir() {
+            plan.actions.push(ComponentAction {
+                name: component.clone(),
+                action: Action::Remove,
+                version: None,
+                environment: None,
+                previous: present.get(component).cloned(),
+            });
         }
     }
+    Ok(plan)
+}
+
+/// Remove specific components from `./INPUT` and the manifest.
+///
+/// This takes multiple components strings (without versions), and if the component
+/// is found in `./INPUT` it is deleted.
+///
+/// If one of `save` or `savedev` was set, `manifest.json` is also updated to remove
+/// the specified components from the corresponding dictionary.
+pub fn remove(
+    component_dir: &Path,
+    manifest: &Manifest,
+    xs: Vec<String>,
+    save: bool,
+    savedev: bool,
+) -> LalResult<()> {
+    debug!("Removing dependencies {:?}", xs);
+    let plan = plan_remove(component_dir, manifest, &xs, save, savedev)?;
+
+    if save || savedev {
+        let mut mf = manifest.clone();
+        for c in &plan.manifest {
+            debug!("Removing {} from manifest", c.name);
+            c.apply(&mut mf);
+        }
+        info!("Updating manifest with removed dependencies");
+        mf.write()?;
+    }
+
+    for a in &plan.actions {
+        debug!("Deleting INPUT/{}", a.name);
+        fs::remove_dir_all(component_dir.join("./INPUT").join(&a.name))?;
+        let _ = fs::remove_file(input::parts_file(component_dir, &a.name));
+    }
     Ok(())
 }
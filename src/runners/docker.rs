@@ -1,7 +1,19 @@
-use std::{path::Path, process::Command, vec::Vec};
+use std::{
+    env, fmt, fs,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    process::{self, Command},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Instant,
+    vec::Vec,
+};
 
-use super::COMMAND_LOCK;
-use crate::core::{CliError, Config, Container, LalResult};
+use super::{
+    run_logged,
+    signals::{self, SignalWatch},
+    Exit, COMMAND_LOCK,
+};
+use crate::core::{CliError, Config, Container, InputStrategy, LalResult, ResourceLimits, UserMapping};
 
 /// Flags for docker run that vary for different use cases
 ///
@@ -28,6 +40,14 @@ pub struct ShellModes {
     pub host_networking: bool,
     /// Environment variables
     pub env_vars: Vec<String>,
+    /// Append the output to this file as well as printing it
+    pub log_file: Option<PathBuf>,
+    /// Component the command is run for, to label containers with
+    pub component: Option<String>,
+    /// Resource limits over those of the container - a timeout set here is only reported
+    pub limits: ResourceLimits,
+    /// Point in time to stop the command at, set by builds from their timeout
+    pub deadline: Option<Instant>,
 }
 
 /// Verifies that `id -u` and `id -g` are sane
@@ -130,6 +150,304 @@ fn build_docker_image(container: &Container, instructions: Vec<String>) -> LalRe
     Ok(())
 }
 
+/// A locally available docker image as listed by `docker images`
+pub struct DockerImage {
+    /// Repository name of the image
+    pub repository: String,
+    /// Tag of the image (`<none>` for untagged images)
+    pub tag: String,
+    /// Short image id
+    pub id: String,
+    /// Whether lal built the image as a user fixup (it carries the `lal.fixup` label)
+    pub fixup: bool,
+}
+
+impl DockerImage {
+    /// Reference usable with `docker rmi` and `docker image inspect`
+    ///
+    /// Untagged images can only be referenced through their id.
+    pub fn reference(&self) -> String {
+        if self.tag == "<none>" {
+            self.id.clone()
+        } else {
+            format!("{}:{}", self.repository, self.tag)
+        }
+    }
+}
+
+/// Lists the images known to the local docker daemon
+pub fn list_docker_images() -> LalResult<Vec<DockerImage>> {
+    trace!("Listing docker images");
+    let output = Command::new("docker")
+        .args(vec!["images", "--format", "{{.Repository}}\t{{.Tag}}\t{{.ID}}"])
+        .output()?;
+    if !output.status.success() {
+        return Err(CliError::SubprocessFailure(output.status.code().unwrap_or(1001)));
+    }
+    let fixups = Command::new("docker")
+        .arg("images")
+        .arg(format!("--filter=label={}", FIXUP_LABEL))
+        .arg("--format={{.ID}}")
+        .output()?;
+    if !fixups.status.success() {
+        return Err(CliError::SubprocessFailure(fixups.status.code().unwrap_or(1001)));
+    }
+    let fixup_ids: Vec<String> = String::from_utf8_lossy(&fixups.stdout)
+        .lines()
+        .map(|l| l.trim().to_string())
+        .collect();
+    let images = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.trim().split('\t').collect();
+            if fields.len() != 3 {
+                return None;
+            }
+            Some(DockerImage {
+                repository: fields[0].into(),
+                tag: fields[1].into(),
+                id: fields[2].into(),
+                fixup: fixup_ids.iter().any(|id| id == fields[2]),
+            })
+        })
+        .collect();
+    Ok(images)
+}
+
+/// Gets the size in bytes of a local docker image
+pub fn docker_image_size(image: &str) -> LalResult<u64> {
+    let output = Command::new("docker")
+        .args(vec!["image", "inspect", "--format", "{{.Size}}", image])
+        .output()?;
+    if !output.status.success() {
+        return Err(CliError::DockerImageNotFound(image.into()));
+    }
+    let size = String::from_utf8_lossy(&output.stdout).trim().parse().unwrap_or(0);
+    Ok(size)
+}
+
+/// Removes a local docker image
+pub fn remove_docker_image(image: &str) -> LalResult<()> {
+    trace!("Removing docker image {}", image);
+    let s = Command::new("docker").arg("rmi").arg(image).status()?;
+    if !s.success() {
+        return Err(CliError::SubprocessFailure(s.code().unwrap_or(1001)));
+    }
+    Ok(())
+}
+
+// labels put on every container lal starts
+const COMPONENT_LABEL: &str = "lal.component";
+const USER_LABEL: &str = "lal.user";
+const PID_LABEL: &str = "lal.pid";
+const HOST_LABEL: &str = "lal.host";
+
+// number of containers started by this process, to keep their names unique
+static CONTAINERS_STARTED: AtomicUsize = AtomicUsize::new(0);
+
+// name of the user running lal, as recorded on its containers
+fn current_user() -> String {
+    match env::var("USER") {
+        Ok(user) => user,
+        Err(_) => {
+            let output = Command::new("id").arg("-un").output();
+            output
+                .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string())
+                .unwrap_or_default()
+        }
+    }
+}
+
+/// Identifies the boot of the host and the pid namespace lal runs in
+///
+/// Recorded on every container lal starts, as the process ids recorded with them
+/// are only meaningful to lal processes with the same one.
+/// `None` where this cannot be read from `/proc`.
+pub fn container_host() -> Option<String> {
+    let boot_id = fs::read_to_string("/proc/sys/kernel/random/boot_id").ok()?;
+    let pid_ns = fs::read_link("/proc/self/ns/pid").ok()?;
+    let pid_ns = pid_ns.to_string_lossy();
+    // the link reads as `pid:[<inode>]`
+    let inode = pid_ns.trim_start_matches("pid:[").trim_end_matches(']');
+    Some(format!("{}-{}", boot_id.trim(), inode))
+}
+
+/// Name and labels for the next container started for a component
+///
+/// Names are of the form `lal-<component>-<pid>-<n>`. Without a component
+/// name, the name of the component directory is used instead.
+fn container_identity(component: Option<&str>, component_dir: &Path) -> (String, Vec<String>) {
+    let component = match component {
+        Some(c) => c.to_string(),
+        None => component_dir
+            .file_name()
+            .map(|f| f.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+    };
+    let component: String = component
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let pid = process::id();
+    let n = CONTAINERS_STARTED.fetch_add(1, Ordering::SeqCst);
+    let name = format!("lal-{}-{}-{}", component, pid, n);
+    let mut labels = vec![
+        format!("--label={}={}", COMPONENT_LABEL, component),
+        format!("--label={}={}", USER_LABEL, current_user()),
+        format!("--label={}={}", PID_LABEL, pid),
+    ];
+    if let Some(host) = container_host() {
+        labels.push(format!("--label={}={}", HOST_LABEL, host));
+    }
+    (name, labels)
+}
+
+/// Whether the lal process that started a container is still around
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ContainerStatus {
+    /// The lal process is still running
+    Running,
+    /// The lal process is gone
+    Orphaned,
+    /// Started from another host or pid namespace, so its lal process cannot be looked up
+    Unknown,
+}
+
+impl fmt::Display for ContainerStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match *self {
+            ContainerStatus::Running => "running",
+            ContainerStatus::Orphaned => "orphaned",
+            ContainerStatus::Unknown => "unknown",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// A running container started by lal as listed by `docker ps`
+pub struct LalContainer {
+    /// Name of the container
+    pub name: String,
+    /// Component it was started for
+    pub component: String,
+    /// User that started it
+    pub user: String,
+    /// Process id of the lal that started it
+    pub pid: u32,
+    /// Host and pid namespace of the lal that started it (empty if unknown)
+    pub host: String,
+    /// Image it runs
+    pub image: String,
+    /// How long it has been running for, as reported by docker
+    pub running_for: String,
+}
+
+impl LalContainer {
+    /// Whether the lal process that started the container is still around
+    ///
+    /// This can only be told for containers started from the same host and pid namespace.
+    pub fn status(&self) -> ContainerStatus {
+        match container_host() {
+            Some(ref host) if *host == self.host => {
+                if Path::new("/proc").join(self.pid.to_string()).exists() {
+                    ContainerStatus::Running
+                } else {
+                    ContainerStatus::Orphaned
+                }
+            }
+            _ => ContainerStatus::Unknown,
+        }
+    }
+
+    /// Whether the container was started by the current user from this host and pid namespace
+    pub fn is_own(&self) -> bool {
+        self.user == current_user() && container_host().as_ref() == Some(&self.host)
+    }
+}
+
+/// Lists the running containers started by lal
+pub fn list_lal_containers() -> LalResult<Vec<LalContainer>> {
+    trace!("Listing lal containers");
+    let label = |l: &str| format!("{{{{.Label \"{}\"}}}}", l);
+    let format = [
+        "{{.Names}}".to_string(),
+        label(COMPONENT_LABEL),
+        label(USER_LABEL),
+        label(PID_LABEL),
+        label(HOST_LABEL),
+        "{{.Image}}".to_string(),
+        "{{.RunningFor}}".to_string(),
+    ]
+    .join("\t");
+    let output = Command::new("docker")
+        .arg("ps")
+        .arg(format!("--filter=label={}", PID_LABEL))
+        .arg(format!("--format={}", format))
+        .output()?;
+    if !output.status.success() {
+        return Err(CliError::SubprocessFailure(output.status.code().unwrap_or(1001)));
+    }
+    let containers = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split('\t').collect();
+            if fields.len() != 7 {
+                return None;
+            }
+            Some(LalContainer {
+                name: fields[0].into(),
+                component: fields[1].into(),
+                user: fields[2].into(),
+                pid: fields[3].parse().ok()?,
+                host: fields[4].into(),
+                image: fields[5].into(),
+                running_for: fields[6].into(),
+            })
+        })
+        .collect();
+    Ok(containers)
+}
+
+/// Kills a running docker container
+pub fn kill_docker_container(name: &str) -> LalResult<()> {
+    trace!("Killing docker container {}", name);
+    let s = Command::new("docker").arg("kill").arg(name).status()?;
+    if !s.success() {
+        return Err(CliError::SubprocessFailure(s.code().unwrap_or(1001)));
+    }
+    Ok(())
+}
+
+// label put on the images built by `fixup_docker_container`
+const FIXUP_LABEL: &str = "lal.fixup";
+
+/// Splits the base image name and id out of a `fixup_docker_container` image
+///
+/// Returns `None` unless the image is named `<base>-u<uid>_g<gid>:from_<id>`.
+/// Images built by older lal versions lack the `lal.fixup` label, so this only
+/// checks the name; see `DockerImage::fixup` for whether lal labelled it.
+pub fn fixup_base_name(image: &DockerImage) -> Option<(&str, &str)> {
+    if !image.tag.starts_with("from_") {
+        return None;
+    }
+    let repository = &image.repository;
+    let idx = repository.rfind("-u")?;
+    let ids = &repository[idx + 2..];
+    let sep = ids.find("_g")?;
+    let (uid, gid) = (&ids[..sep], &ids[sep + 2..]);
+    if uid.parse::<u32>().is_ok() && gid.parse::<u32>().is_ok() {
+        Some((&repository[..idx], image.tag.trim_start_matches("from_")))
+    } else {
+        None
+    }
+}
+
 /// Fixes up docker container for use with given uid and gid
 ///
 /// Returns a container derived from the one passed as an argument, with the `lal`
@@ -148,6 +466,8 @@ fn fixup_docker_container(container: &Container, u: u32, g: u32) -> LalResult<Co
     let modified_container = Container {
         name: format!("{}-u{}_g{}", container.name, u, g),
         tag: format!("from_{}", image_id),
+        user_mapping: UserMapping::Rebuild,
+        limits: container.limits.clone(),
     };
 
     info!("Using container {}", modified_container);
@@ -161,6 +481,7 @@ fn fixup_docker_container(container: &Container, u: u32, g: u32) -> LalResult<Co
         Err(_) => {
             let instructions: Vec<String> = vec![
                 format!("FROM {}", container),
+                format!("LABEL {}={}", FIXUP_LABEL, container),
                 "USER root".into(),
                 format!("RUN groupmod -g {} lal && usermod -u {} lal", g, u),
                 "USER lal".into(),
@@ -173,6 +494,208 @@ fn fixup_docker_container(container: &Container, u: u32, g: u32) -> LalResult<Co
     Ok(modified_container)
 }
 
+/// Reads a file out of a docker image without running its entrypoint
+fn read_docker_image_file(container: &Container, path: &str) -> LalResult<String> {
+    trace!("Reading {} from {}", path, container);
+    let output = Command::new("docker")
+        .args(vec!["run", "--rm", "--entrypoint", "cat"])
+        .arg(container.to_string())
+        .arg(path)
+        .output()?;
+    if !output.status.success() {
+        return Err(CliError::SubprocessFailure(output.status.code().unwrap_or(1001)));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Reads the entrypoint a docker image was built with
+///
+/// Returns an empty vector if the image has no entrypoint.
+fn get_docker_image_entrypoint(container: &Container) -> LalResult<Vec<String>> {
+    trace!("Inspecting entrypoint of {}", container);
+    let output = Command::new("docker")
+        .args(vec!["image", "inspect", "--format={{json .Config.Entrypoint}}"])
+        .arg(container.to_string())
+        .output()?;
+    if !output.status.success() {
+        return Err(CliError::DockerImageNotFound(container.to_string()));
+    }
+    let entrypoint: Option<Vec<String>> = serde_json::from_slice(&output.stdout)?;
+    Ok(entrypoint.unwrap_or_default())
+}
+
+/// Rewrites the `lal` entry of a passwd or group database
+///
+/// The id fields to replace are given as (column, value) pairs.
+/// If no `lal` entry exists, the `fallback` line is appended instead.
+fn remap_lal_entry(database: &str, ids: &[(usize, u32)], fallback: String) -> String {
+    let mut found = false;
+    let mut lines: Vec<String> = database
+        .lines()
+        .map(|line| {
+            let mut fields: Vec<String> = line.split(':').map(String::from).collect();
+            if fields[0] != "lal" {
+                return line.to_string();
+            }
+            found = true;
+            for &(col, id) in ids {
+                if col < fields.len() {
+                    fields[col] = id.to_string();
+                }
+            }
+            fields.join(":")
+        })
+        .collect();
+    if !found {
+        lines.push(fallback);
+    }
+    lines.push(String::new()); // trailing newline
+    lines.join("\n")
+}
+
+/// Remaps the `lal` user and group of an image's passwd and group databases
+///
+/// Returns the new passwd and group contents where `lal` has the given uid and gid.
+pub fn remap_lal_user(passwd: &str, group: &str, u: u32, g: u32) -> (String, String) {
+    let passwd_data = remap_lal_entry(
+        passwd,
+        &[(2, u), (3, g)],
+        format!("lal:x:{}:{}:lal:/home/lal:/bin/sh", u, g),
+    );
+    let group_data = remap_lal_entry(group, &[(2, g)], format!("lal:x:{}:", g));
+    (passwd_data, group_data)
+}
+
+/// Generates passwd and group files where `lal` has the given uid and gid
+///
+/// The files are derived from the ones in the image, and cached by image id
+/// in the lal cache so they only have to be read out of the image once.
+/// Returns the paths of the generated passwd and group files.
+fn generate_passwd_files(
+    cfg: &Config,
+    container: &Container,
+    u: u32,
+    g: u32,
+) -> LalResult<(PathBuf, PathBuf)> {
+    let image_id = get_docker_image_id(container).or_else(|_| {
+        pull_docker_image(container)?;
+        get_docker_image_id(container)
+    })?;
+    let dir = cfg.cache.join("users").join(format!("{}-u{}_g{}", image_id, u, g));
+    let passwd = dir.join("passwd");
+    let group = dir.join("group");
+    if passwd.is_file() && group.is_file() {
+        debug!("Reusing generated passwd and group in {}", dir.display());
+        return Ok((passwd, group));
+    }
+
+    info!("Generating passwd and group for user {}:{} in {}", u, g, container);
+    let (passwd_data, group_data) = remap_lal_user(
+        &read_docker_image_file(container, "/etc/passwd")?,
+        &read_docker_image_file(container, "/etc/group")?,
+        u,
+        g,
+    );
+    fs::create_dir_all(&dir)?;
+    fs::write(&passwd, passwd_data)?;
+    fs::write(&group, group_data)?;
+    Ok((passwd, group))
+}
+
+/// Entrypoint shim that remaps the `lal` user before dropping privileges
+///
+/// Expects `LAL_UID` and `LAL_GID` in the environment and the command as arguments.
+const ENTRYPOINT_SHIM: &str = r#"#!/bin/sh
+set -e
+groupmod -o -g "$LAL_GID" lal
+usermod -o -u "$LAL_UID" lal
+if command -v setpriv > /dev/null; then
+    exec setpriv --reuid="$LAL_UID" --regid="$LAL_GID" --init-groups -- "$@"
+elif command -v su-exec > /dev/null; then
+    exec su-exec lal "$@"
+fi
+exec su lal -s /bin/sh -c 'exec "$0" "$@"' -- "$@"
+"#;
+
+/// Writes the entrypoint shim to the lal cache and returns its location
+fn write_entrypoint_shim(cfg: &Config) -> LalResult<PathBuf> {
+    let dir = cfg.cache.join("users");
+    fs::create_dir_all(&dir)?;
+    let shim = dir.join("lal-entrypoint.sh");
+    fs::write(&shim, ENTRYPOINT_SHIM)?;
+    fs::set_permissions(&shim, fs::Permissions::from_mode(0o755))?;
+    Ok(shim)
+}
+
+/// Arguments for `docker run` that make the container act as the host user
+struct MappedUser {
+    /// The image to run (a derived image when rebuilding)
+    container: Container,
+    /// Extra flags to pass to `docker run`
+    args: Vec<String>,
+    /// Entrypoint to wrap the command in (if the mapping needs one)
+    entrypoint: Option<String>,
+    /// The image's own entrypoint, which the wrapping entrypoint has to run
+    chained: Vec<String>,
+}
+
+/// Resolves the configured `UserMapping` strategy of a container
+fn map_user(cfg: &Config, container: &Container, uid: u32, gid: u32) -> LalResult<MappedUser> {
+    let user = vec!["--user".into(), format!("{}:{}", uid, gid)];
+    match container.user_mapping {
+        UserMapping::Rebuild => {
+            // Fall back to the base container if the fixup fails
+            let modified = fixup_docker_container(container, uid, gid).unwrap_or_else(|e| {
+                debug!("Failed to fixup container for user {}:{}: {}", uid, gid, e);
+                container.clone()
+            });
+            Ok(MappedUser {
+                container: modified,
+                args: user,
+                entrypoint: None,
+                chained: vec![],
+            })
+        }
+        UserMapping::Passwd => {
+            let (passwd, group) = generate_passwd_files(cfg, container, uid, gid)?;
+            let mut args = user;
+            args.push("-v".into());
+            args.push(format!("{}:/etc/passwd:ro", passwd.display()));
+            args.push("-v".into());
+            args.push(format!("{}:/etc/group:ro", group.display()));
+            args.push("--env=HOME=/home/lal".into());
+            Ok(MappedUser {
+                container: container.clone(),
+                args,
+                entrypoint: None,
+                chained: vec![],
+            })
+        }
+        UserMapping::Entrypoint => {
+            let shim = write_entrypoint_shim(cfg)?;
+            let chained = get_docker_image_entrypoint(container).or_else(|_| {
+                pull_docker_image(container)?;
+                get_docker_image_entrypoint(container)
+            })?;
+            let args = vec![
+                "--user".into(),
+                "0:0".into(),
+                "-v".into(),
+                format!("{}:/lal-entrypoint.sh:ro", shim.display()),
+                format!("--env=LAL_UID={}", uid),
+                format!("--env=LAL_GID={}", gid),
+                "--env=HOME=/home/lal".into(),
+            ];
+            Ok(MappedUser {
+                container: container.clone(),
+                args,
+                entrypoint: Some("/lal-entrypoint.sh".into()),
+                chained,
+            })
+        }
+    }
+}
+
 /// Runs an arbitrary command in the configured docker container
 ///
 /// This will mount the current directory as `~/volume` as well as a few conveniences,
@@ -187,10 +710,11 @@ pub fn docker_run(
 ) -> LalResult<()> {
     debug!("Performing docker permission sanity check");
     let (uid, gid) = permission_sanity_check()?;
-    let modified_container: LalResult<Container> = fixup_docker_container(container, uid, gid);
+    let mapped = map_user(cfg, container, uid, gid)?;
+    let limits = container.limits.merge(&modes.limits);
 
     // Shadow container here
-    let container = modified_container.as_ref().unwrap_or(container);
+    let container = &mapped.container;
 
     debug!("Finding home and cwd");
     let home = dirs::home_dir().unwrap(); // crash if no $HOME
@@ -211,6 +735,13 @@ pub fn docker_run(
     debug!(" - mounting {}", &component_dir.display());
     args.push("-v".into());
     args.push(format!("{}:/home/lal/volume", &component_dir.display()));
+    // symlinked INPUT points into the cache, so it must resolve inside the container
+    let extracted = Path::new(&cfg.cache).join("extracted");
+    if cfg.input_strategy == InputStrategy::Symlink && extracted.is_dir() {
+        debug!(" - mounting {}", extracted.display());
+        args.push("-v".into());
+        args.push(format!("{}:{}:ro", extracted.display(), extracted.display()));
+    }
 
     // X11 forwarding
     if modes.x11_forwarding {
@@ -235,15 +766,30 @@ pub fn docker_run(
     if flags.privileged {
         args.push("--privileged".into())
     }
+    args.extend(limits.docker_args());
+    // a known name lets the container be stopped from outside (and found by `lal ps`)
+    let (name, labels) = container_identity(modes.component.as_deref(), component_dir);
+    args.push(format!("--name={}", name));
+    args.extend(labels);
 
     args.push("-w".into());
     args.push("/home/lal/volume".into());
-    args.push("--user".into());
-    args.push(format!("{}:{}", uid, gid));
+    args.extend(mapped.args.clone());
 
     // If no command, then override entrypoint to /bin/bash
     // This happens when we use `lal shell` without args
-    if command.is_empty() {
+    let mut command = command;
+    if let Some(ref shim) = mapped.entrypoint {
+        // the shim execs its arguments, so bash has to go there instead
+        args.push("--entrypoint".into());
+        args.push(shim.clone());
+        if command.is_empty() {
+            command.push("/bin/bash".into());
+        } else {
+            // commands still go through the image's entrypoint, as with the other mappings
+            command.splice(0..0, mapped.chained.clone());
+        }
+    } else if command.is_empty() {
         args.push("--entrypoint".into());
         args.push("/bin/bash".into());
     }
@@ -277,13 +823,27 @@ pub fn docker_run(
         // desired behaviour in all cases, whether or not we are in tests.
         let _guard = COMMAND_LOCK.lock().unwrap();
 
-        let s = Command::new("docker")
-            .args(&args)
-            .current_dir(&component_dir)
-            .status()?;
+        // stop the container rather than leave it running if lal is interrupted
+        let _watch = SignalWatch::new();
+        if let Some(sig) = signals::received() {
+            return Err(CliError::Interrupted(sig));
+        }
+
+        let mut cmd = Command::new("docker");
+        cmd.args(&args).current_dir(&component_dir);
+        let stop = |_| {
+            info!("Killing container {}", name);
+            let _ = kill_docker_container(&name);
+        };
+        let exit = run_logged(&mut cmd, modes.log_file.as_deref(), modes.deadline, &stop)?;
         debug!("Exited docker");
-        if !s.success() {
-            return Err(CliError::SubprocessFailure(s.code().unwrap_or(1001)));
+        match exit {
+            Exit::Status(s) if !s.success() => {
+                return Err(CliError::SubprocessFailure(s.code().unwrap_or(1001)));
+            }
+            Exit::Status(_) => {}
+            Exit::TimedOut => return Err(CliError::Timeout(modes.limits.timeout.unwrap_or(0))),
+            Exit::Interrupted(sig) => return Err(CliError::Interrupted(sig)),
         }
     }
     Ok(())
@@ -1,12 +1,123 @@
 pub use self::{
-    docker::{docker_run, DockerRunFlags, ShellModes},
+    docker::{
+        container_host, docker_image_size, docker_run, fixup_base_name, kill_docker_container,
+        list_docker_images, list_lal_containers, remap_lal_user, remove_docker_image, ContainerStatus,
+        DockerImage, DockerRunFlags, LalContainer, ShellModes,
+    },
     native::native_run,
 };
-use std::sync::Mutex;
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Read, Write},
+    path::Path,
+    process::{Child, Command, ExitStatus, Stdio},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
 
 lazy_static! {
     static ref COMMAND_LOCK: Mutex<()> = Mutex::new(());
 }
 
+// copy everything from a child's output to the terminal and a log file
+fn tee<R: Read + Send + 'static>(
+    mut src: R,
+    stderr: bool,
+    log: Arc<Mutex<File>>,
+) -> thread::JoinHandle<io::Result<()>> {
+    thread::spawn(move || {
+        let mut buf = [0; 8192];
+        loop {
+            let n = src.read(&mut buf)?;
+            if n == 0 {
+                return Ok(());
+            }
+            if stderr {
+                let mut term = io::stderr();
+                term.write_all(&buf[..n])?;
+                term.flush()?;
+            } else {
+                let mut term = io::stdout();
+                term.write_all(&buf[..n])?;
+                term.flush()?;
+            }
+            log.lock().unwrap().write_all(&buf[..n])?;
+        }
+    })
+}
+
+/// How a command run through `run_logged` ended
+enum Exit {
+    /// It exited by itself
+    Status(ExitStatus),
+    /// It was stopped after running past its deadline
+    TimedOut,
+    /// It was stopped because lal received this signal
+    Interrupted(i32),
+}
+
+/// Run a command to completion, appending its output to `log` as well if given
+///
+/// With a `deadline`, or while a `SignalWatch` is held, `stop` is called with the process id
+/// and the command killed once the deadline has passed or lal is asked to stop.
+fn run_logged(
+    cmd: &mut Command,
+    log: Option<&Path>,
+    deadline: Option<Instant>,
+    stop: &dyn Fn(u32),
+) -> io::Result<Exit> {
+    if log.is_none() && deadline.is_none() && !signals::watching() {
+        return cmd.status().map(Exit::Status);
+    }
+    let mut tees = vec![];
+    if let Some(log) = log {
+        let file = OpenOptions::new().create(true).append(true).open(log)?;
+        let file = Arc::new(Mutex::new(file));
+        cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
+        let mut child = cmd.spawn()?;
+        tees.push(tee(child.stdout.take().unwrap(), false, file.clone()));
+        tees.push(tee(child.stderr.take().unwrap(), true, file));
+        let exit = wait_or_stop(&mut child, deadline, stop);
+        for t in tees {
+            if let Err(e) = t.join().unwrap() {
+                warn!("Failed to copy output into {}: {}", log.display(), e);
+            }
+        }
+        exit
+    } else {
+        let mut child = cmd.spawn()?;
+        wait_or_stop(&mut child, deadline, stop)
+    }
+}
+
+// wait for a child, stopping and killing it if it outlives the deadline or lal is interrupted
+fn wait_or_stop(child: &mut Child, deadline: Option<Instant>, stop: &dyn Fn(u32)) -> io::Result<Exit> {
+    if deadline.is_none() && !signals::watching() {
+        return child.wait().map(Exit::Status);
+    }
+    let exit = loop {
+        if let Some(sig) = signals::received() {
+            warn!("Stopping command on signal {}", sig);
+            break Exit::Interrupted(sig);
+        }
+        if let Some(s) = child.try_wait()? {
+            return Ok(Exit::Status(s));
+        }
+        if let Some(d) = deadline {
+            if Instant::now() >= d {
+                warn!("Stopping command that ran out of time");
+                break Exit::TimedOut;
+            }
+        }
+        thread::sleep(Duration::from_millis(100));
+    };
+    stop(child.id());
+    let _ = child.kill();
+    child.wait()?;
+    Ok(exit)
+}
+
 mod docker;
 mod native;
+mod signals;
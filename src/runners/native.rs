@@ -1,9 +1,17 @@
-use super::COMMAND_LOCK;
+use super::{
+    run_logged,
+    signals::{self, SignalWatch},
+    Exit, ShellModes, COMMAND_LOCK,
+};
 use crate::core::{CliError, LalResult};
-use std::{path::Path, process::Command, vec::Vec};
+use std::{os::unix::process::CommandExt, path::Path, process::Command, vec::Vec};
 
 /// Runs an arbitrary command natively, without containerization
-pub fn native_run(mut command: Vec<String>, component_dir: &Path) -> LalResult<()> {
+///
+/// `env_vars` of the form `KEY=VALUE` in `modes` are set for the command,
+/// bare names are already inherited from the current environment.
+/// Of the resource limits in `modes` only the timeout applies, through the `deadline`.
+pub fn native_run(mut command: Vec<String>, modes: &ShellModes, component_dir: &Path) -> LalResult<()> {
     let cmd = command.remove(0);
     let mut script_cmd = Command::new(cmd);
 
@@ -15,12 +23,39 @@ pub fn native_run(mut command: Vec<String>, component_dir: &Path) -> LalResult<(
     // desired behaviour in all cases, whether or not we are in tests.
     let _guard = COMMAND_LOCK.lock().unwrap();
 
+    for var in &modes.env_vars {
+        if let Some(i) = var.find('=') {
+            script_cmd.env(&var[..i], &var[i + 1..]);
+        }
+    }
     script_cmd.args(command).current_dir(&component_dir);
-    let s = script_cmd.status()?;
-
-    if !s.success() {
-        return Err(CliError::SubprocessFailure(s.code().unwrap_or(1001)));
+    let detached = modes.deadline.is_some();
+    let _watch = if detached {
+        // in its own process group so that everything it started can be killed with it,
+        // which also keeps it from seeing a ctrl-c, so lal has to catch that for it
+        script_cmd.process_group(0);
+        Some(SignalWatch::new())
+    } else {
+        None
+    };
+    if let Some(sig) = signals::received() {
+        return Err(CliError::Interrupted(sig));
+    }
+    let stop = |pid| {
+        // the command only has a process group of its own when it has a deadline
+        if detached {
+            let _ = Command::new("kill")
+                .arg("-KILL")
+                .arg("--")
+                .arg(format!("-{}", pid))
+                .status();
+        }
+    };
+    let exit = run_logged(&mut script_cmd, modes.log_file.as_deref(), modes.deadline, &stop)?;
+    match exit {
+        Exit::Status(s) if !s.success() => Err(CliError::SubprocessFailure(s.code().unwrap_or(1001))),
+        Exit::Status(_) => Ok(()),
+        Exit::TimedOut => Err(CliError::Timeout(modes.limits.timeout.unwrap_or(0))),
+        Exit::Interrupted(sig) => Err(CliError::Interrupted(sig)),
     }
-
-    Ok(())
 }
@@ -0,0 +1,66 @@
+use std::sync::{
+    atomic::{AtomicI32, Ordering},
+    Mutex,
+};
+
+// the last SIGINT or SIGTERM received while a container was running (0 for none)
+static RECEIVED: AtomicI32 = AtomicI32::new(0);
+
+lazy_static! {
+    // number of live `SignalWatch`es, the handlers are installed while it is non-zero
+    static ref WATCHERS: Mutex<usize> = Mutex::new(0);
+}
+
+extern "C" fn record(sig: libc::c_int) {
+    RECEIVED.store(sig, Ordering::SeqCst);
+}
+
+/// Catches SIGINT and SIGTERM for as long as it is alive
+///
+/// While a watch is held the signals no longer kill lal, but are recorded so
+/// whoever waits on a container can stop it before giving up.
+pub struct SignalWatch;
+
+impl SignalWatch {
+    /// Start catching the signals
+    pub fn new() -> SignalWatch {
+        let mut watchers = WATCHERS.lock().unwrap();
+        if *watchers == 0 {
+            let handler = record as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            unsafe {
+                libc::signal(libc::SIGINT, handler);
+                libc::signal(libc::SIGTERM, handler);
+            }
+        }
+        *watchers += 1;
+        SignalWatch
+    }
+}
+
+impl Drop for SignalWatch {
+    fn drop(&mut self) {
+        let mut watchers = WATCHERS.lock().unwrap();
+        *watchers -= 1;
+        if *watchers == 0 {
+            unsafe {
+                libc::signal(libc::SIGINT, libc::SIG_DFL);
+                libc::signal(libc::SIGTERM, libc::SIG_DFL);
+            }
+        }
+    }
+}
+
+/// Whether a `SignalWatch` is currently held
+pub fn watching() -> bool {
+    *WATCHERS.lock().unwrap() > 0
+}
+
+/// The signal lal was asked to stop with while a container was running, if any
+///
+/// This stays set, so that nothing else is started on the way out.
+pub fn received() -> Option<i32> {
+    match RECEIVED.load(Ordering::SeqCst) {
+        0 => None,
+        sig => Some(sig),
+    }
+}
@@ -18,7 +18,7 @@ pub fn run(
         Environment::Container(container) => {
             docker_run(&cfg, &container, command, &flags, &modes, &component_dir)
         }
-        Environment::None => native_run(command, &component_dir),
+        Environment::None => native_run(command, &modes, &component_dir),
     }
 }
 
@@ -60,7 +60,7 @@ pub fn shell(
                 command.push("bash".into());
             }
 
-            native_run(command, &component_dir)
+            native_run(command, &modes, &component_dir)
         }
     }
 }
@@ -108,6 +108,6 @@ pub fn script(
                 &component_dir,
             )?)
         }
-        Environment::None => native_run(command, &component_dir),
+        Environment::None => native_run(command, &modes, &component_dir),
     }
 }
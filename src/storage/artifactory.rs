@@ -1,6 +1,7 @@
 #![allow(missing_docs)]
 
 use std::{
+    collections::{BTreeMap, BTreeSet},
     fs::File,
     io::{Read, Write},
     path::{Path, PathBuf},
@@ -18,7 +19,7 @@ use hyper::{
 };
 use hyper_native_tls::NativeTlsClient;
 
-use crate::core::{CliError, LalResult};
+use crate::core::{output, CliError, Compression, ExtractLimits, InputStrategy, LalResult, Lockfile};
 
 
 /// Artifactory credentials
@@ -57,11 +58,19 @@ struct ArtifactoryVersion {
 struct ArtifactoryStorageResponse {
     children: Vec<ArtifactoryVersion>,
 }
+// The deep file list of a folder, used to find yank markers
+#[derive(Deserialize)]
+struct ArtifactoryFileList {
+    files: Vec<ArtifactoryVersion>,
+}
 
 // simple request body fetcher
 fn hyper_req(url: &str) -> LalResult<String> {
     let client = Client::with_connector(HttpsConnector::new(NativeTlsClient::new().unwrap()));
     let mut res = client.get(url).send()?;
+    if res.status == hyper::NotFound {
+        return Err(CliError::BackendNotFound(url.into()));
+    }
     if res.status != hyper::Ok {
         return Err(CliError::BackendFailure(format!(
             "GET request with {}",
@@ -149,7 +158,7 @@ header! {(XCheckSumSha1, "X-Checksum-Sha1") => [String]}
 /// Upload a tarball to artifactory
 ///
 /// This is using a http basic auth PUT to artifactory using config credentials.
-fn upload_artifact(arti: &ArtifactoryConfig, uri: &str, f: &mut File) -> LalResult<()> {
+fn upload_artifact(arti: &ArtifactoryConfig, uri: &str, f: &mut dyn Read) -> LalResult<()> {
     if let Some(creds) = arti.credentials.clone() {
         let client = Client::new();
 
@@ -205,27 +214,134 @@ fn upload_artifact(arti: &ArtifactoryConfig, uri: &str, f: &mut File) -> LalResu
     }
 }
 
-/// Get the maximal version number from the storage api
-fn get_storage_as_u32(uri: &str) -> LalResult<u32> {
-    if let Some(&latest) = get_storage_versions(uri)?.iter().max() {
-        Ok(latest)
+/// Delete a folder or file on artifactory
+///
+/// This is using a http basic auth DELETE to artifactory using config credentials.
+fn delete_artifact(arti: &ArtifactoryConfig, uri: &str) -> LalResult<()> {
+    if let Some(creds) = arti.credentials.clone() {
+        let client = Client::new();
+        let full_uri = format!("{}/{}/{}", arti.slave, arti.release, uri);
+        let auth = Authorization(Basic {
+            username: creds.username,
+            password: Some(creds.password),
+        });
+
+        info!("DELETE {}", full_uri);
+        let resp = client.delete(&full_uri[..]).header(auth).send()?;
+        debug!("resp={:?}", resp);
+        let respstr = format!("{} from DELETE {}", resp.status, full_uri);
+        // already gone is as good as deleted
+        if !resp.status.is_success() && resp.status != StatusCode::NotFound {
+            return Err(CliError::UploadFailure(respstr));
+        }
+        debug!("{}", respstr);
+        Ok(())
     } else {
-        Err(CliError::BackendFailure(
-            "No version information found on API".into(),
-        ))
+        Err(CliError::MissingBackendCredentials)
     }
 }
 
+/// Create an empty marker file on artifactory unless it exists
+///
+/// Artifactory has no conditional PUT, so this only narrows the window for races.
+fn create_marker(arti: &ArtifactoryConfig, uri: &str) -> LalResult<bool> {
+    if let Some(creds) = arti.credentials.clone() {
+        let client = Client::new();
+        let full_uri = format!("{}/{}/{}", arti.slave, arti.release, uri);
+        let auth = Authorization(Basic {
+            username: creds.username,
+            password: Some(creds.password),
+        });
+
+        info!("HEAD {}", full_uri);
+        let resp = client.head(&full_uri[..]).header(auth.clone()).send()?;
+        debug!("resp={:?}", resp);
+        if resp.status.is_success() {
+            return Ok(false);
+        }
+
+        info!("PUT {}", full_uri);
+        let resp = client.put(&full_uri[..]).header(auth).body("").send()?;
+        debug!("resp={:?}", resp);
+        let respstr = format!("{} from PUT {}", resp.status, full_uri);
+        if resp.status != StatusCode::Created {
+            return Err(CliError::UploadFailure(respstr));
+        }
+        debug!("{}", respstr);
+        Ok(true)
+    } else {
+        Err(CliError::MissingBackendCredentials)
+    }
+}
+
+// Find the compression of a version from the names of the files published with it
+//
+// A version without a tarball we recognise is assumed to be a legacy gzip tarball.
+fn find_compression(files: &[&str], name: &str, version: u32) -> Compression {
+    let found = output::FORMATS
+        .iter()
+        .cloned()
+        .find(|c| files.contains(&c.tarball_name(name).as_str()));
+    if found.is_none() {
+        debug!("No tarball found for {}={}, assuming gzip", name, version);
+    }
+    found.unwrap_or_default()
+}
+
+// Find the compression a component was published with from the files next to it
+//
+// Nothing to list (a 404) is left to the download to report, as a legacy gzip tarball.
+fn get_published_compression(
+    art_cfg: &ArtifactoryConfig,
+    name: &str,
+    version: u32,
+    env: &str,
+) -> LalResult<Compression> {
+    let url = format!(
+        "{}/api/storage/{}/{}/{}/{}/{}",
+        art_cfg.master, art_cfg.release, "env", env, name, version
+    );
+    debug!("GET {}", url);
+    let files: Vec<String> = match hyper_req(&url) {
+        Ok(resp) => serde_json::from_str::<ArtifactoryStorageResponse>(&resp)?
+            .children
+            .iter()
+            .map(|r| r.uri.trim_matches('/').to_string())
+            .collect(),
+        Err(CliError::BackendNotFound(_)) => vec![],
+        Err(e) => return Err(e),
+    };
+    let files: Vec<&str> = files.iter().map(String::as_str).collect();
+    Ok(find_compression(&files, name, version))
+}
+
 // The URL for a component tarball under the one of the environment trees
-fn get_dependency_env_url(art_cfg: &ArtifactoryConfig, name: &str, version: u32, env: &str) -> String {
+fn get_dependency_env_url(
+    art_cfg: &ArtifactoryConfig,
+    name: &str,
+    version: u32,
+    env: &str,
+) -> LalResult<String> {
+    let compression = get_published_compression(art_cfg, name, version, env)?;
+    Ok(get_tarball_url(art_cfg, name, version, env, compression))
+}
+
+// The URL for a component tarball of a known compression
+fn get_tarball_url(
+    art_cfg: &ArtifactoryConfig,
+    name: &str,
+    version: u32,
+    env: &str,
+    compression: Compression,
+) -> String {
     let tar_url = format!(
-        "{}/{}/env/{}/{}/{}/{}.tar.gz",
+        "{}/{}/env/{}/{}/{}/{}",
         art_cfg.slave,
         art_cfg.vgroup,
         env,
         name,
         version.to_string(),
-        name
+        compression.tarball_name(name)
     );
 
     trace!("Inferring tarball location as {}", tar_url);
@@ -237,11 +353,20 @@ fn get_dependency_url_latest(art_cfg: &ArtifactoryConfig, name: &str, env: &str)
         "{}/api/storage/{}/{}/{}/{}",
         art_cfg.master, art_cfg.release, "env", env, name
     );
-    let v = get_storage_as_u32(&url)?;
+    // the deep listing has both the yank markers and the tarball of the latest version
+    let files = get_file_list(art_cfg, name, env)?;
+    let yanked = find_yanked(&files);
+    let v = get_storage_versions(&url)?
+        .into_iter()
+        .find(|v| !yanked.contains(v))
+        .ok_or_else(|| CliError::BackendFailure("No version information found on API".into()))?;
 
     debug!("Found latest version as {}", v);
+    let prefix = format!("{}/", v);
+    let version_files: Vec<&str> = files.iter().filter_map(|f| f.strip_prefix(&prefix)).collect();
+    let compression = find_compression(&version_files, name, v);
     Ok(Component {
-        location: get_dependency_env_url(art_cfg, name, v, env),
+        location: get_tarball_url(art_cfg, name, v, env, compression),
         version: v,
         name: name.into(),
     })
@@ -258,6 +383,86 @@ fn get_latest_versions(art_cfg: &ArtifactoryConfig, name: &str, env: &str) -> La
     get_storage_versions(&url)
 }
 
+// Every folder in an environment is a component
+fn get_environment_components(art_cfg: &ArtifactoryConfig, env: &str) -> LalResult<Vec<String>> {
+    let url = format!(
+        "{}/api/storage/{}/{}/{}",
+        art_cfg.master, art_cfg.release, "env", env
+    );
+    debug!("GET {}", url);
+    let resp = hyper_req(&url)?;
+    let res: ArtifactoryStorageResponse = serde_json::from_str(&resp)?;
+    let mut names: Vec<String> = res
+        .children
+        .iter()
+        .map(|c| c.uri.trim_matches('/').to_string())
+        .collect();
+    names.sort();
+    Ok(names)
+}
+
+// Every file below the component folder, as `<version>/<file>` paths
+//
+// Nothing to list (a 404) means nothing was published.
+fn get_file_list(art_cfg: &ArtifactoryConfig, name: &str, env: &str) -> LalResult<Vec<String>> {
+    let url = format!(
+        "{}/api/storage/{}/{}/{}/{}?list&deep=1",
+        art_cfg.master, art_cfg.release, "env", env, name
+    );
+    debug!("GET {}", url);
+    let resp = match hyper_req(&url) {
+        Ok(resp) => resp,
+        Err(CliError::BackendNotFound(_)) => return Ok(vec![]),
+        Err(e) => return Err(e),
+    };
+    let res: ArtifactoryFileList = serde_json::from_str(&resp)?;
+    Ok(res
+        .files
+        .iter()
+        .map(|f| f.uri.trim_matches('/').to_string())
+        .collect())
+}
+
+// Yank markers are found with a deep listing of the component folder
+//
+// The reasons are only fetched when asked for.
+fn get_yanked_set(art_cfg: &ArtifactoryConfig, name: &str, env: &str) -> LalResult<BTreeSet<u32>> {
+    Ok(find_yanked(&get_file_list(art_cfg, name, env)?))
+}
+
+// The versions with a yank marker in a deep file listing
+fn find_yanked(files: &[String]) -> BTreeSet<u32> {
+    let mut yanked = BTreeSet::new();
+    for f in files {
+        let parts: Vec<&str> = f.split('/').collect();
+        if parts.len() == 2 && parts[1] == YANK_MARKER {
+            if let Ok(v) = parts[0].parse::<u32>() {
+                yanked.insert(v);
+            }
+        }
+    }
+    yanked
+}
+
+// The contents of the yank marker of a version, if it has one
+fn get_yank_marker(
+    art_cfg: &ArtifactoryConfig,
+    name: &str,
+    version: u32,
+    env: &str,
+) -> LalResult<Option<String>> {
+    let url = format!(
+        "{}/{}/env/{}/{}/{}/{}",
+        art_cfg.slave, art_cfg.vgroup, env, name, version, YANK_MARKER
+    );
+    debug!("GET {}", url);
+    match hyper_req(&url) {
+        Ok(reason) => Ok(Some(reason)),
+        Err(CliError::BackendNotFound(_)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
 /// Main entry point for install
 fn get_tarball_uri(
     art_cfg: &ArtifactoryConfig,
@@ -267,7 +472,7 @@ fn get_tarball_uri(
 ) -> LalResult<Component> {
     if let Some(v) = version {
         Ok(Component {
-            location: get_dependency_env_url(art_cfg, name, v, env),
+            location: get_dependency_env_url(art_cfg, name, v, env)?,
             version: v,
             name: name.into(),
         })
@@ -287,7 +492,7 @@ pub struct LatestLal {
 
 /// Entry point for `lal::upgrade`
 ///
-/// This mostly duplicates the behaviour in `get_storage_as_u32`, however,
+/// This mostly duplicates the behaviour in `get_storage_versions`, however,
 /// it is parsing the version as a `semver::Version` struct rather than a u32.
 /// This is used regardless of your used backend because we want people to use our
 /// main release of lal on CME-release on cisco artifactory at the moment.
@@ -327,7 +532,7 @@ pub fn get_latest_lal_version() -> LalResult<LatestLal> {
     }
 }
 
-use super::{Backend, Component};
+use super::{Backend, Component, YANK_MARKER};
 
 /// Everything we need for Artifactory to implement the Backend trait
 pub struct ArtifactoryBackend {
@@ -335,6 +540,12 @@ pub struct ArtifactoryBackend {
     pub config: ArtifactoryConfig,
     /// Cache directory
     pub cache: PathBuf,
+    /// How to populate INPUT from the cache
+    pub input: InputStrategy,
+    /// Seconds the local metadata index can be used for
+    pub index_ttl: Option<u64>,
+    /// Limits on extracting tarballs from the cache
+    pub extract_limits: ExtractLimits,
 }
 
 impl ArtifactoryBackend {
@@ -343,8 +554,29 @@ impl ArtifactoryBackend {
         ArtifactoryBackend {
             config: cfg.clone(),
             cache: cache.to_path_buf(),
+            input: InputStrategy::default(),
+            index_ttl: None,
+            extract_limits: ExtractLimits::default(),
         }
     }
+
+    /// Use a non-default strategy for populating INPUT
+    pub fn with_input_strategy(mut self, input: InputStrategy) -> Self {
+        self.input = input;
+        self
+    }
+
+    /// Use the local metadata index for queries until it is older than `ttl` seconds
+    pub fn with_index_ttl(mut self, ttl: Option<u64>) -> Self {
+        self.index_ttl = ttl;
+        self
+    }
+
+    /// Use non-default limits when extracting tarballs
+    pub fn with_extract_limits(mut self, limits: ExtractLimits) -> Self {
+        self.extract_limits = limits;
+        self
+    }
 }
 
 /// Artifact backend trait for `ArtifactoryBackend`
@@ -353,7 +585,14 @@ impl ArtifactoryBackend {
 /// specific low-level use cases, these methods can be used directly.
 impl Backend for ArtifactoryBackend {
     fn get_versions(&self, name: &str, loc: &str) -> LalResult<Vec<u32>> {
-        get_latest_versions(&self.config, name, loc)
+        let (versions, yanked) = self.get_all_versions(name, loc)?;
+        Ok(versions.into_iter().filter(|v| !yanked.contains(v)).collect())
+    }
+
+    fn get_all_versions(&self, name: &str, loc: &str) -> LalResult<(Vec<u32>, BTreeSet<u32>)> {
+        let versions = get_latest_versions(&self.config, name, loc)?;
+        let yanked = get_yanked_set(&self.config, name, loc)?;
+        Ok((versions, yanked))
     }
 
     fn get_latest_version(&self, name: &str, loc: &str) -> LalResult<u32> {
@@ -361,10 +600,36 @@ impl Backend for ArtifactoryBackend {
         Ok(latest.version)
     }
 
+    fn get_yanked(&self, name: &str, loc: &str) -> LalResult<BTreeMap<u32, String>> {
+        let mut yanked = BTreeMap::new();
+        for v in get_yanked_set(&self.config, name, loc)? {
+            let reason = get_yank_marker(&self.config, name, v, loc)?;
+            yanked.insert(v, reason.unwrap_or_default());
+        }
+        Ok(yanked)
+    }
+
+    fn get_yank_reason(&self, name: &str, version: u32, loc: &str) -> LalResult<Option<String>> {
+        get_yank_marker(&self.config, name, version, loc)
+    }
+
+    fn get_components(&self, loc: &str) -> LalResult<Vec<String>> {
+        get_environment_components(&self.config, loc)
+    }
+
     fn get_component_info(&self, name: &str, version: Option<u32>, loc: &str) -> LalResult<Component> {
         get_tarball_uri(&self.config, name, version, loc)
     }
 
+    fn get_published_lockfile(&self, name: &str, version: u32, loc: &str) -> LalResult<Lockfile> {
+        let url = format!(
+            "{}/{}/env/{}/{}/{}/lockfile.json",
+            self.config.slave, self.config.vgroup, loc, name, version
+        );
+        debug!("GET {}", url);
+        Ok(serde_json::from_str(&hyper_req(&url)?)?)
+    }
+
     fn publish_artifact(
         &self,
         _home: Option<&Path>,
@@ -376,26 +641,83 @@ impl Backend for ArtifactoryBackend {
         // this fn basically assumes all the sanity checks have been performed
         // files must exist and lockfile must be sensible
         let artdir = component_dir.join("./ARTIFACT");
-        let tarball = artdir.join(format!("{}.tar.gz", name));
+        let tarball = output::find_tarball(&artdir, name).ok_or(CliError::MissingReleaseBuild)?;
         let lockfile = artdir.join("lockfile.json");
 
         // uri prefix if specific env upload
         let prefix = format!("env/{}/", env);
 
-        let tar_uri = format!("{}{}/{}/{}.tar.gz", prefix, name, version, name);
-        let mut tarf = File::open(tarball)?;
+        let tar_uri = format!(
+            "{}{}/{}/{}",
+            prefix,
+            name,
+            version,
+            tarball.file_name().unwrap().to_string_lossy()
+        );
+        let mut tarf = File::open(&tarball)?;
         upload_artifact(&self.config, &tar_uri, &mut tarf)?;
 
         let mut lockf = File::open(lockfile)?;
         let lf_uri = format!("{}{}/{}/lockfile.json", prefix, name, version);
         upload_artifact(&self.config, &lf_uri, &mut lockf)?;
+
+        // parts and debug files split out by the packaging rules
+        for extra in output::find_tarballs(&artdir, name)? {
+            if extra == tarball {
+                continue;
+            }
+            let extra_uri = format!(
+                "{}{}/{}/{}",
+                prefix,
+                name,
+                version,
+                extra.file_name().unwrap().to_string_lossy()
+            );
+            let mut extraf = File::open(&extra)?;
+            upload_artifact(&self.config, &extra_uri, &mut extraf)?;
+        }
         Ok(())
     }
 
+    fn delete_artifact(&self, _home: Option<&Path>, name: &str, version: u32, env: &str) -> LalResult<()> {
+        delete_artifact(&self.config, &format!("env/{}/{}/{}", env, name, version))
+    }
+
+    fn reserve_version(&self, _home: Option<&Path>, name: &str, version: u32) -> LalResult<bool> {
+        create_marker(&self.config, &format!("reservations/{}/{}", name, version))
+    }
+
+    fn yank_version(
+        &self,
+        _home: Option<&Path>,
+        name: &str,
+        version: u32,
+        env: &str,
+        reason: &str,
+    ) -> LalResult<()> {
+        if !get_latest_versions(&self.config, name, env)?.contains(&version) {
+            return Err(CliError::MissingPublishedVersion(name.into(), version));
+        }
+        let uri = format!("env/{}/{}/{}/{}", env, name, version, YANK_MARKER);
+        upload_artifact(&self.config, &uri, &mut reason.as_bytes())
+    }
+
     fn get_cache_dir(&self) -> PathBuf {
         self.cache.clone()
     }
 
+    fn get_input_strategy(&self) -> InputStrategy {
+        self.input.clone()
+    }
+
+    fn get_index_ttl(&self) -> Option<u64> {
+        self.index_ttl
+    }
+
+    fn get_extract_limits(&self) -> ExtractLimits {
+        self.extract_limits.clone()
+    }
+
     fn raw_fetch(&self, url: &str, dest: &Path) -> LalResult<()> {
         http_download_to_path(url, dest)
     }
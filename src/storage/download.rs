@@ -1,11 +1,21 @@
 use std::{
+    collections::BTreeSet,
     fs,
-    path::{Path, PathBuf},
+    io::prelude::*,
+    path::{Component as PathComponent, Path, PathBuf},
+    process::Command,
 };
 
+use chrono::{DateTime, UTC};
+use tar::Archive;
+use walkdir::WalkDir;
+
 use crate::{
-    core::{output, CliError, LalResult},
-    storage::{Backend, CachedBackend, Component},
+    core::{
+        ensure_dir_exists_fresh, human_size, input, output, CliError, Compression, ExtractLimits,
+        InputStrategy, LalResult, Manifest,
+    },
+    storage::{metadata, Backend, CachedBackend, Component, MetadataIndex},
 };
 
 fn is_cached(backend: &dyn Backend, name: &str, version: u32, env: &str) -> bool {
@@ -21,33 +31,199 @@ fn get_cache_dir(backend: &dyn Backend, name: &str, version: u32, env: &str) ->
         .join(version.to_string())
 }
 
+/// Name of the marker file recording when a cached artifact was last used
+const LAST_USE_FILE: &str = ".lastuse";
+
+// pinned versions can still be fetched after being yanked, but should be moved away from
+fn warn_if_yanked(backend: &dyn Backend, name: &str, version: u32, env: &str) {
+    let yanked = backend.get_yank_reason(name, version, env).ok().and_then(|r| r);
+    if let Some(reason) = yanked {
+        warn!("{}={} has been YANKED from {}", name, version, env);
+        if !reason.trim().is_empty() {
+            warn!("Reason: {}", reason.trim());
+        }
+        warn!("Please update {} to a version that has not been yanked", name);
+    }
+}
+
+// record a cache hit so that `lal cache prune` can evict the least recently used
+fn mark_used(dir: &Path) -> LalResult<()> {
+    let mut f = fs::File::create(dir.join(LAST_USE_FILE))?;
+    write!(f, "{}", UTC::now().to_rfc3339())?;
+    Ok(())
+}
+
+/// When a cached artifact directory was last used by `fetch`, `update` or `export`
+///
+/// Returns `None` for artifacts cached before last use was recorded.
+pub fn cache_last_use(dir: &Path) -> Option<DateTime<UTC>> {
+    let mut data = String::new();
+    fs::File::open(dir.join(LAST_USE_FILE))
+        .and_then(|mut f| f.read_to_string(&mut data))
+        .ok()?;
+    data.trim().parse::<DateTime<UTC>>().ok()
+}
+
 fn stored_tarball_location(
     backend: &dyn Backend,
-    name: &str,
-    version: u32,
+    component: &Component,
     env: &str,
 ) -> Result<PathBuf, CliError> {
     // 1. mkdir -p cacheDir/$name/$version
-    let destdir = get_cache_dir(backend, name, version, env);
+    let destdir = get_cache_dir(backend, &component.name, component.version, env);
     if !destdir.is_dir() {
         fs::create_dir_all(&destdir)?;
     }
-    // 2. stuff $PWD/$name.tar.gz in there
-    let tarname = [name, ".tar.gz"].concat();
+    // 2. stuff $PWD/$name.tar.* in there (with the extension it was published with)
+    let tarname = Compression::from_name(&component.location).tarball_name(&component.name);
     let dest = Path::new(&destdir).join(&tarname);
 
     Ok(dest)
 }
 
-// helper for the unpack_ functions
-fn extract_tarball_to_input(tarname: PathBuf, component_dir: &Path, component: &str) -> LalResult<()> {
-    use flate2::read::GzDecoder;
-    use tar::Archive;
+// how many directories deep a relative path goes, or None if it is absolute or uses `..`
+fn contained_depth(path: &Path) -> Option<usize> {
+    let mut depth = 0;
+    for c in path.components() {
+        match c {
+            PathComponent::Normal(_) => depth += 1,
+            PathComponent::CurDir => {}
+            PathComponent::ParentDir | PathComponent::RootDir | PathComponent::Prefix(_) => return None,
+        }
+    }
+    Some(depth)
+}
 
-    let extract_path = component_dir.join("INPUT").join(component);
-    let _ = fs::remove_dir_all(&extract_path); // remove current dir if exists
-    fs::create_dir_all(&extract_path)?;
-    debug!("extract path: {}", extract_path.display());
+// the normal components of a contained relative path
+fn normalized(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|c| matches!(c, PathComponent::Normal(_)))
+        .collect()
+}
+
+// the first directory of a normalized path that is a symlink extracted earlier
+fn through_symlink(path: &Path, symlinks: &BTreeSet<PathBuf>) -> Option<PathBuf> {
+    let mut prefix = PathBuf::new();
+    let parts: Vec<_> = path.components().collect();
+    for c in &parts[..parts.len().saturating_sub(1)] {
+        prefix.push(c);
+        if symlinks.contains(&prefix) {
+            return Some(prefix);
+        }
+    }
+    None
+}
+
+// reason for rejecting an entry, if it would end up outside the extraction directory
+//
+// Links are only resolved lexically, so entries placed through a symlink extracted earlier,
+// and links resolving through one, are rejected as their real location is not known.
+fn unsafe_entry_reason(
+    path: &Path,
+    link: Option<&Path>,
+    hardlink: bool,
+    symlinks: &BTreeSet<PathBuf>,
+) -> Option<String> {
+    if path.has_root() {
+        return Some("absolute path".into());
+    }
+    if contained_depth(path).is_none() {
+        return Some("path contains '..'".into());
+    }
+    let path = normalized(path);
+    if let Some(link) = through_symlink(&path, symlinks) {
+        return Some(format!("path goes through symlink {}", link.display()));
+    }
+    let target = link?;
+    if target.has_root() {
+        return Some(format!("link to absolute path {}", target.display()));
+    }
+    if hardlink {
+        // hardlink targets are relative to the root of the archive
+        if contained_depth(target).is_none() {
+            return Some(format!("hardlink to {} outside of the tarball", target.display()));
+        }
+        if let Some(link) = through_symlink(&normalized(target), symlinks) {
+            return Some(format!(
+                "hardlink to {} goes through symlink {}",
+                target.display(),
+                link.display()
+            ));
+        }
+        return None;
+    }
+    // symlink targets are relative to the directory containing the symlink
+    let mut resolved = path.parent().map(Path::to_path_buf).unwrap_or_default();
+    for c in target.components() {
+        if symlinks.contains(&resolved) {
+            return Some(format!(
+                "symlink to {} goes through symlink {}",
+                target.display(),
+                resolved.display()
+            ));
+        }
+        match c {
+            PathComponent::Normal(n) => resolved.push(n),
+            PathComponent::ParentDir if resolved.parent().is_some() => {
+                resolved.pop();
+            }
+            PathComponent::CurDir => {}
+            _ => {
+                return Some(format!(
+                    "symlink to {} escapes the extraction directory",
+                    target.display()
+                ))
+            }
+        }
+    }
+    None
+}
+
+// unpack entries one by one after checking that each stays within dest
+fn unpack_archive<R: Read>(archive: &mut Archive<R>, dest: &Path, limits: &ExtractLimits) -> LalResult<()> {
+    let mut count = 0;
+    let mut size = 0;
+    let mut symlinks = BTreeSet::new();
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        let rejected = |reason: String| CliError::UnsafeTarballEntry(path.display().to_string(), reason);
+
+        count += 1;
+        if count > limits.max_entries {
+            return Err(rejected(format!("more than {} entries", limits.max_entries)));
+        }
+        size += entry.header().size()?;
+        if size > limits.max_size {
+            return Err(rejected(format!(
+                "more than {} in total",
+                human_size(limits.max_size)
+            )));
+        }
+
+        let etype = entry.header().entry_type();
+        let link = if etype.is_symlink() || etype.is_hard_link() {
+            entry.link_name()?.map(|l| l.into_owned())
+        } else {
+            None
+        };
+        if let Some(reason) = unsafe_entry_reason(&path, link.as_deref(), etype.is_hard_link(), &symlinks) {
+            return Err(rejected(reason));
+        }
+        entry.unpack_in(dest)?;
+        if etype.is_symlink() {
+            symlinks.insert(normalized(&path));
+        }
+    }
+    Ok(())
+}
+
+// extract a tarball into an arbitrary directory
+fn unpack_tarball(tarname: &Path, dest: &Path, limits: &ExtractLimits) -> LalResult<()> {
+    // go by content rather than extension - tarballs may have been renamed
+    let compression = Compression::detect(tarname)?;
+    fs::create_dir_all(dest)?;
+    debug!("extract path: {} ({:?})", dest.display(), compression);
 
     // Open file, conditionally wrap a progress bar around the file reading
     if cfg!(feature = "progress") {
@@ -56,21 +232,241 @@ fn extract_tarball_to_input(tarname: PathBuf, component_dir: &Path, component: &
             use super::progress::ProgressReader;
             let data = fs::File::open(tarname)?;
             let progdata = ProgressReader::new(data)?;
-            let decompressed = GzDecoder::new(progdata)?; // decoder reads data (proxied)
+            let decompressed = compression.decoder(progdata)?; // decoder reads data (proxied)
             let mut archive = Archive::new(decompressed); // Archive reads decoded
-            archive.unpack(&extract_path)?;
+            unpack_archive(&mut archive, dest, limits)?;
         }
     } else {
         let data = fs::File::open(tarname)?;
-        let decompressed = GzDecoder::new(data)?; // decoder reads data
+        let decompressed = compression.decoder(data)?; // decoder reads data
         let mut archive = Archive::new(decompressed); // Archive reads decoded
-        archive.unpack(&extract_path)?;
+        unpack_archive(&mut archive, dest, limits)?;
     };
 
     debug!("---");
     Ok(())
 }
 
+// helper for the unpack_ functions
+fn extract_tarballs_to_input(
+    tarballs: &[PathBuf],
+    component_dir: &Path,
+    component: &str,
+    limits: &ExtractLimits,
+) -> LalResult<()> {
+    let extract_path = component_dir.join("INPUT").join(component);
+    let _ = fs::remove_dir_all(&extract_path); // remove current dir if exists
+    for tarname in tarballs {
+        let res = unpack_tarball(tarname, &extract_path, limits);
+        if res.is_err() {
+            let _ = fs::remove_dir_all(&extract_path); // do not leave half a component behind
+            return res;
+        }
+    }
+    Ok(())
+}
+
+/// Location of the shared extracted tree of a cached component
+pub fn get_extracted_dir(cache: &Path, name: &str, version: u32, env: &str) -> PathBuf {
+    cache
+        .join("extracted")
+        .join(env)
+        .join(name)
+        .join(version.to_string())
+}
+
+/// Location of the shared extracted tree of some parts of a cached component
+///
+/// These live next to the tree of the whole component as `<version>+<part>[+<part>..]`.
+pub fn get_extracted_parts_dir(
+    cache: &Path,
+    name: &str,
+    version: u32,
+    env: &str,
+    parts: &[String],
+) -> PathBuf {
+    let mut parts = parts.to_vec();
+    parts.sort();
+    parts.dedup();
+    get_extracted_dir(cache, name, version, env).with_file_name(format!("{}+{}", version, parts.join("+")))
+}
+
+// extract cached tarballs once into a shared tree of extracted components
+fn extracted_tree(tarballs: &[PathBuf], tree: PathBuf, limits: &ExtractLimits) -> LalResult<PathBuf> {
+    if tree.is_dir() {
+        debug!("Reusing extracted {}", tree.display());
+        return Ok(tree);
+    }
+    // extract next to the final location then move it in place,
+    // so an interrupted extraction never looks like a complete tree
+    let parent = tree.parent().unwrap();
+    let leaf = tree.file_name().unwrap().to_string_lossy();
+    let staging = parent.join(format!(".{}-{:x}", leaf, rand::random::<u32>()));
+    for tarname in tarballs {
+        if let Err(e) = unpack_tarball(tarname, &staging, limits) {
+            let _ = fs::remove_dir_all(&staging);
+            return Err(e);
+        }
+    }
+    if let Err(e) = fs::rename(&staging, &tree) {
+        let _ = fs::remove_dir_all(&staging);
+        // someone else may have finished the same extraction first
+        if !tree.is_dir() {
+            return Err(e.into());
+        }
+    }
+    Ok(tree)
+}
+
+// file next to an extracted tree listing the component directories symlinking to it
+fn tree_users_file(tree: &Path) -> PathBuf {
+    let leaf = tree.file_name().unwrap().to_string_lossy();
+    tree.with_file_name(format!(".{}.users", leaf))
+}
+
+// remember that `INPUT/<name>` of a component directory is a symlink to a tree
+fn record_tree_user(tree: &Path, component_dir: &Path) -> LalResult<()> {
+    let users_file = tree_users_file(tree);
+    let user = fs::canonicalize(input::final_component_dir(component_dir))?;
+    let user = user.to_string_lossy();
+    let mut users = fs::read_to_string(&users_file).unwrap_or_default();
+    if !users.lines().any(|u| u == user) {
+        users.push_str(&format!("{}\n", user));
+        fs::write(&users_file, users)?;
+    }
+    Ok(())
+}
+
+/// Whether a shared extracted tree in the cache is still used by an `INPUT`
+///
+/// A tree is in use while `INPUT/<name>` of a component directory it was symlinked
+/// into still points to it, or while any of its files is hardlinked from elsewhere.
+pub fn extracted_tree_in_use(tree: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    let name = match tree.parent().and_then(|p| p.file_name()) {
+        Some(name) => name,
+        None => return false,
+    };
+    let resolved = fs::canonicalize(tree).ok();
+    let users = fs::read_to_string(tree_users_file(tree)).unwrap_or_default();
+    for user in users.lines() {
+        let link = Path::new(user).join("INPUT").join(name);
+        if resolved.is_some() && fs::canonicalize(&link).ok() == resolved {
+            return true;
+        }
+    }
+    WalkDir::new(tree)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .any(|e| e.metadata().map(|m| m.nlink() > 1).unwrap_or(false))
+}
+
+/// Remove a shared extracted tree from the cache unless it is still in use
+///
+/// Returns whether the tree was removed.
+pub fn remove_extracted_tree(tree: &Path) -> LalResult<bool> {
+    if !tree.is_dir() {
+        return Ok(false);
+    }
+    if extracted_tree_in_use(tree) {
+        debug!("Keeping {} as it is still used by an INPUT", tree.display());
+        return Ok(false);
+    }
+    debug!("Removing extracted {}", tree.display());
+    fs::remove_dir_all(tree)?;
+    let _ = fs::remove_file(tree_users_file(tree));
+    Ok(true)
+}
+
+// recreate a directory tree using hardlinks or copies (copying across filesystems)
+fn recreate_tree(tree: &Path, dest: &Path, hardlink: bool) -> LalResult<()> {
+    use std::os::unix::fs::symlink;
+    for entry in WalkDir::new(tree).into_iter() {
+        let entry = entry.map_err(|e| CliError::Io(e.into()))?;
+        let target = dest.join(entry.path().strip_prefix(tree).unwrap());
+        let ftype = entry.file_type();
+        if ftype.is_dir() {
+            fs::create_dir_all(&target)?;
+        } else if ftype.is_symlink() {
+            symlink(fs::read_link(entry.path())?, &target)?;
+        } else if !hardlink || fs::hard_link(entry.path(), &target).is_err() {
+            fs::copy(entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
+
+// populate INPUT/component from an extracted tree in the cache
+fn link_tree_to_input(
+    tree: &Path,
+    component_dir: &Path,
+    component: &str,
+    strategy: &InputStrategy,
+) -> LalResult<()> {
+    let input = component_dir.join("INPUT");
+    let dest = input.join(component);
+    let _ = fs::remove_dir_all(&dest); // remove current dir (or symlink) if exists
+    fs::create_dir_all(&input)?;
+    debug!("{:?} {} -> {}", strategy, tree.display(), dest.display());
+
+    match *strategy {
+        InputStrategy::Hardlink => recreate_tree(tree, &dest, true)?,
+        InputStrategy::Reflink => {
+            // only GNU cp knows --reflink, copy the tree by hand with any other
+            let reflinked = match Command::new("cp")
+                .arg("-a")
+                .arg("--reflink=auto")
+                .arg(tree)
+                .arg(&dest)
+                .output()
+            {
+                Ok(out) if out.status.success() => true,
+                Ok(out) => {
+                    debug!(
+                        "cp --reflink failed: {}",
+                        String::from_utf8_lossy(&out.stderr).trim()
+                    );
+                    false
+                }
+                Err(e) => {
+                    debug!("cp --reflink failed: {}", e);
+                    false
+                }
+            };
+            if !reflinked {
+                let _ = fs::remove_dir_all(&dest);
+                recreate_tree(tree, &dest, false)?;
+            }
+        }
+        InputStrategy::Symlink => {
+            std::os::unix::fs::symlink(tree, &dest)?;
+            record_tree_user(tree, component_dir)?;
+        }
+        InputStrategy::Extract => unreachable!("extract strategy does not use extracted trees"),
+    }
+    Ok(())
+}
+
+// versions from the metadata index if it is in use and fresh, falling back to the backend
+//
+// A stale index is not refreshed here, as that would list every component in the environment.
+fn indexed_versions<T: CachedBackend>(backend: &T, name: &str, env: &str) -> LalResult<Vec<u32>> {
+    if let Some(ttl) = backend.get_index_ttl() {
+        let index = MetadataIndex::read(&backend.get_cache_dir(), env);
+        if index.is_fresh(ttl) {
+            let versions = index.versions(name);
+            if !versions.is_empty() {
+                return Ok(versions);
+            }
+            debug!("No versions of {} in the {} index", name, env);
+        } else {
+            debug!("The {} index is stale, listing {} from the backend", env, name);
+        }
+    }
+    backend.get_versions(name, env)
+}
+
 /// Cacheable trait implemented for all Backends.
 ///
 /// As long as we have the Backend trait implemented, we can add a caching layer
@@ -88,7 +484,7 @@ impl<T: Backend> CachedBackend for T {
         let mut result = BTreeSet::new();
         let mut first_pass = true;
         for e in environments {
-            let eres: BTreeSet<_> = self.get_versions(name, &e)?.into_iter().take(100).collect();
+            let eres: BTreeSet<_> = indexed_versions(self, name, &e)?.into_iter().take(100).collect();
             info!("Last versions for {} in {} env is {:?}", name, e, eres);
             if first_pass {
                 // if first pass, can't take intersection with something empty, start with first result
@@ -112,10 +508,13 @@ impl<T: Backend> CachedBackend for T {
         trace!("Locate component {}", name);
 
         let component = self.get_component_info(name, version, env)?;
+        if version.is_some() {
+            warn_if_yanked(self, name, component.version, env);
+        }
 
         if !is_cached(self, &component.name, component.version, env) {
             // download to PWD then move it to stash immediately
-            let tarball_location = stored_tarball_location(self, name, component.version, env)?;
+            let tarball_location = stored_tarball_location(self, &component, env)?;
             self.raw_fetch(&component.location, &tarball_location)?;
         }
         assert!(
@@ -124,8 +523,11 @@ impl<T: Backend> CachedBackend for T {
         );
 
         trace!("Fetching {} from cache", name);
-        let tarname =
-            get_cache_dir(self, &component.name, component.version, env).join(format!("{}.tar.gz", name));
+        let cachedir = get_cache_dir(self, &component.name, component.version, env);
+        if let Err(e) = mark_used(&cachedir) {
+            debug!("Failed to record cache use of {}: {}", cachedir.display(), e);
+        }
+        let tarname = output::find_tarball(&cachedir, name).ok_or(CliError::MissingTarball)?;
         Ok((tarname, component))
     }
 
@@ -144,8 +546,73 @@ impl<T: Backend> CachedBackend for T {
             tarname.to_str().unwrap(),
             component.name
         );
-        extract_tarball_to_input(tarname, &component_dir, name)?;
+        match self.get_input_strategy() {
+            InputStrategy::Extract => {
+                extract_tarballs_to_input(&[tarname], &component_dir, name, &self.get_extract_limits())?
+            }
+            strategy => {
+                let cache = self.get_cache_dir();
+                let tree = get_extracted_dir(&cache, &component.name, component.version, env);
+                let tree = extracted_tree(&[tarname], tree, &self.get_extract_limits())?;
+                link_tree_to_input(&tree, &component_dir, name, &strategy)?;
+            }
+        }
+
+        Ok(component)
+    }
+
+    // `fetch` for dependencies requesting specific parts
+    fn unpack_published_parts(
+        &self,
+        component_dir: &Path,
+        name: &str,
+        version: Option<u32>,
+        env: &str,
+        parts: &[String],
+    ) -> LalResult<Component> {
+        let component = self.get_component_info(name, version, env)?;
+        if version.is_some() {
+            warn_if_yanked(self, name, component.version, env);
+        }
+        let cachedir = get_cache_dir(self, &component.name, component.version, env);
+        fs::create_dir_all(&cachedir)?;
 
+        // parts are published next to the main tarball with the same compression
+        let compression = Compression::from_name(&component.location);
+        let mut tarballs = vec![];
+        for part in parts {
+            let tarname = compression.tarball_name(&output::part_name(name, part));
+            let tarball = cachedir.join(&tarname);
+            if !tarball.is_file() {
+                let location = match component.location.rfind('/') {
+                    Some(i) => format!("{}{}", &component.location[..=i], tarname),
+                    None => tarname.clone(),
+                };
+                if let Err(e) = self.raw_fetch(&location, &tarball) {
+                    let _ = fs::remove_file(&tarball);
+                    warn!("Failed to fetch part {} of {}={}", part, name, component.version);
+                    return Err(e);
+                }
+            }
+            tarballs.push(tarball);
+        }
+        if let Err(e) = mark_used(&cachedir) {
+            debug!("Failed to record cache use of {}: {}", cachedir.display(), e);
+        }
+
+        debug!("Unpacking parts {:?} of {}", parts, name);
+        match self.get_input_strategy() {
+            InputStrategy::Extract => {
+                extract_tarballs_to_input(&tarballs, &component_dir, name, &self.get_extract_limits())?
+            }
+            strategy => {
+                let cache = self.get_cache_dir();
+                let tree = get_extracted_parts_dir(&cache, &component.name, component.version, env, parts);
+                let tree = extracted_tree(&tarballs, tree, &self.get_extract_limits())?;
+                link_tree_to_input(&tree, &component_dir, name, &strategy)?;
+            }
+        }
+        input::record_parts(component_dir, name, parts)?;
         Ok(component)
     }
 
@@ -153,21 +620,18 @@ impl<T: Backend> CachedBackend for T {
     fn unpack_stashed_component(&self, component_dir: &Path, name: &str, code: &str) -> LalResult<()> {
         let tarpath = self.retrieve_stashed_component(name, code)?;
 
-        extract_tarball_to_input(tarpath, &component_dir, name)?;
+        extract_tarballs_to_input(&[tarpath], &component_dir, name, &self.get_extract_limits())?;
         Ok(())
     }
 
     /// helper for unpack_, `export`
     fn retrieve_stashed_component(&self, name: &str, code: &str) -> LalResult<PathBuf> {
-        let tarpath = Path::new(&self.get_cache_dir())
+        let stashdir = Path::new(&self.get_cache_dir())
             .join("stash")
             .join(name)
-            .join(code)
-            .join(format!("{}.tar.gz", name));
-        if !tarpath.is_file() {
-            return Err(CliError::MissingStashArtifact(format!("{}/{}", name, code)));
-        }
-        Ok(tarpath)
+            .join(code);
+        output::find_tarball(&stashdir, name)
+            .ok_or_else(|| CliError::MissingStashArtifact(format!("{}/{}", name, code)))
     }
 
     // helper for `stash`
@@ -177,10 +641,19 @@ impl<T: Backend> CachedBackend for T {
             .join(name)
             .join(code);
         debug!("Creating {:?}", destdir);
-        fs::create_dir_all(&destdir)?;
+        // start fresh so an older stash in another compression is not picked up
+        ensure_dir_exists_fresh(&destdir)?;
 
         // Tar it straight into destination
-        output::tar(&component_dir, &destdir.join(format!("{}.tar.gz", name)))?;
+        let manifest = Manifest::read(component_dir)?;
+        let compression = manifest.compression_for(name);
+        output::tar(
+            &component_dir,
+            &destdir,
+            name,
+            compression,
+            &manifest.packaging_for(name),
+        )?;
 
         // Copy the lockfile there for users inspecting the stashed folder
         // NB: this is not really needed, as it's included in the tarball anyway
@@ -190,4 +663,17 @@ impl<T: Backend> CachedBackend for T {
         )?;
         Ok(())
     }
+
+    fn refresh_index(&self, env: &str) -> LalResult<MetadataIndex> {
+        metadata::refresh(self, env)
+    }
+
+    /// The cached index is used as is until it is older than the configured ttl
+    fn get_index(&self, env: &str) -> LalResult<MetadataIndex> {
+        let index = MetadataIndex::read(&self.get_cache_dir(), env);
+        match self.get_index_ttl() {
+            Some(ttl) if index.is_fresh(ttl) => Ok(index),
+            _ => self.refresh_index(env),
+        }
+    }
 }
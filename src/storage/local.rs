@@ -1,6 +1,7 @@
 #![allow(missing_docs)]
 
 use std::{
+    collections::{BTreeMap, BTreeSet},
     fs,
     io::ErrorKind,
     path::{Path, PathBuf},
@@ -8,14 +9,17 @@ use std::{
     vec::Vec,
 };
 
-use crate::core::{config_dir, ensure_dir_exists_fresh, CliError, LalResult};
+use crate::core::{
+    config_dir, ensure_dir_exists_fresh, output, CliError, Compression, ExtractLimits, InputStrategy,
+    LalResult, Lockfile,
+};
 
 
 /// LocalBackend configuration options (currently none)
 #[derive(Serialize, Deserialize, Clone, Default)]
 pub struct LocalConfig {}
 
-use super::{Backend, Component};
+use super::{Backend, Component, YANK_MARKER};
 
 /// Artifact storage on the local machine
 pub struct LocalBackend {
@@ -23,6 +27,12 @@ pub struct LocalBackend {
     pub config: LocalConfig,
     /// Cache directory
     pub cache: PathBuf,
+    /// How to populate INPUT from the cache
+    pub input: InputStrategy,
+    /// Seconds the local metadata index can be used for
+    pub index_ttl: Option<u64>,
+    /// Limits on extracting tarballs from the cache
+    pub extract_limits: ExtractLimits,
 }
 
 impl LocalBackend {
@@ -37,8 +47,39 @@ impl LocalBackend {
         LocalBackend {
             config: cfg.clone(),
             cache: cache.to_path_buf(),
+            input: InputStrategy::default(),
+            index_ttl: None,
+            extract_limits: ExtractLimits::default(),
         }
     }
+
+    /// Use a non-default strategy for populating INPUT
+    pub fn with_input_strategy(mut self, input: InputStrategy) -> Self {
+        self.input = input;
+        self
+    }
+
+    /// Use the local metadata index for queries until it is older than `ttl` seconds
+    pub fn with_index_ttl(mut self, ttl: Option<u64>) -> Self {
+        self.index_ttl = ttl;
+        self
+    }
+
+    /// Use non-default limits when extracting tarballs
+    pub fn with_extract_limits(mut self, limits: ExtractLimits) -> Self {
+        self.extract_limits = limits;
+        self
+    }
+}
+
+// directory holding the published versions of a component in an environment
+fn component_dir(cache: &Path, name: &str, loc: &str) -> LalResult<PathBuf> {
+    let tar_dir = format!("{}/environments/{}/{}/", cache.display(), loc, name);
+    let dir = config_dir(None).join(tar_dir);
+    if !dir.exists() {
+        return Err(CliError::BackendNotFound(dir.display().to_string()));
+    }
+    Ok(dir)
 }
 
 /// Artifact backend trait for `LocalBackend`
@@ -47,22 +88,33 @@ impl LocalBackend {
 /// specific low-level use cases, these methods can be used directly.
 impl Backend for LocalBackend {
     fn get_versions(&self, name: &str, loc: &str) -> LalResult<Vec<u32>> {
-        let tar_dir = format!("{}/environments/{}/{}/", self.cache.display(), loc, name);
-        let dentries = fs::read_dir(config_dir(None).join(tar_dir));
+        let (versions, yanked) = self.get_all_versions(name, loc)?;
+        Ok(versions.into_iter().filter(|v| !yanked.contains(v)).collect())
+    }
+
+    fn get_all_versions(&self, name: &str, loc: &str) -> LalResult<(Vec<u32>, BTreeSet<u32>)> {
+        let tar_dir = component_dir(&self.cache, name, loc)?;
         let mut versions = vec![];
-        for entry in dentries? {
-            let path = entry?;
-            if let Some(filename) = path.file_name().to_str() {
-                if let Ok(version) = u32::from_str(filename) {
-                    versions.push(version);
+        let mut yanked = BTreeSet::new();
+        for entry in fs::read_dir(tar_dir)? {
+            let path = entry?.path();
+            if let Some(version) = path
+                .file_name()
+                .and_then(|f| f.to_str())
+                .and_then(|f| u32::from_str(f).ok())
+            {
+                if path.join(YANK_MARKER).exists() {
+                    yanked.insert(version);
                 }
+                versions.push(version);
             }
         }
-        Ok(versions)
+        versions.sort_by(|a, b| b.cmp(a)); // descending like the other backends
+        Ok((versions, yanked))
     }
 
     fn get_latest_version(&self, name: &str, loc: &str) -> LalResult<u32> {
-        if let Some(&last) = self.get_versions(name, loc)?.last() {
+        if let Some(&last) = self.get_versions(name, loc)?.first() {
             return Ok(last);
         }
         Err(CliError::BackendFailure(
@@ -70,6 +122,50 @@ impl Backend for LocalBackend {
         ))
     }
 
+    fn get_yanked(&self, name: &str, loc: &str) -> LalResult<BTreeMap<u32, String>> {
+        let (_, yanked) = self.get_all_versions(name, loc)?;
+        let mut reasons = BTreeMap::new();
+        for version in yanked {
+            let reason = self.get_yank_reason(name, version, loc)?;
+            reasons.insert(version, reason.unwrap_or_default());
+        }
+        Ok(reasons)
+    }
+
+    fn get_yank_reason(&self, name: &str, version: u32, loc: &str) -> LalResult<Option<String>> {
+        let tar_dir = format!(
+            "{}/environments/{}/{}/{}",
+            self.cache.display(),
+            loc,
+            name,
+            version
+        );
+        let marker = config_dir(None).join(tar_dir).join(YANK_MARKER);
+        if !marker.is_file() {
+            return Ok(None);
+        }
+        Ok(Some(fs::read_to_string(&marker)?))
+    }
+
+    fn get_components(&self, loc: &str) -> LalResult<Vec<String>> {
+        let env_dir = config_dir(None).join(format!("{}/environments/{}/", self.cache.display(), loc));
+        let mut names = vec![];
+        if !env_dir.is_dir() {
+            // nothing has been published in this environment yet
+            return Ok(names);
+        }
+        for entry in fs::read_dir(env_dir)? {
+            let path = entry?.path();
+            if let Some(name) = path.file_name().and_then(|f| f.to_str()) {
+                if path.is_dir() {
+                    names.push(name.to_string());
+                }
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
     fn get_component_info(&self, name: &str, version: Option<u32>, loc: &str) -> LalResult<Component> {
         info!("get_component_info: {} {:?} {}", name, version, loc);
 
@@ -78,21 +174,28 @@ impl Backend for LocalBackend {
         } else {
             self.get_latest_version(name, loc)?
         };
-        let loc = format!(
-            "{}/environments/{}/{}/{}/{}.tar.gz",
-            self.cache.display(),
-            loc,
-            name,
-            v,
-            name
-        );
+        let dir = format!("{}/environments/{}/{}/{}", self.cache.display(), loc, name, v);
+        // legacy gzip location if the version is not present at all
+        let tarball = output::find_tarball(Path::new(&dir), name)
+            .unwrap_or_else(|| Path::new(&dir).join(Compression::Gzip.tarball_name(name)));
         Ok(Component {
             name: name.into(),
             version: v,
-            location: loc,
+            location: tarball.display().to_string(),
         })
     }
 
+    fn get_published_lockfile(&self, name: &str, version: u32, loc: &str) -> LalResult<Lockfile> {
+        let lock_path = format!(
+            "{}/environments/{}/{}/{}/lockfile.json",
+            self.cache.display(),
+            loc,
+            name,
+            version
+        );
+        Lockfile::from_path(&config_dir(None).join(lock_path), name)
+    }
+
     fn publish_artifact(
         &self,
         home: Option<&Path>,
@@ -104,7 +207,7 @@ impl Backend for LocalBackend {
         // this fn basically assumes all the sanity checks have been performed
         // files must exist and lockfile must be sensible
         let artifactdir = component_dir.join("./ARTIFACT");
-        let tarball = artifactdir.join(format!("{}.tar.gz", name));
+        let tarball = output::find_tarball(&artifactdir, name).ok_or(CliError::MissingReleaseBuild)?;
         let lockfile = artifactdir.join("lockfile.json");
 
         // prefix with environment
@@ -116,12 +219,12 @@ impl Backend for LocalBackend {
             version
         );
         let tar_path = format!(
-            "{}/environments/{}/{}/{}/{}.tar.gz",
+            "{}/environments/{}/{}/{}/{}",
             self.cache.display(),
             env,
             name,
             version,
-            name
+            tarball.file_name().unwrap().to_string_lossy()
         );
         let lock_path = format!(
             "{}/environments/{}/{}/{}/lockfile.json",
@@ -134,9 +237,65 @@ impl Backend for LocalBackend {
         let full_tar_dir = config_dir(home).join(tar_dir);
         ensure_dir_exists_fresh(&full_tar_dir)?;
 
-        fs::copy(tarball, config_dir(home).join(tar_path))?;
+        fs::copy(&tarball, config_dir(home).join(tar_path))?;
         fs::copy(lockfile, config_dir(home).join(lock_path))?;
+        // parts and debug files split out by the packaging rules
+        for extra in output::find_tarballs(&artifactdir, name)? {
+            if extra != tarball {
+                fs::copy(&extra, full_tar_dir.join(extra.file_name().unwrap()))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn delete_artifact(&self, home: Option<&Path>, name: &str, version: u32, env: &str) -> LalResult<()> {
+        let tar_dir = format!(
+            "{}/environments/{}/{}/{}/",
+            self.cache.display(),
+            env,
+            name,
+            version
+        );
+        let full_tar_dir = config_dir(home).join(tar_dir);
+        if full_tar_dir.is_dir() {
+            fs::remove_dir_all(&full_tar_dir)?;
+        }
+        Ok(())
+    }
+
+    fn reserve_version(&self, home: Option<&Path>, name: &str, version: u32) -> LalResult<bool> {
+        let marker_dir = format!("{}/reservations/{}", self.cache.display(), name);
+        let full_marker_dir = config_dir(home).join(marker_dir);
+        fs::create_dir_all(&full_marker_dir)?;
+        // creating a directory is atomic, so only one publisher can get it
+        match fs::create_dir(full_marker_dir.join(version.to_string())) {
+            Ok(_) => Ok(true),
+            Err(ref e) if e.kind() == ErrorKind::AlreadyExists => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
 
+    fn yank_version(
+        &self,
+        home: Option<&Path>,
+        name: &str,
+        version: u32,
+        env: &str,
+        reason: &str,
+    ) -> LalResult<()> {
+        let tar_dir = format!(
+            "{}/environments/{}/{}/{}/",
+            self.cache.display(),
+            env,
+            name,
+            version
+        );
+        let full_tar_dir = config_dir(home).join(tar_dir);
+        if !full_tar_dir.is_dir() {
+            return Err(CliError::MissingPublishedVersion(name.into(), version));
+        }
+        fs::write(full_tar_dir.join(YANK_MARKER), reason)?;
         Ok(())
     }
 
@@ -144,6 +303,18 @@ impl Backend for LocalBackend {
         self.cache.clone()
     }
 
+    fn get_input_strategy(&self) -> InputStrategy {
+        self.input.clone()
+    }
+
+    fn get_index_ttl(&self) -> Option<u64> {
+        self.index_ttl
+    }
+
+    fn get_extract_limits(&self) -> ExtractLimits {
+        self.extract_limits.clone()
+    }
+
     fn raw_fetch(&self, src: &str, dest: &Path) -> LalResult<()> {
         debug!("raw fetch {} -> {}", src, dest.display());
         fs::copy(src, dest)?;
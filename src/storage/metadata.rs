@@ -0,0 +1,177 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::{self, File},
+    io::prelude::*,
+    path::{Path, PathBuf},
+};
+
+use chrono::UTC;
+
+use super::Backend;
+use crate::core::{LalResult, Lockfile};
+
+/// The parts of a published `lockfile.json` kept in the index
+#[derive(Serialize, Deserialize, Clone)]
+pub struct LockfileSummary {
+    /// Built timestamp
+    pub built: Option<String>,
+    /// Revision id from version control
+    pub sha: Option<String>,
+    /// Version of the lal tool
+    pub tool: String,
+    /// Versions of the direct dependencies
+    pub dependencies: BTreeMap<String, String>,
+    /// Versions of every dependency anywhere in the tree
+    pub tree: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl From<&Lockfile> for LockfileSummary {
+    fn from(lock: &Lockfile) -> Self {
+        LockfileSummary {
+            built: lock.built.clone(),
+            sha: lock.sha.clone(),
+            tool: lock.tool.clone(),
+            dependencies: lock
+                .dependencies
+                .iter()
+                .map(|(k, v)| (k.clone(), v.version.clone()))
+                .collect(),
+            tree: lock.find_all_dependency_versions().into_iter().collect(),
+        }
+    }
+}
+
+/// A published version of a component in the index
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct IndexedVersion {
+    /// Reason for yanking the version, if it has been yanked
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub yanked: Option<String>,
+    /// Summary of the published lockfile, if one could be found
+    pub lockfile: Option<LockfileSummary>,
+}
+
+/// Locally cached metadata of the published components in an environment
+///
+/// Stored as `index/<environment>.json` in the cache directory.
+#[derive(Serialize, Deserialize, Default)]
+pub struct MetadataIndex {
+    /// Environment the components were published in
+    pub environment: String,
+    /// Unix timestamp of the last refresh from the backend
+    pub refreshed: i64,
+    /// Every version of every component published in the environment
+    pub components: BTreeMap<String, BTreeMap<u32, IndexedVersion>>,
+}
+
+impl MetadataIndex {
+    /// Location of the index of an environment in a cache directory
+    pub fn path(cache: &Path, env: &str) -> PathBuf {
+        cache.join("index").join(format!("{}.json", env))
+    }
+
+    /// Read the index of an environment from a cache directory
+    ///
+    /// A missing or unreadable index is returned as an empty one that needs refreshing.
+    pub fn read(cache: &Path, env: &str) -> MetadataIndex {
+        let pth = MetadataIndex::path(cache, env);
+        let empty = MetadataIndex {
+            environment: env.into(),
+            ..Default::default()
+        };
+        if !pth.exists() {
+            return empty;
+        }
+        let mut data = String::new();
+        if let Err(e) = File::open(&pth).and_then(|mut f| f.read_to_string(&mut data)) {
+            warn!("Ignoring unreadable index {}: {}", pth.display(), e);
+            return empty;
+        }
+        serde_json::from_str(&data).unwrap_or_else(|e| {
+            warn!("Ignoring unreadable index {}: {}", pth.display(), e);
+            empty
+        })
+    }
+
+    /// Write the index to a cache directory
+    pub fn write(&self, cache: &Path) -> LalResult<()> {
+        let pth = MetadataIndex::path(cache, &self.environment);
+        fs::create_dir_all(pth.parent().unwrap())?;
+        let encoded = serde_json::to_string_pretty(self)?;
+        let mut f = File::create(&pth)?;
+        writeln!(f, "{}", encoded)?;
+        Ok(())
+    }
+
+    /// Whether the index was refreshed less than `ttl` seconds ago
+    pub fn is_fresh(&self, ttl: u64) -> bool {
+        UTC::now().timestamp() - self.refreshed < ttl as i64
+    }
+
+    /// Versions of a component that have not been yanked in descending order
+    pub fn versions(&self, name: &str) -> Vec<u32> {
+        match self.components.get(name) {
+            Some(vs) => vs
+                .iter()
+                .rev()
+                .filter(|&(_, iv)| iv.yanked.is_none())
+                .map(|(&v, _)| v)
+                .collect(),
+            None => vec![],
+        }
+    }
+
+    /// The latest version of a component that has not been yanked
+    pub fn latest(&self, name: &str) -> Option<(u32, &IndexedVersion)> {
+        self.components
+            .get(name)?
+            .iter()
+            .rev()
+            .find(|&(_, iv)| iv.yanked.is_none())
+            .map(|(&v, iv)| (v, iv))
+    }
+}
+
+/// Bring the index of an environment up to date with a backend
+///
+/// Versions and yank status are listed again for every component, but only the
+/// lockfiles of versions that are new to the index are fetched.
+/// The index is only written once every listing succeeded, so a failing backend
+/// never replaces it with an incomplete one.
+pub fn refresh(backend: &dyn Backend, env: &str) -> LalResult<MetadataIndex> {
+    let cache = backend.get_cache_dir();
+    let mut index = MetadataIndex::read(&cache, env);
+    let names = backend.get_components(env)?;
+    let mut known = std::mem::take(&mut index.components);
+
+    for name in names {
+        let (versions, yanked) = backend.get_all_versions(&name, env)?;
+        let mut previous = known.remove(&name).unwrap_or_default();
+
+        let mut indexed = BTreeMap::new();
+        for v in versions {
+            let mut iv: IndexedVersion = previous.remove(&v).unwrap_or_default();
+            if !yanked.contains(&v) {
+                iv.yanked = None;
+            } else if iv.yanked.is_none() {
+                // reasons of versions already known to be yanked are kept from the last refresh
+                let reason = backend.get_yank_reason(&name, v, env)?.unwrap_or_default();
+                iv.yanked = Some(reason.trim().to_string());
+            }
+            if iv.lockfile.is_none() {
+                debug!("Indexing {}={} in {}", name, v, env);
+                match backend.get_published_lockfile(&name, v, env) {
+                    Ok(lock) => iv.lockfile = Some(LockfileSummary::from(&lock)),
+                    Err(e) => debug!("No lockfile for {}={} in {}: {}", name, v, env, e),
+                }
+            }
+            indexed.insert(v, iv);
+        }
+        if !indexed.is_empty() {
+            index.components.insert(name, indexed);
+        }
+    }
+    index.refreshed = UTC::now().timestamp();
+    index.write(&cache)?;
+    Ok(index)
+}
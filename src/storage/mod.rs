@@ -1,4 +1,8 @@
-pub use self::traits::{Backend, BackendConfiguration, CachedBackend, Component};
+pub use self::traits::{Backend, BackendConfiguration, CachedBackend, Component, YANK_MARKER};
+
+pub use self::download::{cache_last_use, extracted_tree_in_use, get_extracted_dir, remove_extracted_tree};
+
+pub use self::metadata::{IndexedVersion, LockfileSummary, MetadataIndex};
 
 pub use self::{
     artifactory::{ArtifactoryBackend, ArtifactoryConfig, Credentials},
@@ -12,6 +16,7 @@ pub use self::artifactory::{get_latest_lal_version, http_download_to_path, Lates
 mod artifactory;
 mod download;
 mod local;
+mod metadata;
 mod traits;
 
 #[cfg(feature = "progress")] mod progress;
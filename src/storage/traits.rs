@@ -1,7 +1,15 @@
-use std::path::{Path, PathBuf};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
 
-use super::{ArtifactoryConfig, LocalConfig};
-use crate::core::LalResult;
+use super::{ArtifactoryConfig, LocalConfig, MetadataIndex};
+use crate::core::{ExtractLimits, InputStrategy, LalResult, Lockfile};
+
+/// Name of the marker file next to a published artifact that has been yanked
+///
+/// The contents of the marker is the reason given for yanking it.
+pub const YANK_MARKER: &str = "YANKED";
 
 /// An enum struct for the currently configured `Backend`
 ///
@@ -47,15 +55,33 @@ pub struct Component {
 /// We do rely on there being a basic API that can implement this trait though.
 pub trait Backend {
     /// Get a list of versions for a component in descending order
+    ///
+    /// Yanked versions are not included.
     fn get_versions(&self, name: &str, loc: &str) -> LalResult<Vec<u32>>;
-    /// Get the latest version of a component
+    /// Get the latest version of a component that has not been yanked
     fn get_latest_version(&self, name: &str, loc: &str) -> LalResult<u32>;
+    /// Get every version of a component in descending order, and which of them are yanked
+    ///
+    /// Unlike `get_versions`, yanked versions are included. This is a single listing,
+    /// the reasons for yanking are not looked up.
+    fn get_all_versions(&self, name: &str, loc: &str) -> LalResult<(Vec<u32>, BTreeSet<u32>)>;
+    /// Get the yanked versions of a component along with the reasons for yanking them
+    fn get_yanked(&self, name: &str, loc: &str) -> LalResult<BTreeMap<u32, String>>;
+    /// Get the reason a version of a component was yanked for, or `None` if it was not
+    fn get_yank_reason(&self, name: &str, version: u32, loc: &str) -> LalResult<Option<String>>;
+    /// Get the names of all components published in a location
+    fn get_components(&self, loc: &str) -> LalResult<Vec<String>>;
 
     /// Get the version and location information of a component
     ///
     /// If no version is given, figure out what latest is
     fn get_component_info(&self, name: &str, ver: Option<u32>, loc: &str) -> LalResult<Component>;
 
+    /// Get the lockfile published with a version of a component
+    ///
+    /// This is a small download compared to the tarball, and is enough to describe the build.
+    fn get_published_lockfile(&self, name: &str, version: u32, loc: &str) -> LalResult<Lockfile>;
+
     /// Publish a release build's ARTIFACT to a specific location
     ///
     /// This will publish everything inside the ARTIFACT dir created by `lal build -r`
@@ -68,6 +94,28 @@ pub trait Backend {
         env: &str,
     ) -> LalResult<()>;
 
+    /// Remove a published version of a component from a specific location
+    ///
+    /// Used to roll back publishes that did not make it to every environment.
+    fn delete_artifact(&self, home: Option<&Path>, name: &str, version: u32, env: &str) -> LalResult<()>;
+
+    /// Reserve a version of a component for a publish in progress
+    ///
+    /// Returns false if the version was already reserved by another publisher.
+    fn reserve_version(&self, home: Option<&Path>, name: &str, version: u32) -> LalResult<bool>;
+
+    /// Mark a published version of a component as yanked in a specific location
+    ///
+    /// This writes a `YANK_MARKER` with the reason next to the artifact.
+    fn yank_version(
+        &self,
+        home: Option<&Path>,
+        name: &str,
+        version: u32,
+        env: &str,
+        reason: &str,
+    ) -> LalResult<()>;
+
     /// Raw fetch of location to a destination
     ///
     /// location can be a HTTPS url / a system path / etc (depending on the backend)
@@ -77,6 +125,22 @@ pub trait Backend {
     ///
     /// This has to be in here for `CachedBackend` to have a straight dependency
     fn get_cache_dir(&self) -> PathBuf;
+
+    /// Return how cached components should be placed into `INPUT`
+    ///
+    /// Like `get_cache_dir`, this is here for `CachedBackend` to use
+    fn get_input_strategy(&self) -> InputStrategy;
+
+    /// Return how many seconds the local metadata index can be used before refreshing
+    ///
+    /// Like `get_cache_dir`, this is here for `CachedBackend` to use.
+    /// If `None`, the backend is queried directly instead.
+    fn get_index_ttl(&self) -> Option<u64>;
+
+    /// Return the limits on extracting a single tarball
+    ///
+    /// Like `get_cache_dir`, this is here for `CachedBackend` to use.
+    fn get_extract_limits(&self) -> ExtractLimits;
 }
 
 /// A secondary trait that builds upon the Backend trait
@@ -106,9 +170,25 @@ pub trait CachedBackend: Backend {
         env: &str,
     ) -> LalResult<Component>;
 
+    /// Retrieve and unpack only some named parts of a cached component in INPUT
+    fn unpack_published_parts(
+        &self,
+        component_dir: &Path,
+        name: &str,
+        version: Option<u32>,
+        env: &str,
+        parts: &[String],
+    ) -> LalResult<Component>;
+
     /// Retrieve and unpack a stashed component to INPUT
     fn unpack_stashed_component(&self, component_dir: &Path, name: &str, code: &str) -> LalResult<()>;
 
     /// Add a stashed component from a folder
     fn stash_output(&self, component_dir: &Path, name: &str, code: &str) -> LalResult<()>;
+
+    /// Bring the local metadata index of a location up to date
+    fn refresh_index(&self, env: &str) -> LalResult<MetadataIndex>;
+
+    /// Get the local metadata index of a location, refreshing it if it is stale
+    fn get_index(&self, env: &str) -> LalResult<MetadataIndex>;
 }
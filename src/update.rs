@@ -1,30 +1,29 @@
-use super::{CliError, LalResult, Manifest};
-use crate::storage::CachedBackend;
-use std::{cmp::Ordering, path::Path};
+use super::{
+    input::{self, InputTransaction},
+    plan::{Action, ComponentAction, ManifestChange, Plan},
+    CliError, LalResult, Manifest,
+};
+use crate::{fetch::unpack_dependency, storage::CachedBackend};
+use std::path::Path;
 
-/// Update specific dependencies outside the manifest
-///
-/// Multiple "components=version" strings can be supplied, where the version is optional.
-/// If no version is supplied, latest is fetched.
+/// Work out what `update` would do without changing anything
 ///
-/// If installation was successful, the fetched tarballs are unpacked into `./INPUT`.
-/// If one `save` or `savedev` was set, the fetched versions are also updated in the
-/// manifest. This provides an easy way to not have to deal with strict JSON manually.
-pub fn update(
+/// Versions are resolved against the backend like `update` does (latest if not given),
+/// but nothing is fetched, and the manifest is not written.
+pub fn plan_update(
     component_dir: &Path,
     manifest: &Manifest,
     backend: &dyn CachedBackend,
-    components: Vec<String>,
+    components: &[String],
     save: bool,
     savedev: bool,
     env: &str,
-) -> LalResult<()> {
-    debug!("Update specific deps: {:?}", components);
+) -> LalResult<Plan> {
+    // versions currently in INPUT (only for reporting)
+    let present = input::analyze(component_dir).unwrap_or_default();
 
-    let mut error = None;
-    let mut updated = Vec::with_capacity(components.len());
-    for comp in &components {
-        info!("Fetch {} {}", env, comp);
+    let mut plan = Plan::default();
+    for comp in components {
         if comp.contains('=') {
             let pair: Vec<&str> = comp.split('=').collect();
             if let Ok(n) = pair[1].parse::<u32>() {
@@ -32,22 +31,24 @@ pub fn update(
                     return Err(CliError::InvalidComponentName(pair[0].into()));
                 }
                 // standard fetch with an integer version
-                match backend.unpack_published_component(&component_dir, pair[0], Some(n), env) {
-                    Ok(c) => updated.push(c),
-                    Err(e) => {
-                        warn!("Failed to update {} ({})", pair[0], e);
-                        error = Some(e);
-                    }
-                }
+                let dep_env = manifest.environment_for(pair[0], env);
+                plan.actions.push(ComponentAction {
+                    name: pair[0].into(),
+                    action: Action::Download,
+                    version: Some(n.to_string()),
+                    environment: Some(dep_env.into()),
+                    previous: present.get(pair[0]).cloned(),
+                });
             } else {
-                // fetch from stash - this does not go into `updated` it it succeeds
+                // fetch from stash - this does not go into the manifest
                 // because we wont and cannot save stashed versions in the manifest
-                let _ = backend
-                    .unpack_stashed_component(&component_dir, pair[0], pair[1])
-                    .map_err(|e| {
-                        warn!("Failed to update {} from stash ({})", pair[0], e);
-                        error = Some(e);
-                    });
+                plan.actions.push(ComponentAction {
+                    name: pair[0].into(),
+                    action: Action::Unstash,
+                    version: Some(pair[1].into()),
+                    environment: None,
+                    previous: present.get(pair[0]).cloned(),
+                });
             }
         } else {
             if &comp.to_lowercase() != comp {
@@ -57,60 +58,151 @@ pub fn update(
 
             // First, since this potentially goes in the manifest
             // make sure the version is found for all supported environments:
-            let supported_versions = backend.get_latest_supported_versions(comp, vec![env.to_string()])?;
+            let dep_env = manifest.environment_for(comp, env);
+            let supported_versions =
+                backend.get_latest_supported_versions(comp, vec![dep_env.to_string()])?;
 
             let ver = supported_versions
                 .into_iter()
                 .max()
                 .ok_or_else(|| CliError::NoIntersectedVersion(comp.clone()))?;
-            info!("Fetch {} {}={}", env, comp, ver);
-
-            match backend.unpack_published_component(&component_dir, comp, Some(ver), env) {
-                Ok(c) => updated.push(c),
-                Err(e) => {
-                    warn!("Failed to update {} ({})", &comp, e);
-                    error = Some(e);
-                }
-            }
+            plan.actions.push(ComponentAction {
+                name: comp.clone(),
+                action: Action::Download,
+                version: Some(ver.to_string()),
+                environment: Some(dep_env.into()),
+                previous: present.get(comp).cloned(),
+            });
         }
     }
-    if let Some(e) = error {
-        return Err(e);
-    }
 
     // Update manifest if saving in any way
     if save || savedev {
-        let mut mf = manifest.clone();
         // find reference to correct list
-        let mut hmap = if save {
-            mf.dependencies.clone()
+        let deps = if save {
+            &manifest.dependencies
         } else {
-            mf.devDependencies.clone()
+            &manifest.devDependencies
         };
-        for c in &updated {
-            debug!("Successfully updated {} at version {}", &c.name, c.version);
-            if hmap.contains_key(&c.name) {
-                let val = hmap.get_mut(&c.name).unwrap();
-                match c.version.cmp(&*val) {
-                    Ordering::Greater => info!("Upgrading {} from {} to {}", c.name, *val, c.version),
-                    Ordering::Less => warn!("Downgrading {} from {} to {}", c.name, *val, c.version),
-                    Ordering::Equal => info!("Maintaining {} at version {}", c.name, c.version),
-                }
-                *val = c.version;
+        for a in plan.actions.iter().filter(|a| a.action == Action::Download) {
+            let to = a.version.as_ref().and_then(|v| v.parse().ok());
+            let from = deps.get(&a.name).cloned();
+            if from != to {
+                plan.manifest.push(ManifestChange {
+                    name: a.name.clone(),
+                    dev: !save,
+                    from,
+                    to,
+                });
             } else {
-                hmap.insert(c.name.clone(), c.version);
+                debug!(
+                    "Maintaining {} at version {}",
+                    a.name,
+                    a.version.as_ref().unwrap()
+                );
             }
         }
-        if save {
-            mf.dependencies = hmap;
-        } else {
-            mf.devDependencies = hmap;
+    }
+    Ok(plan)
+}
+
+/// Update specific dependencies outside the manifest
+///
+/// Multiple "components=version" strings can be supplied, where the version is optional.
+/// If no version is supplied, latest is fetched.
+///
+/// If installation was successful, the fetched tarballs are unpacked into `./INPUT`.
+/// They are staged first, so if any of them fails `./INPUT` is left as it was.
+/// If one `save` or `savedev` was set, the fetched versions are also updated in the
+/// manifest. This provides an easy way to not have to deal with strict JSON manually.
+pub fn update(
+    component_dir: &Path,
+    manifest: &Manifest,
+    backend: &dyn CachedBackend,
+    components: Vec<String>,
+    save: bool,
+    savedev: bool,
+    env: &str,
+) -> LalResult<()> {
+    debug!("Update specific deps: {:?}", components);
+    let plan = plan_update(component_dir, manifest, backend, &components, save, savedev, env)?;
+
+    // staged so that a failed update leaves INPUT as it was
+    let mut tx = InputTransaction::begin(component_dir)?;
+    let mut error = None;
+    for a in &plan.actions {
+        let version = a.version.as_ref().unwrap();
+        let res = match a.action {
+            Action::Download => {
+                let dep_env = a.environment.as_ref().unwrap();
+                let n = version.parse().unwrap(); // planned as an integer
+                info!("Fetch {} {}={}", dep_env, a.name, n);
+                unpack_dependency(backend, manifest, tx.staging_dir(), &a.name, n, dep_env)
+            }
+            Action::Unstash => {
+                info!("Fetch stashed {}={}", a.name, version);
+                backend.unpack_stashed_component(tx.staging_dir(), &a.name, version)
+            }
+            Action::Reuse | Action::Remove => unreachable!("update only fetches"),
+        };
+        match res {
+            Ok(()) => tx.stage(&a.name),
+            Err(e) => {
+                warn!("Failed to update {} ({})", a.name, e);
+                error = Some(e);
+            }
+        }
+    }
+    if let Some(e) = error {
+        return Err(e);
+    }
+    tx.commit()?;
+
+    if save || savedev {
+        let mut mf = manifest.clone();
+        for c in &plan.manifest {
+            match (c.from, c.to) {
+                (Some(from), Some(to)) if to > from => info!("Upgrading {} from {} to {}", c.name, from, to),
+                (Some(from), Some(to)) => warn!("Downgrading {} from {} to {}", c.name, from, to),
+                _ => info!("Adding {} to the manifest", c.name),
+            }
+            c.apply(&mut mf);
         }
         mf.write()?;
     }
     Ok(())
 }
 
+// the dependencies or devDependencies updated by `update_all`
+fn all_components(manifest: &Manifest, dev: bool) -> Vec<String> {
+    if dev {
+        manifest.devDependencies.keys().cloned().collect()
+    } else {
+        manifest.dependencies.keys().cloned().collect()
+    }
+}
+
+/// Work out what `update_all` would do without changing anything
+pub fn plan_update_all(
+    component_dir: &Path,
+    manifest: &Manifest,
+    backend: &dyn CachedBackend,
+    save: bool,
+    dev: bool,
+    env: &str,
+) -> LalResult<Plan> {
+    let deps = all_components(manifest, dev);
+    plan_update(
+        &component_dir,
+        manifest,
+        backend,
+        &deps,
+        save && !dev,
+        save && dev,
+        env,
+    )
+}
+
 /// Wrapper around update that updates all components
 ///
 /// This will pass all dependencies or devDependencies to update.
@@ -124,11 +216,7 @@ pub fn update_all(
     dev: bool,
     env: &str,
 ) -> LalResult<()> {
-    let deps: Vec<String> = if dev {
-        manifest.devDependencies.keys().cloned().collect()
-    } else {
-        manifest.dependencies.keys().cloned().collect()
-    };
+    let deps = all_components(manifest, dev);
     update(
         &component_dir,
         manifest,
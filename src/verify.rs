@@ -44,8 +44,8 @@ pub fn verify(component_dir: &Path, m: &Manifest, env: &str, simple: bool) -> La
         input::verify_consistent_dependency_versions(&lf, m)?;
     }
 
-    // 5. verify all components are built in the same environment
-    input::verify_environment_consistency(&lf, env)?;
+    // 5. verify all components are built in the same environment (or their declared one)
+    input::verify_environment_consistency(lf, m, env)?;
 
     info!("Dependencies fully verified");
     Ok(())
@@ -0,0 +1,58 @@
+use std::path::Path;
+
+use super::{CliError, LalResult};
+use crate::storage::CachedBackend;
+
+/// Withdraw a published version of a component
+///
+/// Writes a yank marker with the reason next to the artifact in every one of
+/// `environments` where `component=version` is published.
+/// Nothing is yanked if the versions in any environment cannot be listed.
+/// Yanked versions are skipped by `lal update` and `lal query --latest`,
+/// but can still be fetched when pinned in a manifest (with a warning).
+pub fn yank(
+    home: Option<&Path>,
+    backend: &dyn CachedBackend,
+    environments: Vec<String>,
+    component: &str,
+    reason: Option<&str>,
+) -> LalResult<()> {
+    let pair: Vec<&str> = component.split('=').collect();
+    if pair.len() != 2 {
+        return Err(CliError::InvalidVersionSpec(component.into()));
+    }
+    let name = pair[0];
+    let version = pair[1]
+        .parse::<u32>()
+        .map_err(|_| CliError::InvalidVersionSpec(component.into()))?;
+    if name.to_lowercase() != name {
+        return Err(CliError::InvalidComponentName(name.into()));
+    }
+
+    // list every environment first, so a failing one does not leave a partial yank
+    let mut targets = vec![];
+    for env in &environments {
+        let (published, already_yanked) = match backend.get_all_versions(name, env) {
+            Ok((versions, yanked)) => (versions.contains(&version), yanked.contains(&version)),
+            // components that were never published here have nothing to yank
+            Err(CliError::BackendNotFound(_)) => (false, false),
+            Err(e) => return Err(e),
+        };
+        if already_yanked {
+            info!("Updating the yank reason of {}={} in {}", name, version, env);
+        } else if published {
+            info!("Yanking {}={} from {}", name, version, env);
+        } else {
+            debug!("{}={} is not published in {}", name, version, env);
+            continue;
+        }
+        targets.push(env);
+    }
+    if targets.is_empty() {
+        return Err(CliError::MissingPublishedVersion(name.into(), version));
+    }
+    for env in targets {
+        backend.yank_version(home, name, version, env, reason.unwrap_or(""))?;
+    }
+    Ok(())
+}
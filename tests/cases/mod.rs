@@ -1,14 +1,21 @@
 mod test_backend;
 mod test_build;
+mod test_cache;
 mod test_clean;
 mod test_envs;
 mod test_export;
 mod test_fetch;
+mod test_history;
+mod test_hooks;
+mod test_index;
 mod test_init;
 mod test_list;
+mod test_outdated;
 mod test_propagate;
+mod test_ps;
 mod test_publish;
 mod test_query;
+mod test_rdeps;
 mod test_remove;
 mod test_shell;
 mod test_stash;
@@ -16,3 +23,4 @@ mod test_status;
 mod test_update;
 mod test_upgrade;
 mod test_verify;
+mod test_yank;
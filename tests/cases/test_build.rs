@@ -1,6 +1,6 @@
 use crate::common::*;
 use parameterized_macro::parameterized;
-use std::process::Command;
+use std::{os::unix::fs::PermissionsExt, process::Command};
 
 #[parameterized(env_name = {"default", "alpine"})]
 fn test_build(env_name: &str) {
@@ -141,3 +141,301 @@ fn test_build_with_release_create_tar_compatible_output(env_name: &str) {
         assert!(metadata.is_file());
     }
 }
+
+// names of the files in a gzipped tarball, as listed by `tar`
+fn tarball_files(tarball: &std::path::Path) -> Vec<String> {
+    let output = Command::new("tar")
+        .arg("tzf")
+        .arg(tarball)
+        .output()
+        .expect("listed tarball");
+    assert!(output.status.success(), "tar could list {:?}", tarball);
+    let mut files: Vec<String> = String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|l| l.trim_start_matches("./").to_string())
+        .filter(|l| !l.is_empty())
+        .collect();
+    files.sort();
+    files
+}
+
+#[parameterized(env_name = {"default", "alpine"})]
+fn test_build_with_packaging_rules(env_name: &str) {
+    let state = setup();
+    if !cfg!(feature = "docker") && env_name == "alpine" {
+        return;
+    }
+
+    let component_dir = clone_component_dir("heylib", &state);
+    let mut manifest = lal::Manifest::read(&component_dir).expect("read heylib manifest");
+    manifest.components.get_mut("heylib").unwrap().packaging = Some(lal::Packaging {
+        include: vec!["*.h".into(), "*.a".into()],
+        exclude: vec!["*.o".into()],
+        debug: vec!["libhey.*".into()],
+        maxSize: Some("1M".into()),
+        ..Default::default()
+    });
+    manifest.write().expect("write heylib manifest");
+
+    let r = fetch::fetch_input(&component_dir, &env_name, &state.backend);
+    assert!(r.is_ok(), "installed heylib dependencies: {:?}", r);
+    let r = build::build_for_release(&component_dir, &env_name, &state.tempdir.path(), "1");
+    assert!(r.is_ok(), "built heylib release: {:?}", r);
+
+    let artdir = component_dir.join("ARTIFACT");
+    assert_eq!(
+        tarball_files(&artdir.join("heylib.tar.gz")),
+        vec!["hey.h", "lockfile.json"]
+    );
+    assert_eq!(
+        tarball_files(&artdir.join("heylib-debug.tar.gz")),
+        vec!["libhey.a"]
+    );
+
+    // the debug tarball is published alongside the main one
+    let r = publish::publish_release(&component_dir, &state.backend, &state.tempdir.path());
+    assert!(r.is_ok(), "published heylib=1 release: {:?}", r);
+    let published = state
+        .backend
+        .cache
+        .join("environments")
+        .join(env_name)
+        .join("heylib/1");
+    assert!(published.join("heylib-debug.tar.gz").is_file());
+
+    // way too small artifact limit
+    manifest.components.get_mut("heylib").unwrap().packaging = Some(lal::Packaging {
+        maxSize: Some("10".into()),
+        ..Default::default()
+    });
+    manifest.write().expect("write heylib manifest");
+    let r = build::build_for_release(&component_dir, &env_name, &state.tempdir.path(), "2");
+    match r {
+        Err(lal::CliError::ArtifactTooLarge(ref name, _, 10)) => assert_eq!(name, "heylib.tar.gz"),
+        _ => panic!("built heylib despite maxSize: {:?}", r),
+    }
+}
+
+#[parameterized(env_name = {"default", "alpine"})]
+fn test_build_with_steps(env_name: &str) {
+    let state = setup();
+    if !cfg!(feature = "docker") && env_name == "alpine" {
+        return;
+    }
+
+    let component_dir = clone_component_dir("heylib", &state);
+    std::fs::remove_file(component_dir.join("BUILD")).expect("remove BUILD");
+    let mut manifest = lal::Manifest::read(&component_dir).expect("read heylib manifest");
+    let mut env = std::collections::BTreeMap::new();
+    env.insert("GREETING".to_string(), "hello".to_string());
+    let steps = vec![
+        lal::BuildStep {
+            name: Some("compile".into()),
+            run: "make clean all".into(),
+            workdir: None,
+            env: Default::default(),
+        },
+        lal::BuildStep {
+            name: None,
+            run: "echo \"$GREETING $LAL_COMPONENT $LAL_CONFIGURATION $LAL_VERSION $LAL_ENV\" > greeting"
+                .into(),
+            workdir: Some("OUTPUT".into()),
+            env,
+        },
+    ];
+    let settings = manifest.components.get_mut("heylib").unwrap();
+    settings.build.insert("release".into(), steps);
+    manifest.write().expect("write heylib manifest");
+
+    fetch::fetch_input(&component_dir, env_name, &state.backend).expect("fetched heylib dependencies");
+    let r = build::build_for_release(&component_dir, env_name, state.tempdir.path(), "1");
+    assert!(r.is_ok(), "built heylib with build steps: {:?}", r);
+    assert!(component_dir.join("OUTPUT/libhey.a").is_file(), "compiled");
+    let greeting = std::fs::read_to_string(component_dir.join("OUTPUT/greeting")).expect("read greeting");
+    assert_eq!(greeting, format!("hello heylib release 1 {}\n", env_name));
+
+    // a failing step fails the build with its name
+    let settings = manifest.components.get_mut("heylib").unwrap();
+    settings.build.get_mut("release").unwrap()[0].run = "exit 4".into();
+    manifest.write().expect("write heylib manifest");
+    let r = build::build_for_release(&component_dir, env_name, state.tempdir.path(), "2");
+    match r {
+        Err(lal::CliError::BuildStepFailure(ref name, 4)) => assert_eq!(name, "compile"),
+        _ => panic!("build should fail on a failing step: {:?}", r),
+    }
+
+    // steps must belong to a configuration and stay within the component
+    let settings = manifest.components.get_mut("heylib").unwrap();
+    settings.build.get_mut("release").unwrap()[1].workdir = Some("../elsewhere".into());
+    assert!(manifest.verify().is_err(), "workdir outside the component");
+    let settings = manifest.components.get_mut("heylib").unwrap();
+    let steps = settings.build.remove("release").unwrap();
+    settings.build.insert("debug".into(), steps);
+    assert!(manifest.verify().is_err(), "steps for an unknown configuration");
+}
+
+#[parameterized(env_name = {"default", "alpine"})]
+fn test_build_with_timeout(env_name: &str) {
+    let state = setup();
+    if !cfg!(feature = "docker") && env_name == "alpine" {
+        return;
+    }
+
+    let component_dir = clone_component_dir("heylib", &state);
+    let build_script = component_dir.join("BUILD");
+    std::fs::write(&build_script, "#!/bin/sh\nsleep 30\n").expect("write BUILD");
+    std::fs::set_permissions(&build_script, std::fs::Permissions::from_mode(0o755)).expect("chmod BUILD");
+    let mut manifest = lal::Manifest::read(&component_dir).expect("read heylib manifest");
+    manifest.components.get_mut("heylib").unwrap().limits = lal::ResourceLimits {
+        timeout: Some(1),
+        ..Default::default()
+    };
+    manifest.write().expect("write heylib manifest");
+
+    fetch::fetch_input(&component_dir, env_name, &state.backend).expect("fetched heylib dependencies");
+    let start = std::time::Instant::now();
+    let r = build::build_for_release(&component_dir, env_name, state.tempdir.path(), "1");
+    match r {
+        Err(lal::CliError::Timeout(1)) => {}
+        _ => panic!("build should time out: {:?}", r),
+    }
+    assert!(start.elapsed().as_secs() < 20, "build was stopped");
+
+    // the timeout covers all build steps together
+    let mut manifest = lal::Manifest::read(&component_dir).expect("read heylib manifest");
+    let settings = manifest.components.get_mut("heylib").unwrap();
+    settings.limits.timeout = Some(2);
+    let step = lal::BuildStep {
+        name: None,
+        run: "sleep 1".into(),
+        workdir: None,
+        env: Default::default(),
+    };
+    settings
+        .build
+        .insert("release".into(), vec![step.clone(), step.clone(), step]);
+    manifest.write().expect("write heylib manifest");
+    let r = build::build_for_release(&component_dir, env_name, state.tempdir.path(), "2");
+    match r {
+        Err(lal::CliError::Timeout(2)) => {}
+        _ => panic!("steps should share the timeout: {:?}", r),
+    }
+}
+
+#[test]
+fn test_resource_limits() {
+    let env = lal::ResourceLimits {
+        cpus: Some(2.0),
+        memory: Some("4g".into()),
+        timeout: Some(3600),
+        ..Default::default()
+    };
+    let component = lal::ResourceLimits {
+        cpus: Some(0.5),
+        pids: Some(100),
+        ..Default::default()
+    };
+    let limits = env.merge(&component);
+    assert_eq!(limits.timeout, Some(3600));
+    assert_eq!(
+        limits.docker_args(),
+        vec!["--cpus=0.5", "--memory=4g", "--pids-limit=100"]
+    );
+    assert!(lal::ResourceLimits::default().docker_args().is_empty());
+}
+
+#[parameterized(env_name = {"default", "alpine"})]
+fn test_build_matrix(env_name: &str) {
+    let state = setup();
+    if !cfg!(feature = "docker") && env_name == "alpine" {
+        return;
+    }
+
+    let component_dir = clone_component_dir("heylib", &state);
+    let mut manifest = lal::Manifest::read(&component_dir).expect("read heylib manifest");
+    manifest
+        .components
+        .get_mut("heylib")
+        .unwrap()
+        .configurations
+        .push("debug".into());
+    manifest.supportedEnvironments = vec![env_name.into()];
+    manifest.write().expect("write heylib manifest");
+
+    let config = lal::Config::read(Some(&state.tempdir.path())).expect("read config");
+    let mut build_opts = build::options(Some(&state.tempdir.path()), &env_name).expect("build options");
+    build_opts.configuration = None;
+    build_opts.version = Some("1".into());
+    let matrix = lal::MatrixOptions::default();
+    let r = lal::build_matrix(
+        &component_dir,
+        &config,
+        &manifest,
+        &state.backend,
+        &build_opts,
+        &matrix,
+        lal::ShellModes::default(),
+    );
+    assert!(r.is_ok(), "built the heylib matrix: {:?}", r);
+
+    let stage = component_dir.join("MATRIX").join(env_name);
+    for configuration in &["release", "debug"] {
+        let artdir = stage.join(configuration).join("ARTIFACT");
+        assert!(
+            artdir.join("heylib.tar.gz").is_file(),
+            "{} was released",
+            configuration
+        );
+        assert!(stage.join(configuration).join("OUTPUT/libhey.a").is_file());
+    }
+    assert!(!component_dir.join("ARTIFACT").exists(), "ARTIFACT was staged");
+
+    // two release builds in the same environment need picking between
+    let r = lal::publish_matrix(
+        Some(&state.tempdir.path()),
+        &component_dir,
+        "heylib",
+        None,
+        &manifest.supportedEnvironments,
+        false,
+        &state.backend,
+    );
+    match r {
+        Err(lal::CliError::InconsistentMatrix(_)) => {}
+        x => panic!("ambiguous matrix publish should fail: {:?}", x),
+    }
+    let r = lal::publish_matrix(
+        Some(&state.tempdir.path()),
+        &component_dir,
+        "heylib",
+        Some("release"),
+        &manifest.supportedEnvironments,
+        false,
+        &state.backend,
+    );
+    assert!(r.is_ok(), "published the heylib matrix: {:?}", r);
+    let published = state
+        .backend
+        .cache
+        .join("environments")
+        .join(env_name)
+        .join("heylib/1");
+    assert!(published.join("heylib.tar.gz").is_file());
+
+    // unknown configurations are rejected up front
+    let matrix = lal::MatrixOptions {
+        environments: vec![],
+        configurations: vec!["profile".into()],
+    };
+    let r = lal::build_matrix(
+        &component_dir,
+        &config,
+        &manifest,
+        &state.backend,
+        &build_opts,
+        &matrix,
+        lal::ShellModes::default(),
+    );
+    assert!(r.is_err(), "cannot build unknown configurations");
+}
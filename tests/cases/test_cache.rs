@@ -0,0 +1,107 @@
+use crate::common::*;
+use parameterized_macro::parameterized;
+
+#[parameterized(env_name = {"default", "alpine"})]
+pub fn test_cache_entries_record_last_use(env_name: &str) {
+    let state = setup();
+    if !cfg!(feature = "docker") && env_name == "alpine" {
+        return;
+    }
+
+    let config = lal::Config::read(Some(&state.tempdir.path())).expect("read config");
+
+    let component_dir = publish_components(&state, &env_name, vec!["heylib", "helloworld"], "1")
+        .expect("published heylib=1 helloworld=1");
+
+    let entries = lal::cache::entries(&config.cache).expect("list cache entries");
+    let heylib = entries
+        .iter()
+        .find(|e| e.name == "heylib" && e.version == "1")
+        .expect("heylib=1 in cache");
+    assert_eq!(heylib.origin(), env_name);
+    assert!(heylib.size > 0, "cached heylib has a size");
+
+    // fetching helloworld used heylib from the cache
+    assert!(component_dir.join("INPUT/heylib").is_dir());
+    assert!(
+        lal::cache_last_use(&heylib.path).is_some(),
+        "recorded last use of heylib"
+    );
+}
+
+#[parameterized(env_name = {"default", "alpine"})]
+pub fn test_cache_prune(env_name: &str) {
+    let state = setup();
+    if !cfg!(feature = "docker") && env_name == "alpine" {
+        return;
+    }
+
+    let config = lal::Config::read(Some(&state.tempdir.path())).expect("read config");
+
+    publish_components(&state, &env_name, vec!["heylib", "helloworld"], "1")
+        .expect("published heylib=1 helloworld=1");
+
+    let r = lal::cache::prune(&config.cache, "1T");
+    assert!(r.is_ok(), "pruned cache to a large size");
+    assert_eq!(lal::cache::entries(&config.cache).unwrap().len(), 2);
+
+    let r = lal::cache::prune(&config.cache, "0");
+    assert!(r.is_ok(), "pruned cache to nothing");
+    assert!(lal::cache::entries(&config.cache).unwrap().is_empty());
+}
+
+#[parameterized(env_name = {"default", "alpine"})]
+pub fn test_cache_prune_keeps_linked_trees(env_name: &str) {
+    let state = setup();
+    if !cfg!(feature = "docker") && env_name == "alpine" {
+        return;
+    }
+
+    let config = lal::Config::read(Some(&state.tempdir.path())).expect("read config");
+    let tree = lal::get_extracted_dir(&config.cache, "heylib", 1, &env_name);
+
+    for strategy in &[lal::InputStrategy::Symlink, lal::InputStrategy::Hardlink] {
+        // with the local backend, evicting heylib also unpublishes it
+        publish_component(&state, &env_name, "heylib", "1").expect("published heylib=1");
+        let component_dir = clone_component_dir("helloworld", &state);
+        let backend = lal::LocalBackend::new(&lal::LocalConfig::default(), &config.cache)
+            .with_input_strategy(strategy.clone());
+        let r = fetch::fetch_input(&component_dir, &env_name, &backend);
+        assert!(r.is_ok(), "installed helloworld dependencies with {:?}", strategy);
+
+        // the tarball is evicted, but the tree INPUT links to is kept
+        let r = lal::cache::prune(&config.cache, "0");
+        assert!(r.is_ok(), "pruned cache to nothing");
+        assert!(lal::cache::entries(&config.cache).unwrap().is_empty());
+        assert!(lal::extracted_tree_in_use(&tree), "{:?} tree in use", strategy);
+        assert!(
+            component_dir.join("INPUT/heylib/hey.h").is_file(),
+            "INPUT/heylib intact with {:?}",
+            strategy
+        );
+        let r = lal::clean(&config.cache, 0);
+        assert!(r.is_ok(), "cleaned the cache");
+        assert!(tree.is_dir(), "clean keeps the {:?} tree", strategy);
+
+        // and removed once nothing uses it anymore
+        std::fs::remove_dir_all(component_dir.join("INPUT")).unwrap();
+        let r = lal::clean(&config.cache, 0);
+        assert!(r.is_ok(), "cleaned the cache");
+        assert!(
+            !tree.is_dir(),
+            "clean removes the unused tree after {:?}",
+            strategy
+        );
+    }
+}
+
+#[test]
+pub fn test_cache_parse_size() {
+    assert_eq!(lal::parse_size("20G").unwrap(), 20_000_000_000);
+    assert_eq!(lal::parse_size("1.5kB").unwrap(), 1500);
+    assert_eq!(lal::parse_size("512").unwrap(), 512);
+    assert!(lal::parse_size("lots").is_err());
+    assert!(lal::parse_size("inf").is_err());
+    assert!(lal::parse_size("nanG").is_err());
+    assert!(lal::parse_size("-1").is_err());
+}
@@ -56,3 +56,64 @@ pub fn test_clean_keep_nothing(env_name: &str) {
     let first = dirs.next();
     assert!(first.is_none(), "no artifacts left in cache");
 }
+
+#[test]
+pub fn test_clean_images_dry_run() {
+    let state = setup();
+    if !cfg!(feature = "docker") {
+        return;
+    }
+
+    let config = lal::Config::read(Some(&state.tempdir.path())).expect("read config");
+
+    let r = lal::clean_images(&config, true);
+    assert!(r.is_ok(), "listed unused lal images: {:?}", r);
+}
+
+#[test]
+pub fn test_find_stale_images() {
+    let state = setup();
+    let mut config = lal::Config::read(Some(&state.tempdir.path())).expect("read config");
+    config.environments.clear();
+    config.environments.insert(
+        "xenial".into(),
+        lal::Environment::Container(lal::Container::default()),
+    );
+
+    let image = |repository: &str, tag: &str, id: &str, fixup: bool| lal::DockerImage {
+        repository: repository.into(),
+        tag: tag.into(),
+        id: id.into(),
+        fixup,
+    };
+    let images = vec![
+        // the configured environment image, and an older pull of it
+        image("ubuntu", "xenial", "aaa", false),
+        image("ubuntu", "bionic", "bbb", false),
+        // fixups of the current and of an outdated ubuntu:xenial
+        image("ubuntu-u1000_g1000", "from_aaa", "ccc", true),
+        image("ubuntu-u1000_g1000", "from_old", "ddd", true),
+        // fixup of an environment that is no longer configured
+        image("alpine-u1000_g1000", "from_eee", "fff", true),
+        // unlabelled fixups from older lal versions, of the current and an outdated image
+        image("ubuntu-u1001_g1001", "from_aaa", "iii", false),
+        image("ubuntu-u1001_g1001", "from_old", "jjj", false),
+        // user images that merely look like fixups
+        image("ubuntu-u1000_g1000", "latest", "ggg", true),
+        image("mine-u1_g2", "from_old", "hhh", false),
+    ];
+
+    let stale: Vec<String> = lal::find_stale_images(&config, images)
+        .iter()
+        .map(|i| i.reference())
+        .collect();
+    assert_eq!(
+        stale,
+        vec![
+            "ubuntu:bionic",
+            "ubuntu-u1000_g1000:from_old",
+            "alpine-u1000_g1000:from_eee",
+            "ubuntu-u1001_g1001:from_old",
+        ]
+    );
+}
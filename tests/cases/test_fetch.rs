@@ -1,5 +1,7 @@
 use crate::common::*;
+use flate2::{write::GzEncoder, Compression};
 use parameterized_macro::parameterized;
+use std::fs;
 
 #[parameterized(env_name = {"default", "alpine"})]
 fn test_fetch_no_deps(env_name: &str) {
@@ -60,3 +62,293 @@ fn test_fetch_with_dev_deps(env_name: &str) {
     let r = fetch::fetch_dev_input(&component_dir, &env_name, &state.backend);
     assert!(r.is_ok(), "installed helloworld dev dependencies");
 }
+
+#[parameterized(env_name = {"default", "alpine"})]
+fn test_fetch_with_input_strategies(env_name: &str) {
+    let state = setup();
+    if !cfg!(feature = "docker") && env_name == "alpine" {
+        return;
+    }
+
+    publish_component(&state, &env_name, "heylib", "1").expect("publish heylib=1");
+    let component_dir = clone_component_dir("helloworld", &state);
+
+    for strategy in &[
+        lal::InputStrategy::Hardlink,
+        lal::InputStrategy::Reflink,
+        lal::InputStrategy::Symlink,
+    ] {
+        // fetch reuses an up to date INPUT, so start from scratch each time
+        let _ = std::fs::remove_dir_all(component_dir.join("INPUT"));
+        let backend = lal::LocalBackend::new(&lal::LocalConfig::default(), &state.backend.cache)
+            .with_input_strategy(strategy.clone());
+        let r = fetch::fetch_input(&component_dir, &env_name, &backend);
+        assert!(r.is_ok(), "installed helloworld dependencies with {:?}", strategy);
+
+        let heylib = component_dir.join("INPUT/heylib");
+        assert!(heylib.join("hey.h").is_file(), "INPUT/heylib populated with {:?}", strategy);
+        let is_link = heylib.symlink_metadata().unwrap().file_type().is_symlink();
+        assert_eq!(is_link, *strategy == lal::InputStrategy::Symlink, "{:?}", strategy);
+    }
+
+    let extracted = lal::get_extracted_dir(&state.backend.cache, "heylib", 1, &env_name);
+    assert!(extracted.join("libhey.a").is_file(), "shared tree extracted once");
+}
+
+#[parameterized(env_name = {"default", "alpine"})]
+fn test_fetch_with_parts(env_name: &str) {
+    let state = setup();
+    if !cfg!(feature = "docker") && env_name == "alpine" {
+        return;
+    }
+
+    // heylib publishes its headers and library as separate parts
+    let component_dir = clone_component_dir("heylib", &state);
+    let mut manifest = lal::Manifest::read(&component_dir).expect("read heylib manifest");
+    let mut parts = std::collections::BTreeMap::new();
+    parts.insert("headers".to_string(), vec!["*.h".to_string()]);
+    parts.insert("lib".to_string(), vec!["*.a".to_string()]);
+    manifest.components.get_mut("heylib").unwrap().packaging = Some(lal::Packaging {
+        parts,
+        ..Default::default()
+    });
+    manifest.write().expect("write heylib manifest");
+
+    let r = build::build_for_release(&component_dir, &env_name, &state.tempdir.path(), "1");
+    assert!(r.is_ok(), "built heylib release: {:?}", r);
+    let r = publish::publish_release(&component_dir, &state.backend, &state.tempdir.path());
+    assert!(r.is_ok(), "published heylib=1 release: {:?}", r);
+
+    // helloworld only wants the headers
+    let component_dir = clone_component_dir("helloworld", &state);
+    let mut manifest = lal::Manifest::read(&component_dir).expect("read helloworld manifest");
+    manifest
+        .dependencyParts
+        .insert("heylib".into(), vec!["headers".into()]);
+    manifest.write().expect("write helloworld manifest");
+
+    let manifest = lal::Manifest::read(&component_dir).expect("reread helloworld manifest");
+    assert_eq!(manifest.dependencies.get("heylib"), Some(&1));
+    assert_eq!(
+        manifest.dependencyParts.get("heylib"),
+        Some(&vec!["headers".to_string()])
+    );
+
+    let r = fetch::fetch_input(&component_dir, &env_name, &state.backend);
+    assert!(r.is_ok(), "installed heylib headers: {:?}", r);
+    let heylib = component_dir.join("INPUT/heylib");
+    assert!(heylib.join("hey.h").is_file(), "fetched the headers part");
+    assert!(
+        heylib.join("lockfile.json").is_file(),
+        "parts come with the lockfile"
+    );
+    assert!(!heylib.join("libhey.a").exists(), "did not fetch the lib part");
+
+    // asking for more parts fetches it again
+    let mut manifest = lal::Manifest::read(&component_dir).expect("read helloworld manifest");
+    manifest
+        .dependencyParts
+        .insert("heylib".into(), vec!["lib".into(), "headers".into()]);
+    manifest.write().expect("write helloworld manifest");
+    let plan = lal::plan_fetch(&component_dir, &manifest, true, &env_name).expect("planned fetch");
+    assert_eq!(plan.actions[0].action, lal::Action::Download, "parts changed");
+    let r = fetch::fetch_input(&component_dir, &env_name, &state.backend);
+    assert!(r.is_ok(), "installed heylib headers and lib: {:?}", r);
+    assert!(heylib.join("libhey.a").is_file(), "fetched the lib part");
+    let plan = lal::plan_fetch(&component_dir, &manifest, true, &env_name).expect("planned fetch");
+    assert_eq!(plan.actions[0].action, lal::Action::Reuse, "parts unchanged");
+
+    // update respects the parts too, and installs them like the backend's strategy
+    let backend = lal::LocalBackend::new(&lal::LocalConfig::default(), &state.backend.cache)
+        .with_input_strategy(lal::InputStrategy::Symlink);
+    manifest
+        .dependencyParts
+        .insert("heylib".into(), vec!["headers".into()]);
+    manifest.write().expect("write helloworld manifest");
+    let r = lal::update(
+        &component_dir,
+        &manifest,
+        &backend,
+        vec!["heylib=1".into()],
+        false,
+        false,
+        &env_name,
+    );
+    assert!(r.is_ok(), "updated heylib headers: {:?}", r);
+    assert!(heylib.join("hey.h").is_file(), "updated the headers part");
+    assert!(!heylib.join("libhey.a").exists(), "did not update the lib part");
+    assert!(
+        heylib.symlink_metadata().unwrap().file_type().is_symlink(),
+        "parts linked from the cache"
+    );
+
+    // going back to the whole component fetches it again
+    manifest.dependencyParts.clear();
+    manifest.write().expect("write helloworld manifest");
+    let plan = lal::plan_fetch(&component_dir, &manifest, true, &env_name).expect("planned fetch");
+    assert_eq!(
+        plan.actions[0].action,
+        lal::Action::Download,
+        "whole component wanted"
+    );
+}
+
+#[parameterized(env_name = {"default", "alpine"})]
+fn test_fetch_dry_run(env_name: &str) {
+    let state = setup();
+    if !cfg!(feature = "docker") && env_name == "alpine" {
+        return;
+    }
+
+    publish_component_versions(&state, env_name, "heylib", vec!["1", "2"]).expect("publish heylib=1,2");
+    let component_dir = clone_component_dir("helloworld", &state);
+    let manifest = lal::Manifest::read(&component_dir).expect("read manifest");
+
+    // nothing in INPUT yet
+    let plan = lal::plan_fetch(&component_dir, &manifest, false, env_name).expect("planned fetch");
+    assert_eq!(plan.actions.len(), 1);
+    assert_eq!(plan.actions[0].name, "heylib");
+    assert_eq!(plan.actions[0].action, lal::Action::Download);
+    assert_eq!(plan.actions[0].version, Some("1".to_string()));
+    assert!(plan.manifest.is_empty());
+    assert!(
+        !component_dir.join("INPUT/heylib").exists(),
+        "dry run fetches nothing"
+    );
+    assert!(plan.print(true).is_ok());
+
+    fetch::fetch_input(&component_dir, env_name, &state.backend).expect("fetched heylib=1");
+    let plan = lal::plan_fetch(&component_dir, &manifest, false, env_name).expect("planned fetch");
+    assert_eq!(plan.actions[0].action, lal::Action::Reuse);
+
+    // a different version in INPUT is replaced
+    update::update(&component_dir, env_name, &state.backend, vec!["heylib=2"]).expect("updated heylib=2");
+    let plan = lal::plan_fetch(&component_dir, &manifest, false, env_name).expect("planned fetch");
+    assert_eq!(plan.actions[0].action, lal::Action::Download);
+    assert_eq!(plan.actions[0].previous, Some("2".to_string()));
+    assert!(plan.print(false).is_ok());
+}
+
+#[parameterized(env_name = {"default", "alpine"})]
+fn test_fetch_failure_keeps_input(env_name: &str) {
+    let state = setup();
+    if !cfg!(feature = "docker") && env_name == "alpine" {
+        return;
+    }
+
+    publish_component(&state, env_name, "heylib", "1").expect("publish heylib=1");
+    let component_dir = clone_component_dir("helloworld", &state);
+    fetch::fetch_input(&component_dir, env_name, &state.backend).expect("fetched heylib=1");
+
+    // a version that was never published cannot be installed
+    let mut manifest = lal::Manifest::read(&component_dir).expect("read manifest");
+    manifest.dependencies.insert("heylib".into(), 999);
+    let r = lal::fetch(&component_dir, &manifest, &state.backend, true, env_name);
+    assert!(r.is_err(), "could not fetch heylib=999");
+
+    let present = lal::input::analyze(&component_dir).expect("analyze INPUT");
+    assert_eq!(present.get("heylib"), Some(&"1".to_string()), "kept heylib=1");
+    assert!(
+        !component_dir.join("INPUT").join(lal::input::STAGING_DIR).exists(),
+        "staging area cleaned up"
+    );
+}
+
+// path, type and link target of an entry in a hand crafted tarball
+type TarEntry<'a> = (&'a str, tar::EntryType, &'a str);
+
+// stash a hand crafted heylib tarball with the given entries
+fn stash_tarball(state: &TestState, code: &str, entries: &[TarEntry<'_>]) {
+    let stashdir = state.backend.cache.join("stash").join("heylib").join(code);
+    fs::create_dir_all(&stashdir).unwrap();
+    let tarball = fs::File::create(stashdir.join("heylib.tar.gz")).unwrap();
+    let mut archive = tar::Builder::new(GzEncoder::new(tarball, Compression::Default));
+    for (path, etype, target) in entries {
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(*etype);
+        header.set_size(0);
+        header.set_link_name(target).unwrap();
+        header.set_cksum();
+        archive.append_data(&mut header, path, &[][..]).unwrap();
+    }
+    archive.into_inner().unwrap().finish().unwrap();
+}
+
+#[parameterized(env_name = {"default", "alpine"})]
+fn test_update_rejects_unsafe_tarball(env_name: &str) {
+    use tar::EntryType::{Link, Symlink};
+    let state = setup();
+    if !cfg!(feature = "docker") && env_name == "alpine" {
+        return;
+    }
+
+    let component_dir = clone_component_dir("heylib", &state);
+    let outside = state.tempdir.path().join("outside");
+
+    // hand craft stashed tarballs, each with one entry escaping INPUT/heylib
+    let evil: Vec<(&str, Vec<TarEntry<'_>>, &str)> = vec![
+        ("escape", vec![("link", Symlink, "../../../outside")], "link"),
+        ("absolute", vec![("link", Symlink, "/etc")], "link"),
+        ("hard", vec![("link", Link, "../outside")], "link"),
+        // links that only escape once an earlier symlink is followed
+        ("chained", vec![("d/up", Symlink, ".."), ("d/up/link", Symlink, "..")], "d/up/link"),
+        ("through", vec![("d/up", Symlink, ".."), ("link", Symlink, "d/up/../..")], "link"),
+        ("hardthrough", vec![("d/up", Symlink, ".."), ("link", Link, "d/up/x")], "link"),
+    ];
+    for (code, entries, rejected) in evil {
+        stash_tarball(&state, code, &entries);
+        let r = update::update(
+            &component_dir,
+            &env_name,
+            &state.backend,
+            vec![&format!("heylib={}", code)],
+        );
+        match r {
+            Err(lal::CliError::UnsafeTarballEntry(ref entry, _)) => assert_eq!(entry, rejected),
+            _ => panic!("heylib={} was not rejected: {:?}", code, r),
+        }
+        assert!(
+            !component_dir.join("INPUT/heylib").exists(),
+            "partial extraction cleaned up"
+        );
+        assert!(!outside.exists(), "nothing written outside INPUT");
+    }
+
+    // links within the tarball are fine
+    stash_tarball(&state, "fine", &[("d/up", Symlink, ".."), ("link", Symlink, "d/up")]);
+    let r = update::update(&component_dir, &env_name, &state.backend, vec!["heylib=fine"]);
+    assert!(r.is_ok(), "installed heylib with internal links: {:?}", r);
+}
+
+#[parameterized(env_name = {"default", "alpine"})]
+fn test_update_with_extract_limits(env_name: &str) {
+    use tar::EntryType::Symlink;
+    let state = setup();
+    if !cfg!(feature = "docker") && env_name == "alpine" {
+        return;
+    }
+
+    let component_dir = clone_component_dir("heylib", &state);
+    stash_tarball(&state, "many", &[("a", Symlink, "b"), ("b", Symlink, "c"), ("c", Symlink, "a")]);
+
+    let limits = lal::ExtractLimits {
+        max_entries: 2,
+        ..Default::default()
+    };
+    let backend = lal::LocalBackend::new(&lal::LocalConfig::default(), &state.backend.cache)
+        .with_extract_limits(limits);
+    let r = update::update(&component_dir, &env_name, &backend, vec!["heylib=many"]);
+    match r {
+        Err(lal::CliError::UnsafeTarballEntry(ref entry, _)) => assert_eq!(entry, "c"),
+        _ => panic!("heylib=many was not rejected: {:?}", r),
+    }
+
+    // the limits come from the config
+    let mut config = lal::Config::read(Some(&state.tempdir.path())).expect("read config");
+    config.max_extract_entries = Some(2);
+    config.max_extract_size = Some("1M".into());
+    let limits = config.extract_limits().expect("valid limits");
+    assert_eq!((limits.max_size, limits.max_entries), (1_000_000, 2));
+    config.max_extract_size = Some("lots".into());
+    assert!(config.extract_limits().is_err(), "invalid max_extract_size");
+}
@@ -0,0 +1,52 @@
+use crate::common::*;
+use parameterized_macro::parameterized;
+use std::{fs, os::unix::fs::PermissionsExt};
+
+#[parameterized(env_name = {"default", "alpine"})]
+fn test_build_history(env_name: &str) {
+    let state = setup();
+    if !cfg!(feature = "docker") && env_name == "alpine" {
+        return;
+    }
+
+    let component_dir = clone_component_dir("heylib", &state);
+    fetch::fetch_input(&component_dir, env_name, &state.backend).expect("fetched heylib dependencies");
+    assert!(lal::log(&component_dir, false).is_err(), "no builds yet");
+
+    build::build_for_release(&component_dir, env_name, state.tempdir.path(), "1").expect("built heylib=1");
+    let records = lal::read_history(&component_dir).expect("read history");
+    assert_eq!(records.len(), 1);
+    let r = &records[0];
+    assert!(r.success());
+    assert_eq!(r.component, "heylib");
+    assert_eq!(r.configuration, "release");
+    assert_eq!(r.environment, env_name);
+    assert_eq!(r.version, Some("1".to_string()));
+    assert_eq!(r.exit_code, Some(0));
+    let log = fs::read_to_string(lal::logs_dir(&component_dir).join(&r.log)).expect("read build log");
+    assert!(
+        log.contains("ar rcs libhey.a"),
+        "build output in the log: {}",
+        log
+    );
+
+    // failures are recorded with their exit code
+    let build_script = component_dir.join("BUILD");
+    fs::write(&build_script, "#!/bin/sh\necho broken\nexit 2\n").expect("write BUILD");
+    fs::set_permissions(&build_script, fs::Permissions::from_mode(0o755)).expect("chmod BUILD");
+    let r = build::build_for_release(&component_dir, env_name, state.tempdir.path(), "2");
+    assert!(r.is_err(), "broken build fails");
+
+    let records = lal::read_history(&component_dir).expect("read history");
+    assert_eq!(records.len(), 2);
+    let r = &records[1];
+    assert!(!r.success());
+    assert_eq!(r.exit_code, Some(2));
+    assert_eq!(r.version, Some("2".to_string()));
+    let log = fs::read_to_string(lal::logs_dir(&component_dir).join(&r.log)).expect("read build log");
+    assert!(log.contains("broken"));
+
+    assert!(lal::log(&component_dir, true).is_ok());
+    assert!(lal::history(&component_dir, Some(1), false).is_ok());
+    assert!(lal::history(&component_dir, None, true).is_ok());
+}
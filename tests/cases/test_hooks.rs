@@ -0,0 +1,78 @@
+use crate::common::*;
+use parameterized_macro::parameterized;
+use std::{fs, os::unix::fs::PermissionsExt, path::Path};
+
+// install a hook script into .lal/hooks
+fn write_hook(component_dir: &Path, hook: lal::Hook, body: &str, mode: u32) {
+    let pth = lal::hook_path(component_dir, hook);
+    fs::create_dir_all(pth.parent().unwrap()).expect("create .lal/hooks");
+    fs::write(&pth, format!("#!/bin/sh\n{}\n", body)).expect("write hook");
+    fs::set_permissions(&pth, fs::Permissions::from_mode(mode)).expect("set hook mode");
+}
+
+const RECORD: &str = "echo \"$0 $LAL_COMPONENT $LAL_CONFIGURATION $LAL_VERSION $LAL_ENV\" >> hooks.log";
+
+#[parameterized(env_name = {"default", "alpine"})]
+fn test_build_hooks(env_name: &str) {
+    let state = setup();
+    if !cfg!(feature = "docker") && env_name == "alpine" {
+        return;
+    }
+
+    let component_dir = clone_component_dir("heylib", &state);
+    fetch::fetch_input(&component_dir, env_name, &state.backend).expect("fetched heylib dependencies");
+    write_hook(&component_dir, lal::Hook::PreBuild, RECORD, 0o755);
+    write_hook(&component_dir, lal::Hook::PostBuild, RECORD, 0o755);
+
+    build::build_for_release(&component_dir, env_name, state.tempdir.path(), "1").expect("built heylib=1");
+    let log = fs::read_to_string(component_dir.join("hooks.log")).expect("hooks ran");
+    assert_eq!(
+        log,
+        format!(
+            "./.lal/hooks/pre-build heylib release 1 {0}\n./.lal/hooks/post-build heylib release 1 {0}\n",
+            env_name
+        )
+    );
+
+    // publish hooks get their context from the release build
+    fs::remove_file(component_dir.join("hooks.log")).expect("clear hooks.log");
+    write_hook(&component_dir, lal::Hook::PrePublish, RECORD, 0o755);
+    let config = lal::Config::read(Some(state.tempdir.path())).expect("read config");
+    let releases = vec![component_dir.clone()];
+    lal::run_release_hook(&config, lal::Hook::PrePublish, &releases, &component_dir)
+        .expect("ran pre-publish");
+    let log = fs::read_to_string(component_dir.join("hooks.log")).expect("hook ran");
+    assert_eq!(
+        log,
+        format!("./.lal/hooks/pre-publish heylib release 1 {}\n", env_name)
+    );
+}
+
+#[parameterized(env_name = {"default", "alpine"})]
+fn test_failing_pre_build_hook(env_name: &str) {
+    let state = setup();
+    if !cfg!(feature = "docker") && env_name == "alpine" {
+        return;
+    }
+
+    let component_dir = clone_component_dir("heylib", &state);
+    fetch::fetch_input(&component_dir, env_name, &state.backend).expect("fetched heylib dependencies");
+
+    write_hook(&component_dir, lal::Hook::PreBuild, "exit 3", 0o755);
+    let r = build::build_for_release(&component_dir, env_name, state.tempdir.path(), "1");
+    match r {
+        Err(lal::CliError::HookFailure(ref hook, 3)) => assert_eq!(hook, "pre-build"),
+        _ => panic!("pre-build hook failure should abort the build: {:?}", r),
+    }
+    assert!(
+        !component_dir.join("ARTIFACT").exists(),
+        "nothing built after a failing pre-build hook"
+    );
+
+    write_hook(&component_dir, lal::Hook::PreBuild, "exit 0", 0o644);
+    let r = build::build_for_release(&component_dir, env_name, state.tempdir.path(), "1");
+    match r {
+        Err(lal::CliError::HookNotExecutable(_)) => {}
+        _ => panic!("non-executable hook should be rejected: {:?}", r),
+    }
+}
@@ -0,0 +1,81 @@
+use crate::common::*;
+use parameterized_macro::parameterized;
+
+use lal::{Backend, CachedBackend};
+
+#[parameterized(env_name = {"default", "alpine"})]
+fn test_index(env_name: &str) {
+    let state = setup();
+    if !cfg!(feature = "docker") && env_name == "alpine" {
+        return;
+    }
+    let home = Some(state.tempdir.path());
+
+    publish_components(&state, env_name, vec!["heylib", "helloworld"], "1")
+        .expect("published heylib=1 helloworld=1");
+    publish_component(&state, env_name, "heylib", "2").expect("published heylib=2");
+    lal::yank(
+        home,
+        &state.backend,
+        vec![env_name.to_string()],
+        "heylib=2",
+        Some("broken"),
+    )
+    .expect("yanked heylib=2");
+
+    let index = state.backend.refresh_index(env_name).expect("refreshed index");
+    assert!(lal::MetadataIndex::path(&state.backend.get_cache_dir(), env_name).is_file());
+    assert_eq!(index.versions("heylib"), vec![1]);
+    assert_eq!(index.components["heylib"][&2].yanked, Some("broken".to_string()));
+    let (version, hello) = index.latest("hello").expect("hello is indexed");
+    assert_eq!(version, 1);
+    let summary = hello.lockfile.as_ref().expect("lockfile of hello=1 indexed");
+    assert_eq!(summary.dependencies["heylib"], "1");
+
+    // a fresh index is used as is until refreshed
+    let backend =
+        lal::LocalBackend::new(&state.backend.config, &state.backend.cache).with_index_ttl(Some(3600));
+    publish_component(&state, env_name, "heylib", "3").expect("published heylib=3");
+    let index = backend.get_index(env_name).expect("cached index");
+    assert_eq!(index.versions("heylib"), vec![1]);
+    let latest = backend
+        .get_latest_supported_versions("heylib", vec![env_name.to_string()])
+        .expect("supported heylib versions");
+    assert_eq!(latest, vec![1]);
+
+    let index = backend.refresh_index(env_name).expect("refreshed index");
+    assert_eq!(index.versions("heylib"), vec![3, 1]);
+    let latest = backend
+        .get_latest_supported_versions("heylib", vec![env_name.to_string()])
+        .expect("supported heylib versions");
+    assert_eq!(latest, vec![1, 3]);
+
+    // a stale index is not refreshed just to look up versions
+    let stale = lal::LocalBackend::new(&state.backend.config, &state.backend.cache).with_index_ttl(Some(0));
+    publish_component(&state, env_name, "heylib", "4").expect("published heylib=4");
+    let latest = stale
+        .get_latest_supported_versions("heylib", vec![env_name.to_string()])
+        .expect("supported heylib versions");
+    assert_eq!(latest, vec![1, 3, 4]);
+    let index = lal::MetadataIndex::read(&state.backend.get_cache_dir(), env_name);
+    assert_eq!(index.versions("heylib"), vec![3, 1], "index left alone");
+
+    // a failing listing keeps the previous index
+    use std::os::unix::fs::PermissionsExt;
+    let heylib_dir = state
+        .backend
+        .cache
+        .join("environments")
+        .join(env_name)
+        .join("heylib");
+    std::fs::set_permissions(&heylib_dir, std::fs::Permissions::from_mode(0o000)).expect("chmod heylib");
+    // root can list the directory regardless
+    let unlistable = std::fs::read_dir(&heylib_dir).is_err();
+    let r = backend.refresh_index(env_name);
+    std::fs::set_permissions(&heylib_dir, std::fs::Permissions::from_mode(0o755)).expect("chmod heylib");
+    if unlistable {
+        assert!(r.is_err(), "refresh fails when heylib cannot be listed");
+        let index = lal::MetadataIndex::read(&state.backend.get_cache_dir(), env_name);
+        assert_eq!(index.versions("heylib"), vec![3, 1], "previous index kept");
+    }
+}
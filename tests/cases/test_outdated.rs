@@ -0,0 +1,46 @@
+use crate::common::*;
+use parameterized_macro::parameterized;
+
+#[parameterized(env_name = {"default", "alpine"})]
+fn test_outdated(env_name: &str) {
+    let state = setup();
+    if !cfg!(feature = "docker") && env_name == "alpine" {
+        return;
+    }
+
+    publish_components(&state, env_name, vec!["heylib", "helloworld"], "1")
+        .expect("published heylib=1 helloworld=1");
+    publish_component(&state, env_name, "heylib", "2").expect("published heylib=2");
+
+    // heylib=2 can be taken on its own
+    let component_dir = clone_component_dir("prop-leaf", &state);
+    let mut manifest = lal::Manifest::read(&component_dir).expect("read prop-leaf manifest");
+    manifest.supportedEnvironments = vec![env_name.to_string()];
+    manifest.dependencies.insert("heylib".into(), 1);
+
+    let statuses = lal::find_outdated(&manifest, &state.backend, env_name).expect("found outdated");
+    assert_eq!(statuses.len(), 1);
+    assert_eq!(statuses[0].name, "heylib");
+    assert_eq!((statuses[0].current, statuses[0].latest), (1, 2));
+    assert!(statuses[0].is_outdated());
+    assert_eq!(statuses[0].consistent, Some(true));
+    let r = lal::outdated(&manifest, &state.backend, env_name, false);
+    assert!(r.is_err(), "outdated fails when heylib is behind");
+
+    // but not next to hello=1 which was built against heylib=1
+    manifest.dependencies.insert("hello".into(), 1);
+    let statuses = lal::find_outdated(&manifest, &state.backend, env_name).expect("found outdated");
+    let heylib = statuses
+        .iter()
+        .find(|s| s.name == "heylib")
+        .expect("heylib status");
+    assert_eq!(heylib.consistent, Some(false));
+    assert!(heylib.conflicts.contains_key("heylib"));
+    let hello = statuses.iter().find(|s| s.name == "hello").expect("hello status");
+    assert!(!hello.is_outdated());
+
+    manifest.dependencies.remove("hello");
+    manifest.dependencies.insert("heylib".into(), 2);
+    let r = lal::outdated(&manifest, &state.backend, env_name, true);
+    assert!(r.is_ok(), "nothing is outdated after upgrading: {:?}", r);
+}
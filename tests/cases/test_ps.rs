@@ -0,0 +1,51 @@
+use crate::common::*;
+
+fn container(pid: u32, host: &str) -> lal::LalContainer {
+    lal::LalContainer {
+        name: format!("lal-heylib-{}-0", pid),
+        component: "heylib".into(),
+        user: "lal".into(),
+        pid,
+        host: host.into(),
+        image: "alpine:latest".into(),
+        running_for: "2 minutes ago".into(),
+    }
+}
+
+#[test]
+fn test_container_status() {
+    use lal::ContainerStatus::*;
+
+    // beyond the kernel's pid_max, so never a running process
+    let gone = 1 << 23;
+    assert_eq!(
+        container(gone, "").status(),
+        Unknown,
+        "started without a host label"
+    );
+    assert_eq!(
+        container(gone, "another-host").status(),
+        Unknown,
+        "started from another host or pid namespace"
+    );
+    if let Some(host) = lal::container_host() {
+        assert_eq!(container(std::process::id(), &host).status(), Running);
+        assert_eq!(container(gone, &host).status(), Orphaned);
+    }
+}
+
+#[test]
+fn test_ps_and_kill() {
+    let _state = setup();
+    if !cfg!(feature = "docker") {
+        return;
+    }
+
+    let r = lal::ps();
+    assert!(r.is_ok(), "listed lal containers: {:?}", r);
+
+    match lal::kill(&["not-a-lal-container"], false) {
+        Err(lal::CliError::UnknownContainer(_)) => {}
+        r => panic!("only containers started by lal are stopped: {:?}", r),
+    }
+}
@@ -84,3 +84,280 @@ fn test_publish_without_version(env_name: &str) {
     let r = publish::publish_release(&component_dir, &state.backend, &state.tempdir.path());
     assert!(r.is_err(), "can't publish without version: {:?}", r);
 }
+
+#[parameterized(env_name = {"default", "alpine"})]
+fn test_publish_compressed(env_name: &str) {
+    let state = setup();
+    if !cfg!(feature = "docker") && env_name == "alpine" {
+        return;
+    }
+
+    // zstd for everything in the manifest, xz for heylib only
+    let component_dir = clone_component_dir("heylib", &state);
+    let mut manifest = lal::Manifest::read(&component_dir).expect("read heylib manifest");
+    manifest.compression = Some(lal::Compression::Zstd);
+    manifest.write().expect("write heylib manifest");
+
+    let r = build::build_for_release(&component_dir, &env_name, &state.tempdir.path(), "1");
+    assert!(r.is_ok(), "built heylib release: {:?}", r);
+    let r = publish::publish_release(&component_dir, &state.backend, &state.tempdir.path());
+    assert!(r.is_ok(), "published heylib=1 release: {:?}", r);
+
+    manifest.components.get_mut("heylib").unwrap().compression = Some(lal::Compression::Xz);
+    manifest.write().expect("write heylib manifest");
+
+    let r = build::build_for_release(&component_dir, &env_name, &state.tempdir.path(), "2");
+    assert!(r.is_ok(), "built heylib release: {:?}", r);
+    let r = publish::publish_release(&component_dir, &state.backend, &state.tempdir.path());
+    assert!(r.is_ok(), "published heylib=2 release: {:?}", r);
+
+    let published = Path::new(&state.backend.get_cache_dir())
+        .join("environments")
+        .join(&env_name)
+        .join("heylib");
+    let zst = published.join("1/heylib.tar.zst");
+    assert_eq!(lal::Compression::detect(&zst).ok(), Some(lal::Compression::Zstd));
+    let xz = published.join("2/heylib.tar.xz");
+    assert_eq!(lal::Compression::detect(&xz).ok(), Some(lal::Compression::Xz));
+
+    // helloworld depends on heylib=1
+    let component_dir = clone_component_dir("helloworld", &state);
+    let r = fetch::fetch_input(&component_dir, &env_name, &state.backend);
+    assert!(r.is_ok(), "installed zstd compressed heylib: {:?}", r);
+    assert!(component_dir.join("INPUT/heylib/hey.h").is_file());
+}
+
+#[parameterized(env_name = {"default", "alpine"})]
+fn test_publish_matrix_atomically(env_name: &str) {
+    use std::fs;
+    let state = setup();
+    if !cfg!(feature = "docker") && env_name == "alpine" {
+        return;
+    }
+
+    let component_dir = clone_component_dir("heylib", &state);
+    let r = fetch::fetch_input(&component_dir, &env_name, &state.backend);
+    assert!(r.is_ok(), "installed heylib dependencies: {:?}", r);
+    let r = build::build_for_release(&component_dir, &env_name, &state.tempdir.path(), "1");
+    assert!(r.is_ok(), "built heylib release: {:?}", r);
+
+    // stage the release like `lal build --matrix` would
+    let matrix = component_dir.join("MATRIX");
+    fs::create_dir_all(matrix.join(env_name).join("release")).unwrap();
+    fs::rename(
+        component_dir.join("ARTIFACT"),
+        matrix.join(env_name).join("release/ARTIFACT"),
+    )
+    .unwrap();
+
+    let envs = vec![env_name.to_string(), "xenial".to_string()];
+    let published = |env: &str| {
+        let cache_dir = state.backend.get_cache_dir();
+        Path::new(&cache_dir)
+            .join("environments")
+            .join(env)
+            .join("heylib/1/heylib.tar.gz")
+            .exists()
+    };
+    let publish_matrix = || {
+        lal::publish_matrix(
+            Some(&state.tempdir.path()),
+            &component_dir,
+            "heylib",
+            None,
+            &envs,
+            false,
+            &state.backend,
+        )
+    };
+
+    // nothing is published while an environment is missing
+    let r = publish_matrix();
+    assert!(r.is_err(), "cannot publish without a xenial build");
+    assert!(!published(env_name), "nothing was published");
+
+    // pretend the same release was built in xenial
+    let xenial = matrix.join("xenial/release");
+    fs::create_dir_all(&xenial).unwrap();
+    fs::rename(
+        matrix.join(env_name).join("release/ARTIFACT"),
+        xenial.join("ARTIFACT"),
+    )
+    .unwrap();
+    let r = build::build_for_release(&component_dir, &env_name, &state.tempdir.path(), "1");
+    assert!(r.is_ok(), "rebuilt heylib release: {:?}", r);
+    fs::rename(
+        component_dir.join("ARTIFACT"),
+        matrix.join(env_name).join("release/ARTIFACT"),
+    )
+    .unwrap();
+    let mut lock = lal::Lockfile::release_build(&xenial).expect("read xenial lockfile");
+    lock.envname = "xenial".into();
+    lock.write(&xenial.join("ARTIFACT/lockfile.json"))
+        .expect("write xenial lockfile");
+
+    // every environment is checked before anything is uploaded
+    let tarball = xenial.join("ARTIFACT/heylib.tar.gz");
+    let data = fs::read(&tarball).unwrap();
+    fs::write(&tarball, &data[..data.len() / 2]).unwrap();
+    match publish_matrix() {
+        Err(lal::CliError::InvalidReleaseBuild(_)) => {}
+        x => panic!("publishing a truncated xenial tarball should fail: {:?}", x),
+    }
+    assert!(!published(env_name), "nothing was published to {}", env_name);
+    assert!(!published("xenial"), "nothing was published to xenial");
+    fs::write(&tarball, &data).unwrap();
+
+    // a failing upload to xenial rolls back the other environment
+    let blocked = state.backend.get_cache_dir().join("environments/xenial");
+    fs::create_dir_all(blocked.parent().unwrap()).unwrap();
+    fs::write(&blocked, "not a directory").unwrap();
+    let r = publish_matrix();
+    assert!(r.is_err(), "publish to xenial failed");
+    assert!(!published(env_name), "rolled back the {} publish", env_name);
+    fs::remove_file(&blocked).unwrap();
+
+    let r = publish_matrix();
+    assert!(r.is_ok(), "published heylib=1 everywhere: {:?}", r);
+    assert!(published(env_name));
+    assert!(published("xenial"));
+}
+
+#[parameterized(env_name = {"default", "alpine"})]
+fn test_publish_safety_checks(env_name: &str) {
+    use std::{fs, os::unix::fs::PermissionsExt};
+    let state = setup();
+    if !cfg!(feature = "docker") && env_name == "alpine" {
+        return;
+    }
+
+    let component_dir = publish_component(&state, &env_name, "heylib", "1").expect("published heylib=1");
+
+    // released versions are not overwritten by accident
+    let r = publish::publish_release(&component_dir, &state.backend, &state.tempdir.path());
+    match r {
+        Err(lal::CliError::VersionAlreadyPublished(_, 1, _)) => {}
+        x => panic!("republishing heylib=1 should fail: {:?}", x),
+    }
+    let r = lal::publish(
+        Some(&state.tempdir.path()),
+        &component_dir,
+        "heylib",
+        true,
+        &state.backend,
+    );
+    assert!(r.is_ok(), "republished heylib=1 with force: {:?}", r);
+
+    // versions that cannot be listed are not assumed to be free
+    let heylib_dir = Path::new(&state.backend.get_cache_dir())
+        .join("environments")
+        .join(&env_name)
+        .join("heylib");
+    fs::set_permissions(&heylib_dir, fs::Permissions::from_mode(0o300)).expect("chmod heylib");
+    // root can list the directory regardless
+    let unlistable = fs::read_dir(&heylib_dir).is_err();
+    let r = publish::publish_release(&component_dir, &state.backend, &state.tempdir.path());
+    fs::set_permissions(&heylib_dir, fs::Permissions::from_mode(0o755)).expect("chmod heylib");
+    match r {
+        Err(lal::CliError::Io(_)) => {}
+        _ if !unlistable => {}
+        x => panic!("publishing when heylib cannot be listed should fail: {:?}", x),
+    }
+
+    // the lockfile must be for the component being published
+    let artdir = component_dir.join("ARTIFACT");
+    fs::copy(artdir.join("heylib.tar.gz"), artdir.join("hello.tar.gz")).unwrap();
+    let r = lal::publish(
+        Some(&state.tempdir.path()),
+        &component_dir,
+        "hello",
+        false,
+        &state.backend,
+    );
+    match r {
+        Err(lal::CliError::InvalidReleaseBuild(_)) => {}
+        x => panic!("publishing heylib as hello should fail: {:?}", x),
+    }
+
+    // truncated tarballs are caught before uploading
+    let data = fs::read(artdir.join("heylib.tar.gz")).unwrap();
+    fs::write(artdir.join("heylib.tar.gz"), &data[..data.len() / 2]).unwrap();
+    let r = lal::publish(
+        Some(&state.tempdir.path()),
+        &component_dir,
+        "heylib",
+        true,
+        &state.backend,
+    );
+    match r {
+        Err(lal::CliError::InvalidReleaseBuild(_)) => {}
+        x => panic!("publishing a truncated tarball should fail: {:?}", x),
+    }
+
+    // releases built against stashed dependencies cannot be published
+    stash_component(&state, &env_name, "heylib", "blah").expect("stashed heylib=blah");
+    let component_dir = clone_component_dir("helloworld", &state);
+    let r = update::update(&component_dir, &env_name, &state.backend, vec!["heylib=blah"]);
+    assert!(r.is_ok(), "using stashed dependency: {:?}", r);
+    let mut build_opts = build::options(Some(&state.tempdir.path()), &env_name).expect("build options");
+    build_opts.simple_verify = true;
+    build_opts.version = Some("1".into());
+    let r = build::build_with_options(&component_dir, &env_name, &state.tempdir.path(), &build_opts);
+    assert!(r.is_ok(), "built hello with a stashed heylib: {:?}", r);
+    let r = publish::publish_release(&component_dir, &state.backend, &state.tempdir.path());
+    match r {
+        Err(lal::CliError::InvalidReleaseBuild(_)) => {}
+        x => panic!("publishing with a stashed dependency should fail: {:?}", x),
+    }
+}
+
+#[parameterized(env_name = {"default", "alpine"})]
+fn test_publish_next_version(env_name: &str) {
+    use std::{fs, os::unix::fs::PermissionsExt};
+    let state = setup();
+    if !cfg!(feature = "docker") && env_name == "alpine" {
+        return;
+    }
+    let home = Some(state.tempdir.path());
+    let envs = vec![env_name.to_string()];
+
+    // the first version of a new component is 1
+    let r = lal::next_version(home, &state.backend, "heylib", &envs);
+    assert_eq!(r.ok(), Some(1), "allocated heylib=1");
+
+    // versions are one higher than the latest, skipping reserved ones
+    publish_component(&state, &env_name, "heylib", "3").expect("published heylib=3");
+
+    // nothing is reserved when the published versions cannot be listed
+    let heylib_dir = Path::new(&state.backend.get_cache_dir())
+        .join("environments")
+        .join(&env_name)
+        .join("heylib");
+    fs::set_permissions(&heylib_dir, fs::Permissions::from_mode(0o300)).expect("chmod heylib");
+    // root can list the directory regardless
+    if fs::read_dir(&heylib_dir).is_err() {
+        let r = lal::next_version(home, &state.backend, "heylib", &envs);
+        assert!(
+            r.is_err(),
+            "no version allocated when heylib cannot be listed: {:?}",
+            r
+        );
+    }
+    fs::set_permissions(&heylib_dir, fs::Permissions::from_mode(0o755)).expect("chmod heylib");
+
+    let r = lal::next_version(home, &state.backend, "heylib", &envs);
+    assert_eq!(r.ok(), Some(4), "allocated heylib=4");
+    let r = lal::next_version(home, &state.backend, "heylib", &envs);
+    assert_eq!(r.ok(), Some(5), "heylib=4 is reserved");
+
+    // and publishing the allocated version works as normal
+    let component_dir = clone_component_dir("heylib", &state);
+    let r = fetch::fetch_input(&component_dir, &env_name, &state.backend);
+    assert!(r.is_ok(), "installed heylib dependencies: {:?}", r);
+    let r = build::build_for_release(&component_dir, &env_name, &state.tempdir.path(), "4");
+    assert!(r.is_ok(), "built heylib=4 release: {:?}", r);
+    let r = publish::publish_release(&component_dir, &state.backend, &state.tempdir.path());
+    assert!(r.is_ok(), "published heylib=4 release: {:?}", r);
+    let r = lal::next_version(home, &state.backend, "heylib", &envs);
+    assert_eq!(r.ok(), Some(6), "allocated heylib=6");
+}
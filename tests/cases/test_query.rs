@@ -11,7 +11,12 @@ pub fn test_query(env_name: &str) {
     publish_components(&state, &env_name, vec!["heylib", "helloworld"], "1")
         .expect("published heylib=1 helloworld=1");
 
-    let r = lal::query(&state.backend, Some(&env_name), "hello", false);
+    let r = lal::query(
+        &state.backend,
+        &[env_name.to_string()],
+        "hello",
+        &Default::default(),
+    );
     assert!(r.is_ok(), "could query for hello");
 }
 
@@ -25,6 +30,116 @@ pub fn test_query_last(env_name: &str) {
     publish_components(&state, &env_name, vec!["heylib", "helloworld"], "1")
         .expect("published heylib=1 helloworld=1");
 
-    let r = lal::query(&state.backend, Some(&env_name), "hello", true);
+    let r = lal::query(
+        &state.backend,
+        &[env_name.to_string()],
+        "hello",
+        &lal::QueryOptions {
+            latest: true,
+            ..Default::default()
+        },
+    );
     assert!(r.is_ok(), "could query for hello");
 }
+
+#[parameterized(env_name = {"default", "alpine"})]
+pub fn test_query_details_and_filters(env_name: &str) {
+    use lal::Backend;
+
+    let state = setup();
+    if !cfg!(feature = "docker") && env_name == "alpine" {
+        return;
+    }
+
+    publish_components(&state, env_name, vec!["heylib", "helloworld"], "1")
+        .expect("published heylib=1 helloworld=1");
+    publish_component_versions(&state, env_name, "helloworld", vec!["2", "3"])
+        .expect("published helloworld=2 helloworld=3");
+    let envs = [env_name.to_string()];
+    let r = lal::yank(
+        Some(state.tempdir.path()),
+        &state.backend,
+        envs.to_vec(),
+        "hello=3",
+        Some("broken"),
+    );
+    assert!(r.is_ok(), "yanked hello=3: {:?}", r);
+
+    let lock = state
+        .backend
+        .get_published_lockfile("hello", 1, env_name)
+        .expect("published lockfile of hello=1");
+    assert_eq!(lock.dependencies["heylib"].version, "1");
+
+    let entries = |opts: lal::QueryOptions| lal::query_entries(&state.backend, &envs, "hello", &opts);
+    let versions = |opts: lal::QueryOptions| -> Vec<u32> {
+        entries(opts)
+            .expect("queried hello")
+            .iter()
+            .map(|e| e.version)
+            .collect()
+    };
+
+    // yanked versions are listed in order with the rest, but are never the latest
+    let all = entries(lal::QueryOptions {
+        details: true,
+        ..Default::default()
+    })
+    .expect("queried details of hello");
+    let listed: Vec<(u32, Option<&str>)> = all.iter().map(|e| (e.version, e.yanked.as_deref())).collect();
+    assert_eq!(listed, vec![(3, Some("broken")), (2, None), (1, None)]);
+    assert_eq!(all[2].dependencies["heylib"], "1");
+    assert_eq!(all[2].sha, lock.sha);
+    let latest = versions(lal::QueryOptions {
+        latest: true,
+        ..Default::default()
+    });
+    assert_eq!(latest, vec![2]);
+
+    let r = lal::query(
+        &state.backend,
+        &envs,
+        "hello",
+        &lal::QueryOptions {
+            json: true,
+            depends_on: Some("heylib=1".into()),
+            since: Some("2000-01-01".into()),
+            ..Default::default()
+        },
+    );
+    assert!(r.is_ok(), "filtered hello on heylib=1: {:?}", r);
+    let filtered = versions(lal::QueryOptions {
+        depends_on: Some("heylib=1".into()),
+        since: Some("2000-01-01".into()),
+        ..Default::default()
+    });
+    assert_eq!(filtered, vec![3, 2, 1]);
+
+    // filters that match nothing leave an empty list
+    let none = versions(lal::QueryOptions {
+        depends_on: Some("heylib=7".into()),
+        ..Default::default()
+    });
+    assert!(none.is_empty(), "no version depends on heylib=7: {:?}", none);
+    let none = versions(lal::QueryOptions {
+        sha: Some("not-a-sha".into()),
+        ..Default::default()
+    });
+    assert!(none.is_empty(), "no version has the sha: {:?}", none);
+    let none = versions(lal::QueryOptions {
+        since: Some("2999-01-01".into()),
+        ..Default::default()
+    });
+    assert!(none.is_empty(), "no version built in the future: {:?}", none);
+
+    let r = entries(lal::QueryOptions {
+        since: Some("yesterday".into()),
+        ..Default::default()
+    });
+    assert!(r.is_err(), "could not query with an invalid date");
+    let r = entries(lal::QueryOptions {
+        depends_on: Some("heylib".into()),
+        ..Default::default()
+    });
+    assert!(r.is_err(), "could not query with a dependency without version");
+}
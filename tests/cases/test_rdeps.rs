@@ -0,0 +1,43 @@
+use crate::common::*;
+use parameterized_macro::parameterized;
+
+use lal::Backend;
+
+#[parameterized(env_name = {"default", "alpine"})]
+fn test_rdeps(env_name: &str) {
+    let state = setup();
+    if !cfg!(feature = "docker") && env_name == "alpine" {
+        return;
+    }
+    let envs = vec![env_name.to_string()];
+
+    publish_components(&state, env_name, vec!["heylib", "helloworld"], "1")
+        .expect("published heylib=1 helloworld=1");
+
+    let rdeps = lal::find_reverse_dependencies(&state.backend, &envs, "heylib", None)
+        .expect("found reverse dependencies of heylib");
+    assert_eq!(rdeps.len(), 1);
+    assert_eq!(rdeps[0].name, "hello");
+    assert_eq!(rdeps[0].version, 1);
+    assert_eq!(rdeps[0].environment, env_name);
+    assert!(rdeps[0].direct, "hello depends on heylib directly");
+    assert!(rdeps[0].versions.contains("1"));
+
+    // the index is kept in the cache and reused
+    let index = state
+        .backend
+        .get_cache_dir()
+        .join("index")
+        .join(format!("{}.json", env_name));
+    assert!(index.is_file(), "index cached at {}", index.display());
+
+    let rdeps = lal::find_reverse_dependencies(&state.backend, &envs, "heylib", Some(2))
+        .expect("found reverse dependencies of heylib=2");
+    assert!(rdeps.is_empty(), "nothing uses heylib=2");
+    let rdeps = lal::find_reverse_dependencies(&state.backend, &envs, "hello", None)
+        .expect("found reverse dependencies of hello");
+    assert!(rdeps.is_empty(), "nothing uses hello");
+
+    let r = lal::rdeps(&state.backend, &envs, "heylib", None);
+    assert!(r.is_ok(), "printed reverse dependencies of heylib: {:?}", r);
+}
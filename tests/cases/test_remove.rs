@@ -71,3 +71,59 @@ pub fn test_remove_dependencies_with_save() {
     assert_eq!(manifest.dependencies.len(), 0);
     assert_eq!(manifest.dependencies.get_key_value("heylib"), None);
 }
+
+#[test]
+pub fn test_remove_dependencies_dry_run() {
+    let state = setup();
+
+    // "helloworld" has 1 dependency
+    let component_dir = clone_component_dir("helloworld", &state);
+    std::fs::create_dir_all(component_dir.join("INPUT/heylib")).expect("create INPUT/heylib");
+
+    let manifest = lal::Manifest::read(&component_dir).expect("read manifest");
+    let plan = lal::plan_remove(&component_dir, &manifest, &["heylib".to_string()], true, false)
+        .expect("planned removal of heylib");
+    assert_eq!(plan.actions.len(), 1);
+    assert_eq!(plan.actions[0].action, lal::Action::Remove);
+    assert_eq!(plan.manifest.len(), 1);
+    assert_eq!((plan.manifest[0].from, plan.manifest[0].to), (Some(1), None));
+    assert!(plan.print(false).is_ok());
+
+    // nothing removed
+    assert!(component_dir.join("INPUT/heylib").is_dir(), "INPUT/heylib kept");
+    let manifest = lal::Manifest::read(&component_dir).expect("read manifest");
+    assert_eq!(manifest.dependencies.get("heylib"), Some(&1));
+
+    let r = lal::plan_remove(&component_dir, &manifest, &["nothere".to_string()], true, false);
+    assert!(r.is_err(), "cannot plan removal of a missing dependency");
+}
+
+#[test]
+pub fn test_remove_keeps_shared_dependency_settings() {
+    let mut manifest = lal::Manifest::default();
+    manifest.dependencies.insert("heylib".into(), 1);
+    manifest.devDependencies.insert("heylib".into(), 1);
+    manifest
+        .dependencyParts
+        .insert("heylib".into(), vec!["runtime".into()]);
+    manifest
+        .dependencyEnvironments
+        .insert("heylib".into(), "xenial".into());
+    let change = |dev: bool| lal::ManifestChange {
+        name: "heylib".into(),
+        dev,
+        from: Some(1),
+        to: None,
+    };
+
+    // still a normal dependency, so its parts and environment stay
+    change(true).apply(&mut manifest);
+    assert!(manifest.devDependencies.is_empty());
+    assert_eq!(manifest.dependencyParts["heylib"], vec!["runtime".to_string()]);
+    assert_eq!(manifest.dependencyEnvironments["heylib"], "xenial");
+
+    change(false).apply(&mut manifest);
+    assert!(manifest.dependencies.is_empty());
+    assert!(manifest.dependencyParts.is_empty());
+    assert!(manifest.dependencyEnvironments.is_empty());
+}
@@ -54,3 +54,30 @@ pub fn test_run_scripts(env_name: &str) {
     );
     assert!(r.is_ok(), "could run `subroutine` script");
 }
+
+#[test]
+pub fn test_remap_lal_user() {
+    let passwd = "root:x:0:0:root:/root:/bin/bash\nlal:x:1000:1000:lal:/home/lal:/bin/bash\n";
+    let group = "root:x:0:\nlal:x:1000:\nwheel:x:10:lal\n";
+    let (passwd_data, group_data) = lal::remap_lal_user(passwd, group, 1234, 5678);
+    assert_eq!(
+        passwd_data,
+        "root:x:0:0:root:/root:/bin/bash\nlal:x:1234:5678:lal:/home/lal:/bin/bash\n"
+    );
+    assert_eq!(group_data, "root:x:0:\nlal:x:5678:\nwheel:x:10:lal\n");
+
+    // images without a lal user get one appended
+    let (passwd_data, group_data) = lal::remap_lal_user("root:x:0:0:root:/root:/bin/sh", "root:x:0:", 1, 2);
+    assert_eq!(
+        passwd_data,
+        "root:x:0:0:root:/root:/bin/sh\nlal:x:1:2:lal:/home/lal:/bin/sh\n"
+    );
+    assert_eq!(group_data, "root:x:0:\nlal:x:2:\n");
+
+    // users whose name merely starts with lal are left alone
+    let (passwd_data, _) = lal::remap_lal_user("lally:x:7:7::/:/bin/sh\n", "", 1, 2);
+    assert_eq!(
+        passwd_data,
+        "lally:x:7:7::/:/bin/sh\nlal:x:1:2:lal:/home/lal:/bin/sh\n"
+    );
+}
@@ -1,6 +1,9 @@
 use crate::common::*;
 use parameterized_macro::parameterized;
-use std::path::{Path, PathBuf};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
 
 fn assert_missing_lockfile(path: &PathBuf, name: &str) {
     match lal::Lockfile::from_path(path, &name) {
@@ -718,3 +721,77 @@ fn test_update_all_to_latest_with_save_savedev(env_name: &str) {
         1,
     );
 }
+
+// -- Dry runs
+
+#[parameterized(env_name = {"default", "alpine"})]
+fn test_update_dry_run(env_name: &str) {
+    let state = setup();
+    if !cfg!(feature = "docker") && env_name == "alpine" {
+        return;
+    }
+
+    let r = publish_component_versions(&state, env_name, "heylib", vec!["1", "2"]);
+    assert!(r.is_ok(), "published heylib=1 and heylib=2: {:?}", r);
+    let component_dir = clone_component_dir("helloworld", &state);
+    update::update(&component_dir, env_name, &state.backend, vec!["heylib=1"]).expect("updated heylib=1");
+    let manifest = lal::Manifest::read(&component_dir).expect("read manifest");
+
+    // lal update --save --dry-run heylib
+    let plan = lal::plan_update(
+        &component_dir,
+        &manifest,
+        &state.backend,
+        &["heylib".to_string()],
+        true,
+        false,
+        env_name,
+    )
+    .expect("planned update");
+    assert_eq!(plan.actions.len(), 1);
+    assert_eq!(plan.actions[0].action, lal::Action::Download);
+    assert_eq!(plan.actions[0].version, Some("2".to_string()));
+    assert_eq!(plan.actions[0].previous, Some("1".to_string()));
+    assert_eq!(plan.manifest.len(), 1);
+    assert_eq!((plan.manifest[0].from, plan.manifest[0].to), (Some(1), Some(2)));
+    assert!(!plan.manifest[0].dev);
+    assert!(plan.print(false).is_ok());
+
+    // lal update-all --save --dry-run
+    let plan = lal::plan_update_all(&component_dir, &manifest, &state.backend, true, false, env_name)
+        .expect("planned update-all");
+    assert_eq!(plan.manifest.len(), 1);
+    assert!(plan.print(true).is_ok());
+
+    // nothing changed
+    assert_manifest(&component_dir, "heylib", 1);
+    assert_lockfile(&component_dir.join("INPUT/heylib/lockfile.json"), "heylib", 1);
+}
+
+#[test]
+pub fn test_input_commit_rolls_back() {
+    let state = setup();
+    let component_dir = state.tempdir.path().join("rollback");
+    let input = component_dir.join("INPUT");
+    fs::create_dir_all(input.join("a")).unwrap();
+    fs::write(input.join("a/old"), "").unwrap();
+    fs::write(input.join(".a.parts"), "old").unwrap();
+    fs::create_dir_all(input.join("gone")).unwrap();
+
+    let mut tx = lal::input::InputTransaction::begin(&component_dir).unwrap();
+    let staged = tx.staging_dir().join("INPUT");
+    fs::create_dir_all(staged.join("a")).unwrap();
+    fs::write(staged.join("a/new"), "").unwrap();
+    fs::write(staged.join(".a.parts"), "new").unwrap();
+    tx.stage("a");
+    tx.remove("gone");
+    // never unpacked, so swapping it in fails after a and gone were moved
+    tx.stage("missing");
+    assert!(tx.commit().is_err(), "commit failed");
+
+    assert!(input.join("a/old").is_file(), "previous a restored");
+    assert!(!input.join("a/new").exists(), "staged a not left in INPUT");
+    assert_eq!(fs::read_to_string(input.join(".a.parts")).unwrap(), "old");
+    assert!(input.join("gone").is_dir(), "removed component restored");
+    assert!(!input.join(".staging").exists(), "staging cleaned up");
+}
@@ -103,3 +103,54 @@ fn test_verify_with_stashed_deps(env_name: &str) {
         "allow stashed versions with the simpler verify algorithm"
     );
 }
+
+#[parameterized(
+    env_name = {"default", "default", "alpine", "alpine"},
+    simple = {true, false, true, false},
+)]
+fn test_verify_with_dependency_environment(env_name: &str, simple: bool) {
+    let state = setup();
+    if !cfg!(feature = "docker") && env_name == "alpine" {
+        return;
+    }
+
+    // heylib only exists in env_name
+    publish_component(&state, &env_name, "heylib", "1").expect("published heylib=1");
+
+    // helloworld builds in xenial, but declares heylib as coming from env_name
+    let component_dir = clone_component_dir("helloworld", &state);
+    let mut manifest = lal::Manifest::read(&component_dir).expect("read helloworld manifest");
+    manifest
+        .dependencyEnvironments
+        .insert("heylib".into(), env_name.into());
+    manifest.write().expect("write helloworld manifest");
+
+    let manifest = lal::Manifest::read(&component_dir).expect("reread helloworld manifest");
+    assert_eq!(manifest.dependencies.get("heylib"), Some(&1));
+    assert_eq!(
+        manifest.dependencyEnvironments.get("heylib"),
+        Some(&env_name.to_string())
+    );
+
+    let r = fetch::fetch_input(&component_dir, "xenial", &state.backend);
+    assert!(r.is_ok(), "fetched heylib from its declared environment: {:?}", r);
+
+    let r = verify::verify(&component_dir, "xenial", simple);
+    assert!(r.is_ok(), "verify accepts the declared environment");
+
+    // an undeclared mismatch is still caught
+    let mut manifest = lal::Manifest::read(&component_dir).expect("read helloworld manifest");
+    manifest.dependencyEnvironments.clear();
+    manifest.write().expect("write helloworld manifest");
+    let r = verify::verify(&component_dir, "xenial", simple);
+    assert!(r.is_err(), "verify fails without the declared environment");
+
+    // as is a dependency that was not built in its declared environment
+    let mut manifest = lal::Manifest::read(&component_dir).expect("read helloworld manifest");
+    manifest
+        .dependencyEnvironments
+        .insert("heylib".into(), "xenial".into());
+    manifest.write().expect("write helloworld manifest");
+    let r = verify::verify(&component_dir, &env_name, simple);
+    assert!(r.is_err(), "verify fails when heylib is not built in xenial");
+}
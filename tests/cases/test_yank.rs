@@ -0,0 +1,117 @@
+use crate::common::*;
+use parameterized_macro::parameterized;
+
+use lal::Backend;
+use std::{fs, os::unix::fs::PermissionsExt};
+
+fn input_version(component_dir: &std::path::Path, name: &str) -> String {
+    let lockfile = component_dir.join("INPUT").join(name).join("lockfile.json");
+    lal::Lockfile::from_path(&lockfile, name)
+        .expect("read lockfile")
+        .version
+}
+
+#[parameterized(env_name = {"default", "alpine"})]
+fn test_yank(env_name: &str) {
+    let state = setup();
+    if !cfg!(feature = "docker") && env_name == "alpine" {
+        return;
+    }
+    let home = Some(state.tempdir.path());
+    let envs = vec![env_name.to_string()];
+
+    publish_component(&state, env_name, "heylib", "1").expect("published heylib=1");
+    publish_component(&state, env_name, "heylib", "2").expect("published heylib=2");
+
+    let r = lal::yank(
+        home,
+        &state.backend,
+        envs.clone(),
+        "heylib=2",
+        Some("segfaults on start"),
+    );
+    assert!(r.is_ok(), "yanked heylib=2: {:?}", r);
+
+    // yanked versions are hidden, but their reason is kept
+    let versions = state
+        .backend
+        .get_versions("heylib", env_name)
+        .expect("heylib versions");
+    assert_eq!(versions, vec![1]);
+    let latest = state
+        .backend
+        .get_latest_version("heylib", env_name)
+        .expect("latest heylib");
+    assert_eq!(latest, 1);
+    let yanked = state
+        .backend
+        .get_yanked("heylib", env_name)
+        .expect("yanked heylib");
+    assert_eq!(yanked.get(&2).map(|r| r.as_str()), Some("segfaults on start"));
+    let (all, yanked) = state
+        .backend
+        .get_all_versions("heylib", env_name)
+        .expect("all heylib versions");
+    assert_eq!(all, vec![2, 1], "yanked versions listed with the rest");
+    assert_eq!(yanked.into_iter().collect::<Vec<_>>(), vec![2]);
+    let reason = state.backend.get_yank_reason("heylib", 2, env_name);
+    assert_eq!(reason.ok(), Some(Some("segfaults on start".to_string())));
+    let reason = state.backend.get_yank_reason("heylib", 1, env_name);
+    assert_eq!(reason.ok(), Some(None), "heylib=1 is not yanked");
+    let r = lal::query(
+        &state.backend,
+        &[env_name.to_string()],
+        "heylib",
+        &Default::default(),
+    );
+    assert!(r.is_ok(), "queried heylib with a yanked version: {:?}", r);
+
+    // update skips the yanked version
+    let component_dir = clone_component_dir("helloworld", &state);
+    let r = update::update(&component_dir, env_name, &state.backend, vec!["heylib"]);
+    assert!(r.is_ok(), "updated heylib: {:?}", r);
+    assert_eq!(input_version(&component_dir, "heylib"), "1");
+
+    // but pinned versions can still be fetched
+    let mut manifest = lal::Manifest::read(&component_dir).expect("read helloworld manifest");
+    manifest.dependencies.insert("heylib".into(), 2);
+    manifest.write().expect("write helloworld manifest");
+    let r = fetch::fetch_input(&component_dir, env_name, &state.backend);
+    assert!(r.is_ok(), "fetched pinned yanked heylib=2: {:?}", r);
+    assert_eq!(input_version(&component_dir, "heylib"), "2");
+
+    // yanked versions are never allocated again
+    let r = lal::next_version(home, &state.backend, "heylib", &envs);
+    assert_eq!(r.ok(), Some(3), "allocated heylib=3");
+
+    // only published versions can be yanked
+    match lal::yank(home, &state.backend, envs.clone(), "heylib=4", None) {
+        Err(lal::CliError::MissingPublishedVersion(_, 4)) => {}
+        x => panic!("yanking an unpublished version should fail: {:?}", x),
+    }
+    match lal::yank(home, &state.backend, envs.clone(), "heylib", None) {
+        Err(lal::CliError::InvalidVersionSpec(_)) => {}
+        x => panic!("yanking without a version should fail: {:?}", x),
+    }
+
+    // nothing is yanked when an environment cannot be listed
+    let unlisted = state.backend.get_cache_dir().join("environments/xenial/heylib");
+    fs::create_dir_all(&unlisted).unwrap();
+    fs::set_permissions(&unlisted, fs::Permissions::from_mode(0o000)).unwrap();
+    // root can list the directory regardless
+    if fs::read_dir(&unlisted).is_ok() {
+        return;
+    }
+    let both = vec![env_name.to_string(), "xenial".to_string()];
+    let r = lal::yank(home, &state.backend, both, "heylib=1", None);
+    fs::set_permissions(&unlisted, fs::Permissions::from_mode(0o755)).unwrap();
+    match r {
+        Err(lal::CliError::Io(_)) => {}
+        x => panic!("yanking when xenial cannot be listed should fail: {:?}", x),
+    }
+    let (_, yanked) = state
+        .backend
+        .get_all_versions("heylib", env_name)
+        .expect("list heylib");
+    assert!(!yanked.contains(&1), "heylib=1 was not yanked in {}", env_name);
+}
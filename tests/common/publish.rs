@@ -7,5 +7,5 @@ pub fn publish_release(
 ) -> lal::LalResult<()> {
     let manifest = lal::Manifest::read(&component_dir)?;
 
-    lal::publish(Some(&home), &component_dir, &manifest.name, backend)
+    lal::publish(Some(&home), &component_dir, &manifest.name, false, backend)
 }